@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+
+# Builds the seed corpus of `fuzz_targets/olean.rs` (and `libs::olean`'s `test_corpus`): one small olean per version
+# in `libs::olean::DATA`, compiled by that official toolchain, plus their SHA256SUMS.
+
+from argparse import ArgumentDefaultsHelpFormatter, ArgumentParser
+from hashlib import sha256
+from pathlib import Path
+from requests import get
+from subprocess import run
+from tempfile import TemporaryDirectory
+
+# (version, git hash), as in `libs::olean::DATA`.
+VERSIONS = [
+    ('4.26.0', 'd8204c9fd894f91bbb2cdfec5912ec8196fd8562'),
+    ('4.27.0-rc1', '2fcce7258eeb6e324366bc25f9058293b04b7547'),
+    ('4.27.0', 'db93fe1608548721853390a10cd40580fe7d22ae'),
+    ('4.28.0-rc1', '3b0f2862196c6a8af9eb0025ee650252694013dd'),
+]
+
+SOURCE = '''\
+namespace Corpus
+
+structure Point where
+  x : Nat
+  y : Nat
+
+def Point.add (p q : Point) : Point := ⟨p.x + q.x, p.y + q.y⟩
+
+theorem Point.add_comm (p q : Point) : p.add q = q.add p := by
+  simp [Point.add, Nat.add_comm]
+
+theorem «fancy name» : ∀ n : Nat, n + 0 = n := fun _ => rfl
+
+end Corpus
+'''
+
+def parse_args():
+    parser = ArgumentParser(formatter_class=ArgumentDefaultsHelpFormatter)
+    parser.add_argument('--release-server', help='Where Lean releases are downloaded from', default='https://github.com/leanprover/lean4/releases/download/')
+    parser.add_argument('--output', help='Corpus directory', type=Path, default=Path(__file__).parent / 'corpus' / 'olean')
+    return parser.parse_args()
+
+def build(server, version, githash, tmp):
+    archive = tmp / f'lean-{version}-linux.tar.zst'
+    with get(f'{server}v{version}/{archive.name}', stream=True) as r:
+        r.raise_for_status()
+        with archive.open('wb') as f:
+            for chunk in r.iter_content(1 << 20):
+                f.write(chunk)
+    run(['tar', '--zstd', '-xf', archive, '-C', tmp], check=True)
+    lean = tmp / f'lean-{version}-linux' / 'bin' / 'lean'
+    assert run([lean, '--githash'], check=True, capture_output=True, text=True).stdout.strip() == githash
+
+    source = tmp / 'Corpus.lean'
+    source.write_text(SOURCE)
+    olean = tmp / f'{version}.olean'
+    run([lean, '-o', olean, source], check=True, cwd=tmp)
+    return olean.read_bytes()
+
+def main():
+    args = parse_args()
+    args.output.mkdir(parents=True, exist_ok=True)
+    sums = []
+    for version, githash in VERSIONS:
+        with TemporaryDirectory() as tmp:
+            data = build(args.release_server, version, githash, Path(tmp))
+        name = f'{version}.olean'
+        (args.output / name).write_bytes(data)
+        sums.append(f'{sha256(data).hexdigest()}  {name}\n')
+        print(name, len(data))
+    (args.output / 'SHA256SUMS').write_text(''.join(sums))
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,35 @@
+#![no_main]
+#![feature(
+    ascii_char,
+    const_index,
+    const_trait_impl,
+    pattern,
+    ptr_cast_array,
+    result_option_map_or_default,
+    unsafe_cell_access,
+)]
+#![allow(
+    confusable_idents,
+    mixed_script_confusables,
+    uncommon_codepoints,
+)]
+
+#[path = "../../src/libs"]
+mod libs {
+    pub mod olean;
+    pub mod util;
+    pub mod validate;
+}
+
+use libfuzzer_sys::fuzz_target;
+
+use libs::olean;
+
+// cargo +nightly fuzz run olean (seeds: fuzz/corpus/olean, built by fuzz/fetch_corpus.py)
+fuzz_target!(|data: &[u8]| {
+    olean::init();
+    if let Some(meta) = olean::parse_meta(data) {
+        let _ = olean::parse_consts(meta);
+        let _ = olean::parse_imports(meta);
+    }
+});
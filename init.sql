@@ -18,66 +18,108 @@ SET xmloption = content;
 SET client_min_messages = warning;
 SET row_security = off;
 
+ALTER TABLE ONLY lean4oj.user_suspensions DROP CONSTRAINT user_suspensions_uid_fkey;
+ALTER TABLE ONLY lean4oj.user_totp DROP CONSTRAINT user_totp_uid_fkey;
 ALTER TABLE ONLY lean4oj.user_preference DROP CONSTRAINT user_preference_uid_fkey;
 ALTER TABLE ONLY lean4oj.user_information DROP CONSTRAINT user_information_uid_fkey;
 ALTER TABLE ONLY lean4oj.user_groups DROP CONSTRAINT user_groups_uid_fkey;
 ALTER TABLE ONLY lean4oj.user_groups DROP CONSTRAINT user_groups_gid_fkey;
+ALTER TABLE ONLY lean4oj.upload_tokens DROP CONSTRAINT upload_tokens_uid_fkey;
 ALTER TABLE ONLY lean4oj.submissions DROP CONSTRAINT submissions_submitter_fkey;
 ALTER TABLE ONLY lean4oj.submissions DROP CONSTRAINT submissions_pid_fkey;
+ALTER TABLE ONLY lean4oj.storage_usage DROP CONSTRAINT storage_usage_uid_fkey;
+ALTER TABLE ONLY lean4oj.sessions DROP CONSTRAINT sessions_uid_fkey;
 ALTER TABLE ONLY lean4oj.problems DROP CONSTRAINT problems_owner_fkey;
 ALTER TABLE ONLY lean4oj.problem_tags DROP CONSTRAINT problem_tags_tid_fkey;
 ALTER TABLE ONLY lean4oj.problem_tags DROP CONSTRAINT problem_tags_pid_fkey;
+ALTER TABLE ONLY lean4oj.oauth_links DROP CONSTRAINT oauth_links_uid_fkey;
+ALTER TABLE ONLY lean4oj.notifications DROP CONSTRAINT notifications_uid_fkey;
 ALTER TABLE ONLY lean4oj.discussions DROP CONSTRAINT discussions_publisher_fkey;
 ALTER TABLE ONLY lean4oj.discussions DROP CONSTRAINT discussions_pid_fkey;
 ALTER TABLE ONLY lean4oj.discussion_replies DROP CONSTRAINT discussion_replies_publisher_fkey;
 ALTER TABLE ONLY lean4oj.discussion_replies DROP CONSTRAINT discussion_replies_did_fkey;
 ALTER TABLE ONLY lean4oj.discussion_reactions DROP CONSTRAINT discussion_reactions_uid_fkey;
+ALTER TABLE ONLY lean4oj.access_tokens DROP CONSTRAINT access_tokens_uid_fkey;
 DROP INDEX lean4oj.users_ac_idx;
 DROP INDEX lean4oj.user_groups_gid_uid_idx;
 DROP INDEX lean4oj.submissions_submitter_submit_time_idx;
 DROP INDEX lean4oj.submissions_submitter_status_idx;
 DROP INDEX lean4oj.submissions_submitter_sid_idx;
+DROP INDEX lean4oj.submissions_submitter_pid_status_idx;
+DROP INDEX lean4oj.submissions_status_submit_time_idx;
 DROP INDEX lean4oj.submissions_status_sid_idx;
 DROP INDEX lean4oj.submissions_pid_status_sid_idx;
 DROP INDEX lean4oj.submissions_pid_sid_idx;
+DROP INDEX lean4oj.sessions_uid_idx;
 DROP INDEX lean4oj.problems_owner_pid_idx;
+DROP INDEX lean4oj.notifications_uid_idx;
+DROP INDEX lean4oj.notifications_uid_id_idx;
+DROP INDEX lean4oj.notifications_time_idx;
 DROP INDEX lean4oj.discussion_replies_did_id_idx;
 DROP INDEX lean4oj.discussion_reactions_eid_emoji_idx;
+DROP INDEX lean4oj.audit_logs_uid_id_idx;
+DROP INDEX lean4oj.audit_logs_target_uid_id_idx;
 ALTER TABLE ONLY lean4oj.users DROP CONSTRAINT users_pkey;
 ALTER TABLE ONLY lean4oj.users DROP CONSTRAINT users_email_key;
+ALTER TABLE ONLY lean4oj.user_totp DROP CONSTRAINT user_totp_pkey;
+ALTER TABLE ONLY lean4oj.user_suspensions DROP CONSTRAINT user_suspensions_pkey;
 ALTER TABLE ONLY lean4oj.user_preference DROP CONSTRAINT user_preference_pkey;
 ALTER TABLE ONLY lean4oj.user_information DROP CONSTRAINT user_information_pkey;
 ALTER TABLE ONLY lean4oj.user_groups DROP CONSTRAINT user_groups_pkey;
+ALTER TABLE ONLY lean4oj.upload_tokens DROP CONSTRAINT upload_tokens_pkey;
 ALTER TABLE ONLY lean4oj.tags DROP CONSTRAINT tags_pkey;
 ALTER TABLE ONLY lean4oj.submissions DROP CONSTRAINT submissions_pkey;
+ALTER TABLE ONLY lean4oj.storage_usage DROP CONSTRAINT storage_usage_pkey;
+ALTER TABLE ONLY lean4oj.sessions DROP CONSTRAINT sessions_pkey;
 ALTER TABLE ONLY lean4oj.problems DROP CONSTRAINT problems_pkey;
 ALTER TABLE ONLY lean4oj.problem_tags DROP CONSTRAINT problem_tags_pkey;
+ALTER TABLE ONLY lean4oj.oauth_pending DROP CONSTRAINT oauth_pending_pkey;
+ALTER TABLE ONLY lean4oj.oauth_links DROP CONSTRAINT oauth_links_uid_provider_key;
+ALTER TABLE ONLY lean4oj.oauth_links DROP CONSTRAINT oauth_links_pkey;
+ALTER TABLE ONLY lean4oj.notifications DROP CONSTRAINT notifications_pkey;
 ALTER TABLE ONLY lean4oj.groups DROP CONSTRAINT groups_pkey;
 ALTER TABLE ONLY lean4oj.discussions DROP CONSTRAINT discussions_pkey;
 ALTER TABLE ONLY lean4oj.discussion_replies DROP CONSTRAINT discussion_replies_pkey;
 ALTER TABLE ONLY lean4oj.discussion_reactions DROP CONSTRAINT discussion_reactions_pkey;
+ALTER TABLE ONLY lean4oj.audit_logs DROP CONSTRAINT audit_logs_pkey;
+ALTER TABLE ONLY lean4oj.access_tokens DROP CONSTRAINT access_tokens_token_hash_key;
+ALTER TABLE ONLY lean4oj.access_tokens DROP CONSTRAINT access_tokens_pkey;
 ALTER TABLE lean4oj.tags ALTER COLUMN id DROP DEFAULT;
 ALTER TABLE lean4oj.submissions ALTER COLUMN sid DROP DEFAULT;
 ALTER TABLE lean4oj.problems ALTER COLUMN pid DROP DEFAULT;
+ALTER TABLE lean4oj.notifications ALTER COLUMN id DROP DEFAULT;
 ALTER TABLE lean4oj.discussions ALTER COLUMN id DROP DEFAULT;
 ALTER TABLE lean4oj.discussion_replies ALTER COLUMN id DROP DEFAULT;
+ALTER TABLE lean4oj.audit_logs ALTER COLUMN id DROP DEFAULT;
 DROP TABLE lean4oj.users;
+DROP TABLE lean4oj.user_totp;
+DROP TABLE lean4oj.user_suspensions;
 DROP TABLE lean4oj.user_preference;
 DROP TABLE lean4oj.user_information;
 DROP TABLE lean4oj.user_groups;
+DROP TABLE lean4oj.upload_tokens;
 DROP SEQUENCE lean4oj.tags_id_seq;
 DROP TABLE lean4oj.tags;
 DROP SEQUENCE lean4oj.submissions_sid_seq;
 DROP TABLE lean4oj.submissions;
+DROP TABLE lean4oj.storage_usage;
+DROP TABLE lean4oj.sessions;
 DROP SEQUENCE lean4oj.problems_pid_seq;
 DROP TABLE lean4oj.problems;
 DROP TABLE lean4oj.problem_tags;
+DROP TABLE lean4oj.oauth_pending;
+DROP TABLE lean4oj.oauth_links;
+DROP SEQUENCE lean4oj.notifications_id_seq;
+DROP TABLE lean4oj.notifications;
 DROP TABLE lean4oj.groups;
 DROP SEQUENCE lean4oj.discussions_id_seq;
 DROP TABLE lean4oj.discussions;
 DROP SEQUENCE lean4oj.discussion_replies_id_seq;
 DROP TABLE lean4oj.discussion_replies;
 DROP TABLE lean4oj.discussion_reactions;
+DROP SEQUENCE lean4oj.audit_logs_id_seq;
+DROP TABLE lean4oj.audit_logs;
+DROP TABLE lean4oj.access_tokens;
 DROP SCHEMA lean4oj;
 --
 -- Name: lean4oj; Type: SCHEMA; Schema: -; Owner: -
@@ -90,6 +132,56 @@ SET default_tablespace = '';
 
 SET default_table_access_method = heap;
 
+--
+-- Name: access_tokens; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.access_tokens (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    name character varying(24) NOT NULL,
+    token_hash bytea NOT NULL,
+    scopes text[] NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    last_used timestamp without time zone,
+    expire_time timestamp without time zone
+);
+
+
+--
+-- Name: audit_logs; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.audit_logs (
+    id bigint NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    action text NOT NULL,
+    target_uid character varying(24) COLLATE public.case_insensitive,
+    object_type text,
+    object_id text,
+    details jsonb NOT NULL,
+    "time" timestamp without time zone NOT NULL
+);
+
+
+--
+-- Name: audit_logs_id_seq; Type: SEQUENCE; Schema: lean4oj; Owner: -
+--
+
+CREATE SEQUENCE lean4oj.audit_logs_id_seq
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+
+--
+-- Name: audit_logs_id_seq; Type: SEQUENCE OWNED BY; Schema: lean4oj; Owner: -
+--
+
+ALTER SEQUENCE lean4oj.audit_logs_id_seq OWNED BY lean4oj.audit_logs.id;
+
+
 --
 -- Name: discussion_reactions; Type: TABLE; Schema: lean4oj; Owner: -
 --
@@ -107,7 +199,7 @@ CREATE TABLE lean4oj.discussion_reactions (
 
 CREATE TABLE lean4oj.discussion_replies (
     id integer NOT NULL,
-    content text NOT NULL,
+    rcontent jsonb NOT NULL,
     publish timestamp without time zone NOT NULL,
     edit timestamp without time zone NOT NULL,
     did integer NOT NULL,
@@ -141,8 +233,7 @@ ALTER SEQUENCE lean4oj.discussion_replies_id_seq OWNED BY lean4oj.discussion_rep
 
 CREATE TABLE lean4oj.discussions (
     id integer NOT NULL,
-    title character varying(256) NOT NULL,
-    content text NOT NULL,
+    dcontent jsonb NOT NULL,
     publish timestamp without time zone NOT NULL,
     edit timestamp without time zone NOT NULL,
     update timestamp without time zone CONSTRAINT discussions_reply_latest_not_null NOT NULL,
@@ -178,7 +269,70 @@ ALTER SEQUENCE lean4oj.discussions_id_seq OWNED BY lean4oj.discussions.id;
 
 CREATE TABLE lean4oj.groups (
     gid character varying(48) NOT NULL COLLATE public.case_insensitive,
-    member_count integer NOT NULL
+    member_count integer NOT NULL,
+    storage_quota bigint,
+    require_totp boolean DEFAULT false NOT NULL
+);
+
+
+--
+-- Name: notifications; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.notifications (
+    id bigint NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    kind text NOT NULL,
+    details jsonb NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    is_read boolean DEFAULT false NOT NULL
+);
+
+
+--
+-- Name: notifications_id_seq; Type: SEQUENCE; Schema: lean4oj; Owner: -
+--
+
+CREATE SEQUENCE lean4oj.notifications_id_seq
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+
+--
+-- Name: notifications_id_seq; Type: SEQUENCE OWNED BY; Schema: lean4oj; Owner: -
+--
+
+ALTER SEQUENCE lean4oj.notifications_id_seq OWNED BY lean4oj.notifications.id;
+
+
+--
+-- Name: oauth_links; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.oauth_links (
+    provider character varying(32) NOT NULL,
+    subject character varying(256) NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    email character varying(256),
+    create_time timestamp without time zone NOT NULL,
+    last_login timestamp without time zone
+);
+
+
+--
+-- Name: oauth_pending; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.oauth_pending (
+    state character varying(43) NOT NULL,
+    provider character varying(32) NOT NULL,
+    verifier character varying(43) NOT NULL,
+    nonce character varying(43) NOT NULL,
+    link character varying(24) COLLATE public.case_insensitive,
+    create_time timestamp without time zone NOT NULL
 );
 
 
@@ -229,6 +383,33 @@ CREATE SEQUENCE lean4oj.problems_pid_seq
 ALTER SEQUENCE lean4oj.problems_pid_seq OWNED BY lean4oj.problems.pid;
 
 
+--
+-- Name: sessions; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.sessions (
+    id bytea NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    data jsonb NOT NULL,
+    expiry timestamp without time zone NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    ip text,
+    user_agent text
+);
+
+
+--
+-- Name: storage_usage; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.storage_usage (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    module text NOT NULL,
+    size bigint NOT NULL,
+    files integer NOT NULL
+);
+
+
 --
 -- Name: submissions; Type: TABLE; Schema: lean4oj; Owner: -
 --
@@ -245,7 +426,8 @@ CREATE TABLE lean4oj.submissions (
     message text DEFAULT ''::text NOT NULL,
     answer_size bigint NOT NULL,
     answer_hash bytea NOT NULL,
-    answer_obj text DEFAULT ''::text NOT NULL
+    answer_obj text DEFAULT ''::text NOT NULL,
+    sources text[]
 );
 
 
@@ -300,6 +482,20 @@ CREATE SEQUENCE lean4oj.tags_id_seq
 ALTER SEQUENCE lean4oj.tags_id_seq OWNED BY lean4oj.tags.id;
 
 
+--
+-- Name: upload_tokens; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.upload_tokens (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    name character varying(24) NOT NULL,
+    verifier bytea NOT NULL,
+    read_only boolean DEFAULT false NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    last_used timestamp without time zone
+);
+
+
 --
 -- Name: user_groups; Type: TABLE; Schema: lean4oj; Owner: -
 --
@@ -336,6 +532,32 @@ CREATE TABLE lean4oj.user_preference (
 );
 
 
+--
+-- Name: user_suspensions; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.user_suspensions (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    reason text NOT NULL,
+    until timestamp without time zone,
+    suspended_by character varying(24) NOT NULL COLLATE public.case_insensitive,
+    create_time timestamp without time zone NOT NULL
+);
+
+
+--
+-- Name: user_totp; Type: TABLE; Schema: lean4oj; Owner: -
+--
+
+CREATE TABLE lean4oj.user_totp (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    secret bytea NOT NULL,
+    enabled boolean DEFAULT false NOT NULL,
+    last_step bigint DEFAULT 0 NOT NULL,
+    recovery_codes bytea[] DEFAULT '{}'::bytea[] NOT NULL
+);
+
+
 --
 -- Name: users; Type: TABLE; Schema: lean4oj; Owner: -
 --
@@ -344,15 +566,26 @@ CREATE TABLE lean4oj.users (
     uid character varying(24) NOT NULL COLLATE public.case_insensitive,
     username character varying(24) NOT NULL,
     email character varying(256) NOT NULL COLLATE public.case_insensitive,
-    password character varying(43) NOT NULL,
+    password character varying(128) NOT NULL,
     register_time timestamp without time zone NOT NULL,
     ac integer DEFAULT 0 NOT NULL,
     nickname character varying(24) DEFAULT ''::character varying NOT NULL,
     bio character varying(160) DEFAULT ''::character varying NOT NULL,
-    avatar_info character varying(272) NOT NULL
+    avatar_info character varying(272) NOT NULL,
+    storage_quota bigint,
+    email_verified boolean DEFAULT true NOT NULL,
+    email_confirmed boolean DEFAULT false NOT NULL,
+    rsync_verifier bytea
 );
 
 
+--
+-- Name: audit_logs id; Type: DEFAULT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.audit_logs ALTER COLUMN id SET DEFAULT nextval('lean4oj.audit_logs_id_seq'::regclass);
+
+
 --
 -- Name: discussion_replies id; Type: DEFAULT; Schema: lean4oj; Owner: -
 --
@@ -367,6 +600,13 @@ ALTER TABLE ONLY lean4oj.discussion_replies ALTER COLUMN id SET DEFAULT nextval(
 ALTER TABLE ONLY lean4oj.discussions ALTER COLUMN id SET DEFAULT nextval('lean4oj.discussions_id_seq'::regclass);
 
 
+--
+-- Name: notifications id; Type: DEFAULT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.notifications ALTER COLUMN id SET DEFAULT nextval('lean4oj.notifications_id_seq'::regclass);
+
+
 --
 -- Name: problems pid; Type: DEFAULT; Schema: lean4oj; Owner: -
 --
@@ -395,16 +635,16 @@ ALTER TABLE ONLY lean4oj.tags ALTER COLUMN id SET DEFAULT nextval('lean4oj.tags_
 -- Data for Name: user_groups; Type: TABLE DATA; Schema: lean4oj; Owner: -
 --
 
-COPY lean4oj.groups (gid, member_count) FROM stdin;
-Lean4OJ.Admin	0
-Lean4OJ.EditHomepage	0
-Lean4OJ.Judger	0
-Lean4OJ.ManageContest	0
-Lean4OJ.ManageDiscussion	0
-Lean4OJ.ManageProblem	0
-Lean4OJ.ManageUser	0
-Lean4OJ.ManageUserGroup	0
-Lean4OJ.TooManyOLeans	0
+COPY lean4oj.groups (gid, member_count, require_totp) FROM stdin;
+Lean4OJ.Admin	0	t
+Lean4OJ.EditHomepage	0	f
+Lean4OJ.Judger	0	f
+Lean4OJ.ManageContest	0	f
+Lean4OJ.ManageDiscussion	0	f
+Lean4OJ.ManageProblem	0	t
+Lean4OJ.ManageUser	0	f
+Lean4OJ.ManageUserGroup	0	f
+Lean4OJ.TooManyOLeans	0	f
 \.
 
 --
@@ -432,6 +672,30 @@ build		build		1970-01-01 00:00:00	0
 submission		submission		1970-01-01 00:00:00	0			
 \.
 
+--
+-- Name: access_tokens access_tokens_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.access_tokens
+    ADD CONSTRAINT access_tokens_pkey PRIMARY KEY (uid, name);
+
+
+--
+-- Name: access_tokens access_tokens_token_hash_key; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.access_tokens
+    ADD CONSTRAINT access_tokens_token_hash_key UNIQUE (token_hash);
+
+
+--
+-- Name: audit_logs audit_logs_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.audit_logs
+    ADD CONSTRAINT audit_logs_pkey PRIMARY KEY (id);
+
+
 --
 -- Name: discussion_reactions discussion_reactions_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -464,6 +728,38 @@ ALTER TABLE ONLY lean4oj.groups
     ADD CONSTRAINT groups_pkey PRIMARY KEY (gid);
 
 
+--
+-- Name: notifications notifications_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.notifications
+    ADD CONSTRAINT notifications_pkey PRIMARY KEY (id);
+
+
+--
+-- Name: oauth_links oauth_links_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.oauth_links
+    ADD CONSTRAINT oauth_links_pkey PRIMARY KEY (provider, subject);
+
+
+--
+-- Name: oauth_links oauth_links_uid_provider_key; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.oauth_links
+    ADD CONSTRAINT oauth_links_uid_provider_key UNIQUE (uid, provider);
+
+
+--
+-- Name: oauth_pending oauth_pending_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.oauth_pending
+    ADD CONSTRAINT oauth_pending_pkey PRIMARY KEY (state);
+
+
 --
 -- Name: problem_tags problem_tags_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -480,6 +776,22 @@ ALTER TABLE ONLY lean4oj.problems
     ADD CONSTRAINT problems_pkey PRIMARY KEY (pid);
 
 
+--
+-- Name: sessions sessions_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.sessions
+    ADD CONSTRAINT sessions_pkey PRIMARY KEY (id);
+
+
+--
+-- Name: storage_usage storage_usage_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.storage_usage
+    ADD CONSTRAINT storage_usage_pkey PRIMARY KEY (uid, module);
+
+
 --
 -- Name: submissions submissions_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -496,6 +808,14 @@ ALTER TABLE ONLY lean4oj.tags
     ADD CONSTRAINT tags_pkey PRIMARY KEY (id);
 
 
+--
+-- Name: upload_tokens upload_tokens_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.upload_tokens
+    ADD CONSTRAINT upload_tokens_pkey PRIMARY KEY (uid, name);
+
+
 --
 -- Name: user_groups user_groups_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -520,6 +840,22 @@ ALTER TABLE ONLY lean4oj.user_preference
     ADD CONSTRAINT user_preference_pkey PRIMARY KEY (uid);
 
 
+--
+-- Name: user_suspensions user_suspensions_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.user_suspensions
+    ADD CONSTRAINT user_suspensions_pkey PRIMARY KEY (uid);
+
+
+--
+-- Name: user_totp user_totp_pkey; Type: CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.user_totp
+    ADD CONSTRAINT user_totp_pkey PRIMARY KEY (uid);
+
+
 --
 -- Name: users users_email_key; Type: CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -536,6 +872,20 @@ ALTER TABLE ONLY lean4oj.users
     ADD CONSTRAINT users_pkey PRIMARY KEY (uid);
 
 
+--
+-- Name: audit_logs_target_uid_id_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX audit_logs_target_uid_id_idx ON lean4oj.audit_logs USING btree (target_uid, id);
+
+
+--
+-- Name: audit_logs_uid_id_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX audit_logs_uid_id_idx ON lean4oj.audit_logs USING btree (uid, id);
+
+
 --
 -- Name: discussion_reactions_eid_emoji_idx; Type: INDEX; Schema: lean4oj; Owner: -
 --
@@ -550,6 +900,27 @@ CREATE INDEX discussion_reactions_eid_emoji_idx ON lean4oj.discussion_reactions
 CREATE INDEX discussion_replies_did_id_idx ON lean4oj.discussion_replies USING btree (did, id);
 
 
+--
+-- Name: notifications_time_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX notifications_time_idx ON lean4oj.notifications USING btree ("time");
+
+
+--
+-- Name: notifications_uid_id_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX notifications_uid_id_idx ON lean4oj.notifications USING btree (uid, id);
+
+
+--
+-- Name: notifications_uid_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX notifications_uid_idx ON lean4oj.notifications USING btree (uid) WHERE (NOT is_read);
+
+
 --
 -- Name: problems_owner_pid_idx; Type: INDEX; Schema: lean4oj; Owner: -
 --
@@ -557,6 +928,13 @@ CREATE INDEX discussion_replies_did_id_idx ON lean4oj.discussion_replies USING b
 CREATE INDEX problems_owner_pid_idx ON lean4oj.problems USING btree (owner, pid);
 
 
+--
+-- Name: sessions_uid_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX sessions_uid_idx ON lean4oj.sessions USING btree (uid);
+
+
 --
 -- Name: submissions_pid_sid_idx; Type: INDEX; Schema: lean4oj; Owner: -
 --
@@ -578,6 +956,20 @@ CREATE INDEX submissions_pid_status_sid_idx ON lean4oj.submissions USING btree (
 CREATE INDEX submissions_status_sid_idx ON lean4oj.submissions USING btree (status, sid);
 
 
+--
+-- Name: submissions_status_submit_time_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX submissions_status_submit_time_idx ON lean4oj.submissions USING btree (status, submit_time) INCLUDE (submitter, pid);
+
+
+--
+-- Name: submissions_submitter_pid_status_idx; Type: INDEX; Schema: lean4oj; Owner: -
+--
+
+CREATE INDEX submissions_submitter_pid_status_idx ON lean4oj.submissions USING btree (submitter, pid, status) INCLUDE (lean_toolchain);
+
+
 --
 -- Name: submissions_submitter_sid_idx; Type: INDEX; Schema: lean4oj; Owner: -
 --
@@ -613,6 +1005,14 @@ CREATE INDEX user_groups_gid_uid_idx ON lean4oj.user_groups USING btree (gid, ui
 CREATE INDEX users_ac_idx ON lean4oj.users USING btree (ac);
 
 
+--
+-- Name: access_tokens access_tokens_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.access_tokens
+    ADD CONSTRAINT access_tokens_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
 --
 -- Name: discussion_reactions discussion_reactions_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -653,6 +1053,22 @@ ALTER TABLE ONLY lean4oj.discussions
     ADD CONSTRAINT discussions_publisher_fkey FOREIGN KEY (publisher) REFERENCES lean4oj.users(uid) MATCH FULL;
 
 
+--
+-- Name: notifications notifications_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.notifications
+    ADD CONSTRAINT notifications_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
+--
+-- Name: oauth_links oauth_links_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.oauth_links
+    ADD CONSTRAINT oauth_links_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
 --
 -- Name: problem_tags problem_tags_pid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -677,6 +1093,22 @@ ALTER TABLE ONLY lean4oj.problems
     ADD CONSTRAINT problems_owner_fkey FOREIGN KEY (owner) REFERENCES lean4oj.users(uid) MATCH FULL;
 
 
+--
+-- Name: sessions sessions_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.sessions
+    ADD CONSTRAINT sessions_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
+--
+-- Name: storage_usage storage_usage_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.storage_usage
+    ADD CONSTRAINT storage_usage_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
 --
 -- Name: submissions submissions_pid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -693,6 +1125,14 @@ ALTER TABLE ONLY lean4oj.submissions
     ADD CONSTRAINT submissions_submitter_fkey FOREIGN KEY (submitter) REFERENCES lean4oj.users(uid) MATCH FULL;
 
 
+--
+-- Name: upload_tokens upload_tokens_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.upload_tokens
+    ADD CONSTRAINT upload_tokens_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
 --
 -- Name: user_groups user_groups_gid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
 --
@@ -725,6 +1165,22 @@ ALTER TABLE ONLY lean4oj.user_preference
     ADD CONSTRAINT user_preference_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
 
 
+--
+-- Name: user_suspensions user_suspensions_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.user_suspensions
+    ADD CONSTRAINT user_suspensions_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
+--
+-- Name: user_totp user_totp_uid_fkey; Type: FK CONSTRAINT; Schema: lean4oj; Owner: -
+--
+
+ALTER TABLE ONLY lean4oj.user_totp
+    ADD CONSTRAINT user_totp_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+
 --
 -- PostgreSQL database dump complete
 --
@@ -0,0 +1,25 @@
+-- Personal access tokens (`l4oj_...`) authenticate API requests on behalf of a user, limited to the scopes they were
+-- created with. Only the SHA-256 of a token is stored, unique so that a request finds its token by it.
+
+BEGIN;
+
+CREATE TABLE lean4oj.access_tokens (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    name character varying(24) NOT NULL,
+    token_hash bytea NOT NULL,
+    scopes text[] NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    last_used timestamp without time zone,
+    expire_time timestamp without time zone
+);
+
+ALTER TABLE ONLY lean4oj.access_tokens
+    ADD CONSTRAINT access_tokens_pkey PRIMARY KEY (uid, name);
+
+ALTER TABLE ONLY lean4oj.access_tokens
+    ADD CONSTRAINT access_tokens_token_hash_key UNIQUE (token_hash);
+
+ALTER TABLE ONLY lean4oj.access_tokens
+    ADD CONSTRAINT access_tokens_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+COMMIT;
@@ -0,0 +1,35 @@
+-- Security-relevant actions (logins, account and group changes, rejudges, suspensions, ...) are recorded in an
+-- append-only audit log, listed by actor or by target user.
+
+BEGIN;
+
+CREATE TABLE lean4oj.audit_logs (
+    id bigint NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    action text NOT NULL,
+    target_uid character varying(24) COLLATE public.case_insensitive,
+    object_type text,
+    object_id text,
+    details jsonb NOT NULL,
+    "time" timestamp without time zone NOT NULL
+);
+
+CREATE SEQUENCE lean4oj.audit_logs_id_seq
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER SEQUENCE lean4oj.audit_logs_id_seq OWNED BY lean4oj.audit_logs.id;
+
+ALTER TABLE ONLY lean4oj.audit_logs ALTER COLUMN id SET DEFAULT nextval('lean4oj.audit_logs_id_seq'::regclass);
+
+ALTER TABLE ONLY lean4oj.audit_logs
+    ADD CONSTRAINT audit_logs_pkey PRIMARY KEY (id);
+
+CREATE INDEX audit_logs_target_uid_id_idx ON lean4oj.audit_logs USING btree (target_uid, id);
+
+CREATE INDEX audit_logs_uid_id_idx ON lean4oj.audit_logs USING btree (uid, id);
+
+COMMIT;
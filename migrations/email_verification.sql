@@ -0,0 +1,12 @@
+-- Accounts have to verify their email before they can submit or post discussions. Accounts registered from now on
+-- start unverified (`register` inserts `false`); existing accounts are taken as verified, as they were never asked.
+--
+-- `email_confirmed` tells the addresses actually confirmed by a mailed link from those taken as verified, so that
+-- only the former are trusted for linking OAuth accounts by email.
+
+BEGIN;
+
+ALTER TABLE lean4oj.users ADD COLUMN email_verified boolean DEFAULT true NOT NULL;
+ALTER TABLE lean4oj.users ADD COLUMN email_confirmed boolean DEFAULT false NOT NULL;
+
+COMMIT;
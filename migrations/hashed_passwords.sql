@@ -0,0 +1,14 @@
+-- Passwords are stored as scrypt hashes (`$scrypt$ln=..,r=..,p=..$<salt>$<key>`, see `libs::password`), which do not
+-- fit the 43 characters of the client-side value that used to be stored as is. Such rows are rehashed on their next
+-- login.
+--
+-- The rsync daemon cannot check challenges against a hash, so it gets `rsync_verifier` (the SHA-256 state after the
+-- password and the fixed start of every challenge) instead. SQL cannot compute that, so every row gets its own on its
+-- next login (or password change); until then only upload tokens work over rsync.
+
+BEGIN;
+
+ALTER TABLE lean4oj.users ALTER COLUMN password TYPE character varying(128);
+ALTER TABLE lean4oj.users ADD COLUMN rsync_verifier bytea;
+
+COMMIT;
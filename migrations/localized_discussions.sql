@@ -0,0 +1,50 @@
+-- Discussions and replies keep each translation in a jsonb `LocaleDict` (`dcontent`, `rcontent`) instead of a JSON
+-- `LocaleDict` of strings stuffed into the title and content behind U+EA97.
+--
+-- Untagged text is filed under `zh_CN`, the locale `LocaleDict::apply` falls back to. A discussion whose title and
+-- content were translated to different locales gets every locale of either, the missing half falling back the same way.
+-- Malformed magic text is kept as is, as it used to be shown.
+
+BEGIN;
+
+CREATE FUNCTION pg_temp.l4_dict(s text) RETURNS jsonb
+    LANGUAGE plpgsql IMMUTABLE
+    AS $$
+DECLARE
+    d jsonb;
+BEGIN
+    IF left(s, 1) = U&'\EA97' THEN
+        BEGIN
+            SELECT jsonb_object_agg(key, value) INTO d FROM jsonb_each(substr(s, 2)::jsonb) WHERE jsonb_typeof(value) = 'string';
+        EXCEPTION WHEN others THEN
+            d := NULL;
+        END;
+    END IF;
+    RETURN coalesce(d, jsonb_build_object('zh_CN', s));
+END
+$$;
+
+CREATE FUNCTION pg_temp.l4_apply(d jsonb, locale text) RETURNS text
+    LANGUAGE sql IMMUTABLE
+    AS $$
+    SELECT coalesce(d ->> locale, d ->> 'zh_CN', (SELECT value FROM jsonb_each_text(d) ORDER BY key COLLATE "C" LIMIT 1));
+$$;
+
+ALTER TABLE lean4oj.discussions ADD COLUMN dcontent jsonb;
+
+UPDATE lean4oj.discussions SET dcontent = (
+    SELECT jsonb_object_agg(l, jsonb_build_object('title', pg_temp.l4_apply(t, l), 'content', pg_temp.l4_apply(c, l)))
+    FROM (SELECT pg_temp.l4_dict(title) t, pg_temp.l4_dict(content) c) x,
+    LATERAL (SELECT jsonb_object_keys(t) UNION SELECT jsonb_object_keys(c)) k(l)
+);
+
+ALTER TABLE lean4oj.discussions ALTER COLUMN dcontent SET NOT NULL;
+ALTER TABLE lean4oj.discussions DROP COLUMN title;
+ALTER TABLE lean4oj.discussions DROP COLUMN content;
+
+ALTER TABLE lean4oj.discussion_replies ADD COLUMN rcontent jsonb;
+UPDATE lean4oj.discussion_replies SET rcontent = pg_temp.l4_dict(content);
+ALTER TABLE lean4oj.discussion_replies ALTER COLUMN rcontent SET NOT NULL;
+ALTER TABLE lean4oj.discussion_replies DROP COLUMN content;
+
+COMMIT;
@@ -0,0 +1,39 @@
+-- Users are notified of replies, judged submissions and the like. Each notification is kept as a row, listed newest
+-- first and counted while unread, and deleted after 90 days; new ones are also announced on the `lean4oj_notification`
+-- channel so that every instance can relay them to its subscribers.
+
+BEGIN;
+
+CREATE TABLE lean4oj.notifications (
+    id bigint NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    kind text NOT NULL,
+    details jsonb NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    is_read boolean DEFAULT false NOT NULL
+);
+
+CREATE SEQUENCE lean4oj.notifications_id_seq
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER SEQUENCE lean4oj.notifications_id_seq OWNED BY lean4oj.notifications.id;
+
+ALTER TABLE ONLY lean4oj.notifications ALTER COLUMN id SET DEFAULT nextval('lean4oj.notifications_id_seq'::regclass);
+
+ALTER TABLE ONLY lean4oj.notifications
+    ADD CONSTRAINT notifications_pkey PRIMARY KEY (id);
+
+CREATE INDEX notifications_time_idx ON lean4oj.notifications USING btree ("time");
+
+CREATE INDEX notifications_uid_id_idx ON lean4oj.notifications USING btree (uid, id);
+
+CREATE INDEX notifications_uid_idx ON lean4oj.notifications USING btree (uid) WHERE (NOT is_read);
+
+ALTER TABLE ONLY lean4oj.notifications
+    ADD CONSTRAINT notifications_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+COMMIT;
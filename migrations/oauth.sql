@@ -0,0 +1,37 @@
+-- Accounts can be linked to OAuth2/OpenID Connect providers and logged in through them. `oauth_links` maps a provider's
+-- subject to an account, at most one per provider; `oauth_pending` holds the logins sent to a provider until it
+-- redirects back, possibly to another instance, and is pruned of those older than ten minutes.
+
+BEGIN;
+
+CREATE TABLE lean4oj.oauth_links (
+    provider character varying(32) NOT NULL,
+    subject character varying(256) NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    email character varying(256),
+    create_time timestamp without time zone NOT NULL,
+    last_login timestamp without time zone
+);
+
+CREATE TABLE lean4oj.oauth_pending (
+    state character varying(43) NOT NULL,
+    provider character varying(32) NOT NULL,
+    verifier character varying(43) NOT NULL,
+    nonce character varying(43) NOT NULL,
+    link character varying(24) COLLATE public.case_insensitive,
+    create_time timestamp without time zone NOT NULL
+);
+
+ALTER TABLE ONLY lean4oj.oauth_links
+    ADD CONSTRAINT oauth_links_pkey PRIMARY KEY (provider, subject);
+
+ALTER TABLE ONLY lean4oj.oauth_links
+    ADD CONSTRAINT oauth_links_uid_provider_key UNIQUE (uid, provider);
+
+ALTER TABLE ONLY lean4oj.oauth_pending
+    ADD CONSTRAINT oauth_pending_pkey PRIMARY KEY (state);
+
+ALTER TABLE ONLY lean4oj.oauth_links
+    ADD CONSTRAINT oauth_links_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+COMMIT;
@@ -0,0 +1,6 @@
+-- The ranklist counts accepted submissions in a time window, which this index answers without touching the table.
+--
+-- Built concurrently so that judging goes on meanwhile, hence outside a transaction. Should it fail halfway, drop the
+-- invalid index and run this again.
+
+CREATE INDEX CONCURRENTLY IF NOT EXISTS submissions_status_submit_time_idx ON lean4oj.submissions USING btree (status, submit_time) INCLUDE (submitter, pid);
@@ -0,0 +1,25 @@
+-- Sessions are kept in PostgreSQL, with an in-memory cache in front, instead of only in memory, so that they survive
+-- restarts and are shared between instances. Existing sessions were lost on the restart anyway, so there is nothing
+-- to carry over. `ip` and `user_agent` are what the session list shows of the client that logged in.
+
+BEGIN;
+
+CREATE TABLE lean4oj.sessions (
+    id bytea NOT NULL,
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    data jsonb NOT NULL,
+    expiry timestamp without time zone NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    ip text,
+    user_agent text
+);
+
+ALTER TABLE ONLY lean4oj.sessions
+    ADD CONSTRAINT sessions_pkey PRIMARY KEY (id);
+
+CREATE INDEX sessions_uid_idx ON lean4oj.sessions USING btree (uid);
+
+ALTER TABLE ONLY lean4oj.sessions
+    ADD CONSTRAINT sessions_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+COMMIT;
@@ -0,0 +1,25 @@
+-- Storage usage is recorded per top-level module of each user's tree. A quota can be set per user or per group; a null
+-- quota means none is set, leaving the default of `TOTAL_FILE_LIMIT` (see `quota::limit`).
+--
+-- `storage_usage` starts empty: on startup, `quota::backfill` walks the tree of every user without rows, so existing
+-- uploads count against the quota as soon as the new version runs.
+
+BEGIN;
+
+CREATE TABLE lean4oj.storage_usage (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    module text NOT NULL,
+    size bigint NOT NULL,
+    files integer NOT NULL
+);
+
+ALTER TABLE ONLY lean4oj.storage_usage
+    ADD CONSTRAINT storage_usage_pkey PRIMARY KEY (uid, module);
+
+ALTER TABLE ONLY lean4oj.storage_usage
+    ADD CONSTRAINT storage_usage_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+ALTER TABLE lean4oj.users ADD COLUMN storage_quota bigint;
+ALTER TABLE lean4oj.groups ADD COLUMN storage_quota bigint;
+
+COMMIT;
@@ -0,0 +1,8 @@
+-- Submissions can be Lean sources compiled by the judger instead of an uploaded olean. `sources` lists their module
+-- names relative to the submitter, in compile order; it is null for olean submissions, which all existing ones are.
+
+BEGIN;
+
+ALTER TABLE lean4oj.submissions ADD COLUMN sources text[];
+
+COMMIT;
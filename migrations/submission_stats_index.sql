@@ -0,0 +1,7 @@
+-- User statistics go over a user's submissions per problem, status and toolchain, which this index answers without
+-- touching the table.
+--
+-- Built concurrently so that judging goes on meanwhile, hence outside a transaction. Should it fail halfway, drop the
+-- invalid index and run this again.
+
+CREATE INDEX CONCURRENTLY IF NOT EXISTS submissions_submitter_pid_status_idx ON lean4oj.submissions USING btree (submitter, pid, status) INCLUDE (lean_toolchain);
@@ -0,0 +1,24 @@
+-- Users can enable TOTP two-factor authentication. A row is created on enrollment and `enabled` once the first code
+-- is confirmed; `last_step` keeps a code from being used twice, and only digests of the recovery codes are stored.
+--
+-- A group can require its members to have TOTP enabled; no group does to begin with.
+
+BEGIN;
+
+CREATE TABLE lean4oj.user_totp (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    secret bytea NOT NULL,
+    enabled boolean DEFAULT false NOT NULL,
+    last_step bigint DEFAULT 0 NOT NULL,
+    recovery_codes bytea[] DEFAULT '{}'::bytea[] NOT NULL
+);
+
+ALTER TABLE ONLY lean4oj.user_totp
+    ADD CONSTRAINT user_totp_pkey PRIMARY KEY (uid);
+
+ALTER TABLE ONLY lean4oj.user_totp
+    ADD CONSTRAINT user_totp_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+ALTER TABLE lean4oj.groups ADD COLUMN require_totp boolean DEFAULT false NOT NULL;
+
+COMMIT;
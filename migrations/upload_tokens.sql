@@ -0,0 +1,21 @@
+-- The rsync daemon authenticates with per-user upload tokens instead of account passwords. A token is kept as the
+-- `rsync_verifier` of its secret (see `libs::password`), never as the secret itself, and can be revoked on its own.
+
+BEGIN;
+
+CREATE TABLE lean4oj.upload_tokens (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    name character varying(24) NOT NULL,
+    verifier bytea NOT NULL,
+    read_only boolean DEFAULT false NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    last_used timestamp without time zone
+);
+
+ALTER TABLE ONLY lean4oj.upload_tokens
+    ADD CONSTRAINT upload_tokens_pkey PRIMARY KEY (uid, name);
+
+ALTER TABLE ONLY lean4oj.upload_tokens
+    ADD CONSTRAINT upload_tokens_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+COMMIT;
@@ -0,0 +1,20 @@
+-- Users can be suspended, for good (`until` null) or until a given time, which keeps them from logging in, submitting,
+-- posting and uploading. A user has at most one suspension at a time; lifting it deletes the row.
+
+BEGIN;
+
+CREATE TABLE lean4oj.user_suspensions (
+    uid character varying(24) NOT NULL COLLATE public.case_insensitive,
+    reason text NOT NULL,
+    until timestamp without time zone,
+    suspended_by character varying(24) NOT NULL COLLATE public.case_insensitive,
+    create_time timestamp without time zone NOT NULL
+);
+
+ALTER TABLE ONLY lean4oj.user_suspensions
+    ADD CONSTRAINT user_suspensions_pkey PRIMARY KEY (uid);
+
+ALTER TABLE ONLY lean4oj.user_suspensions
+    ADD CONSTRAINT user_suspensions_uid_fkey FOREIGN KEY (uid) REFERENCES lean4oj.users(uid) MATCH FULL;
+
+COMMIT;
@@ -10,6 +10,9 @@ pub mod fs;
 mod group;
 mod homepage;
 mod judge_client;
+mod notification;
+mod oauth;
+mod olean;
 mod problem;
 mod submission;
 mod user;
@@ -27,6 +30,9 @@ pub fn all() -> Router {
         .nest("/group", group::router(header))
         .nest("/homepage", homepage::router(header))
         .nest("/judgeClient", judge_client::router(header))
+        .nest("/notification", notification::router(header))
+        .nest("/oauth", oauth::router(header))
+        .nest("/olean", olean::router(header))
         .nest("/problem", problem::router(header))
         .nest("/submission", submission::router(header))
         .nest("/user", user::router(header))
@@ -6,33 +6,42 @@ use axum::{
     body::Body,
     extract::Query,
     response::{IntoResponse, Response},
-    routing::{get, post, post_service},
+    routing::{get, post},
 };
 use base64::{display::Base64Display, prelude::BASE64_STANDARD};
 use bytes::Bytes;
 use compact_str::CompactString;
-use http::{StatusCode, Uri, header, response::Parts};
+use http::{HeaderMap, StatusCode, Uri, header, response::Parts};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use smallvec::SmallVec;
-use tower_sessions_core::session::Id;
+use tokio_postgres::Client;
+use tower_sessions_core::{Session, session::Id};
 
 use crate::{
-    bad,
+    bad, exs,
     libs::{
+        audit,
         auth::{Encoded, Session_, availability},
         constants::{
-            APPLICATION_JAVASCRIPT_UTF_8, APPLICATION_JSON_UTF_8, BYTES_NULL, PASSWORD_LENGTH,
+            APPLICATION_JAVASCRIPT_UTF_8, APPLICATION_JSON_UTF_8, BYTES_EMPTY, BYTES_NULL, PASSWORD_LENGTH,
         },
-        db::{DBError, JsonChecked, get_connection},
+        db::{DBError, DBResult, JsonChecked, get_connection},
+        mail, otp, password,
         preference::server::PreferenceConfig,
         privilege,
-        request::{JsonReqult, RawPayload, Repult},
+        ratelimit::{self, AVAILABILITY_ADDRESS, LOGIN_ACCOUNT, LOGIN_ACCOUNT_ANYWHERE, LOGIN_ADDRESS, REGISTER_ADDRESS, RESET_ADDRESS, TOTP_ACCOUNT},
+        request::{JsonReqult, Repult},
         response::JkmxJsonResponse,
-        session,
+        serde::{JsMaybeTime, WithJson},
+        session::{self, GlobalStore},
+        token::{self, Claims, Purpose},
         validate::{check_email, check_uid, check_username},
     },
     models::{
         group::GroupA,
+        suspension::Suspension,
+        totp::Totp,
         user::{User, UserA},
     },
 };
@@ -62,6 +71,15 @@ mod private {
         let err = super::DBError::new(tokio_postgres::error::Kind::RowCount, Some("database insertion error".into()));
         return super::JkmxJsonResponse::Error(super::StatusCode::INTERNAL_SERVER_ERROR, err.into());
     }
+
+    /// The token of `session` as handed to the client.
+    pub(super) fn encode(session: &super::Session<super::GlobalStore>) -> Result<String, openssl::error::ErrorStack> {
+        use super::{BASE64_STANDARD, Base64Display, Encoded, Id, mem, slice};
+
+        let encoded = Encoded::try_from(session.id().unwrap_or(Id(0)))?;
+        let bytes: &[u8] = unsafe { slice::from_raw_parts((&raw const encoded).cast(), mem::size_of::<Encoded>()) };
+        Ok(Base64Display::new(bytes, &BASE64_STANDARD).to_string())
+    }
 }
 
 #[derive(Deserialize)]
@@ -167,47 +185,117 @@ struct LoginRequest {
     password: String,
 }
 
-async fn login(req: JsonReqult<LoginRequest>) -> JkmxJsonResponse {
-    const SQL_ID: &str = "select uid, username from lean4oj.users where uid = $1 and username != '' and password = $2";
-    const SQL_EMAIL: &str = "select uid, username from lean4oj.users where username != '' and email = $1 and password = $2";
+async fn login(headers: HeaderMap, req: JsonReqult<LoginRequest>) -> JkmxJsonResponse {
+    const SQL_ID: &str = "select uid, username, password from lean4oj.users where uid = $1 and username != ''";
+    const SQL_EMAIL: &str = "select uid, username, password from lean4oj.users where username != '' and email = $1";
 
     let Json(LoginRequest { identifier, email, password }) = req?;
     if identifier.is_none() && email.is_none() { bad!(BYTES_NULL) }
 
+    let address = ratelimit::address(&headers);
+    if let Some(address) = address && let Err(t) = ratelimit::check(&LOGIN_ADDRESS, address) { return ratelimit::limited(t); }
+    // before the lookup, so that known and unknown accounts are limited alike.
+    let account = identifier.as_ref().or(email.as_ref()).cloned().unwrap_or_default();
+    let key = ratelimit::pair(&account, address);
+    if let Err(t) = ratelimit::check(&LOGIN_ACCOUNT, &key) { return ratelimit::limited(t); }
+    if let Err(t) = ratelimit::check(&LOGIN_ACCOUNT_ANYWHERE, &account) { return ratelimit::limited(t); }
+    let failed = || {
+        ratelimit::fail(&LOGIN_ACCOUNT, &key);
+        ratelimit::fail(&LOGIN_ACCOUNT_ANYWHERE, &account);
+        if let Some(address) = address { ratelimit::fail(&LOGIN_ADDRESS, address); }
+    };
+
     let mut conn = get_connection().await?;
     let row = if let Some(id) = identifier {
         let stmt = conn.prepare_static(SQL_ID.into()).await?;
-        conn.query_one(&stmt, &[&&*id, &password]).await
+        conn.query_one(&stmt, &[&&*id]).await
     } else {
         let email = unsafe { email.unwrap_unchecked() };
         let stmt = conn.prepare_static(SQL_EMAIL.into()).await?;
-        conn.query_one(&stmt, &[&&*email, &password]).await
+        conn.query_one(&stmt, &[&&*email]).await
     };
     let row = match row {
         Ok(r) => r,
-        Err(e) => return JkmxJsonResponse::Error(StatusCode::BAD_REQUEST, e.into()),
+        Err(e) => {
+            // as slow as a wrong password.
+            password::verify_dummy(&password).await?;
+            failed();
+            return JkmxJsonResponse::Error(StatusCode::BAD_REQUEST, e.into());
+        }
     };
 
-    let uid = row.try_get(0)?;
+    let uid = row.try_get::<_, &str>(0)?;
     let username = row.try_get::<_, &str>(1)?;
-    let session = session::create(uid).await?;
-    let encoded = Encoded::try_from(session.id().unwrap_or(Id(0)))?;
-    let bytes: &[u8] = unsafe { slice::from_raw_parts((&raw const encoded).cast(), mem::size_of::<Encoded>()) };
-    let res = format!(r#"{{"token":"{}","username":"{username}"}}"#, Base64Display::new(bytes, &BASE64_STANDARD));
+    // indistinguishable from an unknown user.
+    if !password::verify(uid, &password, row.try_get(2)?, &mut conn).await? {
+        failed();
+        let err = DBError::new(tokio_postgres::error::Kind::RowCount, None);
+        return JkmxJsonResponse::Error(StatusCode::BAD_REQUEST, err.into());
+    }
+    ratelimit::reset(&LOGIN_ACCOUNT, &key);
+    ratelimit::reset(&LOGIN_ACCOUNT_ANYWHERE, &account);
+
+    finish_login(uid, username, Value::Null, &headers, &mut conn).await
+}
+
+#[derive(Serialize)]
+struct SuspendedResponse<'a> {
+    error: &'static str,
+    reason: &'a str,
+    #[serde(serialize_with = "JsMaybeTime")]
+    until: Option<SystemTime>,
+}
+
+/// Unlike elsewhere, logins are told why they are refused (only once the password checks out).
+async fn suspended(uid: &str, conn: &mut Client) -> DBResult<Option<JkmxJsonResponse>> {
+    let Some(suspension) = Suspension::of(uid, conn).await? else { return Ok(None) };
+    let res = SuspendedResponse { error: "SUSPENDED", reason: &suspension.reason, until: suspension.until };
+    Ok(Some(JkmxJsonResponse::Response(StatusCode::OK, WithJson(res).to_string().into())))
+}
+
+/// Once the first factor (`via`) checks out: the TOTP challenge if `uid` has or needs a second one, a session
+/// otherwise.
+pub(super) async fn finish_login(uid: &str, username: &str, via: Value, headers: &HeaderMap, conn: &mut Client) -> JkmxJsonResponse {
+    if let Some(res) = suspended(uid, conn).await? { return res; }
+    let enabled = Totp::of(uid, conn).await?.is_some_and(|totp| totp.enabled);
+    if enabled || Totp::required(uid, conn).await? {
+        // re-read, as `verify` may have just rehashed the password the challenge is bound to.
+        let Some(user) = User::by_uid(uid, conn).await? else { return private::err() };
+        let Some(challenge) = token::issue(Purpose::Login, &user.uid, user.password.as_bytes()) else { return private::err() };
+        let res = format!(r#"{{"challenge":"{challenge}","totpEnrollmentRequired":{}}}"#, !enabled);
+        return JkmxJsonResponse::Response(StatusCode::OK, res.into());
+    }
+
+    let session = session::create(uid.into(), headers).await?;
+    audit::log(uid, "auth.login", None, audit::Object::None, via, conn).await;
+    let res = format!(r#"{{"token":"{}","username":"{username}"}}"#, private::encode(&session)?);
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
 
 async fn logout(Session_(session): Session_) -> JkmxJsonResponse {
     if let Some(session) = session {
+        let mut conn = get_connection().await?;
+        let user = User::from_session(&session, &mut conn).await?;
         session.delete().await?;
+        if let Some(user) = user {
+            audit::log(&user.uid, "auth.logout", None, audit::Object::None, Value::Null, &mut conn).await;
+        }
     }
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_NULL)
 }
 
-async fn check_availability(req: Uri) -> JkmxJsonResponse {
+async fn check_availability(headers: HeaderMap, req: Uri) -> JkmxJsonResponse {
     let Some(query) = req.query() else { return JkmxJsonResponse::Response(StatusCode::OK, BYTES_NULL) };
 
-    let res = match form_urlencoded::parse(query.as_bytes()).next() {
+    let pair = form_urlencoded::parse(query.as_bytes()).next();
+    // lookups enumerate accounts.
+    if pair.as_ref().is_some_and(|(key, _)| matches!(&**key, "identifier" | "email"))
+    && let Some(address) = ratelimit::address(&headers)
+    && let Err(t) = ratelimit::hit(&AVAILABILITY_ADDRESS, address) {
+        return ratelimit::limited(t);
+    }
+
+    let res = match pair {
         Some((deref!("username"), _)) => const { Bytes::from_static(br#"{"usernameAvailable":true}"#) },
         Some((deref!("identifier"), id)) => {
             let mut conn = get_connection().await?;
@@ -235,9 +323,10 @@ struct RegisterRequest {
 
 async fn register(
     Extension(now): Extension<SystemTime>,
+    headers: HeaderMap,
     req: JsonReqult<RegisterRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_USERS: &str = "insert into lean4oj.users (uid, username, email, password, register_time, avatar_info) values ($1, $2, $3::text, $4, $5, 'gravatar:' || $3::text)";
+    const SQL_USERS: &str = "insert into lean4oj.users (uid, username, email, password, register_time, avatar_info, email_verified, rsync_verifier) values ($1, $2, $3::text, $4, $5, 'gravatar:' || $3::text, false, $6)";
     const SQL_USER_INFORMATION: &str = "insert into lean4oj.user_information (uid) values ($1)";
     const SQL_USER_PREFERENCE: &str = "insert into lean4oj.user_preference (uid) values ($1)";
 
@@ -251,37 +340,465 @@ async fn register(
     if !check_username(&username) || !check_uid(&identifier) || check_email(&email).is_none() || password.len() != PASSWORD_LENGTH || !password.is_ascii() {
         bad!(BYTES_NULL)
     }
+    if let Some(address) = ratelimit::address(&headers) && let Err(t) = ratelimit::hit(&REGISTER_ADDRESS, address) {
+        return ratelimit::limited(t);
+    }
 
+    let verifier = password::rsync_verifier(password.as_bytes()).map(Vec::from);
+    let password = password::hash(&password).await?;
     let mut conn = get_connection().await?;
     let stmt_users = conn.prepare_static(SQL_USERS.into()).await?;
     let stmt_user_information = conn.prepare_static(SQL_USER_INFORMATION.into()).await?;
     let stmt_user_preference = conn.prepare_static(SQL_USER_PREFERENCE.into()).await?;
     let txn = conn.transaction().await?;
-    let n = txn.execute(&stmt_users, &[&&*identifier, &&*username, &&*email, &&*password, &now]).await?;
+    let n = txn.execute(&stmt_users, &[&&*identifier, &&*username, &&*email, &&*password, &now, &verifier]).await?;
     if n != 1 { return private::err() }
     let n = txn.execute(&stmt_user_information, &[&&*identifier]).await?;
     if n != 1 { return private::err() }
     let n = txn.execute(&stmt_user_preference, &[&&*identifier]).await?;
     if n != 1 { return private::err() }
     txn.commit().await?;
+    audit::log(&identifier, "auth.register", None, audit::Object::None, Value::Null, &mut conn).await;
+    mail::send_email_verification(&identifier, &username, &email);
+
+    let session = session::create(identifier.into_string(), &headers).await?;
+    let res = format!(r#"{{"token":"{}"}}"#, private::encode(&session)?);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+/// The actor and target of a session request, the target being oneself, or anyone with `ManageUser`.
+pub(super) async fn session_target(
+    session: Option<&Session<GlobalStore>>,
+    user_id: &str,
+    conn: &mut Client,
+) -> DBResult<Result<(User, User), JkmxJsonResponse>> {
+    let Some(session) = session else { return Ok(Err(JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL))) };
+    let Some(s_user) = User::from_session(session, conn).await? else {
+        return Ok(Err(JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL)));
+    };
+    let Some(t_user) = User::by_uid(user_id, conn).await? else {
+        return Ok(Err(JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NO_SUCH_USER"}"#))));
+    };
+    if s_user.uid != t_user.uid && !privilege::check(&s_user.uid, "Lean4OJ.ManageUser", conn).await? {
+        return Ok(Err(JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL)));
+    }
+    Ok(Ok((s_user, t_user)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListUserSessionsRequest {
+    user_id: CompactString,
+}
+
+async fn list_user_sessions(
+    Session_(session): Session_,
+    req: JsonReqult<ListUserSessionsRequest>,
+) -> JkmxJsonResponse {
+    let Json(ListUserSessionsRequest { user_id }) = req?;
 
-    let session = session::create(identifier.into_string()).await?;
-    let encoded = Encoded::try_from(session.id().unwrap_or(Id(0)))?;
-    let bytes: &[u8] = unsafe { slice::from_raw_parts((&raw const encoded).cast(), mem::size_of::<Encoded>()) };
-    let res = format!(r#"{{"token":"{}"}}"#, Base64Display::new(bytes, &BASE64_STANDARD));
+    let mut conn = get_connection().await?;
+    let (s_user, user) = match session_target(session.as_ref(), &user_id, &mut conn).await? {
+        Ok(t) => t,
+        Err(res) => return res,
+    };
+
+    let current = if s_user.uid == user.uid { session.as_ref().and_then(Session::id) } else { None };
+    let sessions = session::list(&user.uid, current, &mut conn).await?;
+
+    let res = format!(r#"{{"sessions":{}}}"#, WithJson(sessions));
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
 
-const fn list_user_sessions(header: &'static Parts) -> RawPayload {
-    RawPayload { header, body: br#"{"sessions":[]}"# }
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokeUserSessionRequest {
+    user_id: CompactString,
+    session_id: CompactString,
+}
+
+async fn revoke_user_session(
+    Session_(session): Session_,
+    req: JsonReqult<RevokeUserSessionRequest>,
+) -> JkmxJsonResponse {
+    let Json(RevokeUserSessionRequest { user_id, session_id }) = req?;
+
+    let Ok(id) = session_id.parse::<Id>() else { bad!(BYTES_NULL) };
+
+    let mut conn = get_connection().await?;
+    let (s_user, user) = match session_target(session.as_ref(), &user_id, &mut conn).await? {
+        Ok(t) => t,
+        Err(res) => return res,
+    };
+
+    if !session::revoke(&user.uid, id, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NO_SUCH_SESSION"}"#));
+    }
+    audit::log(&s_user.uid, "auth.revokeSession", Some(&*user.uid), audit::Object::None, Value::from(&*session_id), &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokeAllUserSessionsRequest {
+    user_id: CompactString,
+}
+
+/// Revoking one's own sessions keeps the current one.
+async fn revoke_all_user_sessions(
+    Session_(session): Session_,
+    req: JsonReqult<RevokeAllUserSessionsRequest>,
+) -> JkmxJsonResponse {
+    let Json(RevokeAllUserSessionsRequest { user_id }) = req?;
+
+    let mut conn = get_connection().await?;
+    let (s_user, user) = match session_target(session.as_ref(), &user_id, &mut conn).await? {
+        Ok(t) => t,
+        Err(res) => return res,
+    };
+
+    let except = if s_user.uid == user.uid { session.as_ref().and_then(Session::id) } else { None };
+    let n = session::revoke_all(&user.uid, except, &mut conn).await?;
+    audit::log(&s_user.uid, "auth.revokeAllSessions", Some(&*user.uid), audit::Object::None, Value::from(n), &mut conn).await;
+
+    let res = format!(r#"{{"revoked":{n}}}"#);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+const RATE_LIMITED: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"RATE_LIMITED"}"#));
+const INVALID_TOKEN: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"INVALID_TOKEN"}"#));
+
+async fn send_email_verification(Session_(session): Session_) -> JkmxJsonResponse {
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+
+    if user.is_email_verified(&mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"ALREADY_VERIFIED"}"#));
+    }
+    if !mail::send_email_verification(&user.uid, &user.username, &user.email) { return RATE_LIMITED; }
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+struct VerifyEmailRequest {
+    token: CompactString,
+}
+
+async fn verify_email(req: JsonReqult<VerifyEmailRequest>) -> JkmxJsonResponse {
+    let Json(VerifyEmailRequest { token }) = req?;
+
+    let Some(claims) = Claims::parse(Purpose::VerifyEmail, &token) else { return INVALID_TOKEN };
+    let mut conn = get_connection().await?;
+    let Some(user) = User::by_uid(&claims.uid, &mut conn).await? else { return INVALID_TOKEN };
+    // a changed email invalidates the token.
+    if !claims.verify(user.email.as_bytes()) || !User::verify_email(&user.uid, &user.email, &mut conn).await? {
+        return INVALID_TOKEN;
+    }
+    audit::log(&user.uid, "auth.verifyEmail", None, audit::Object::None, Value::from(&*user.email), &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+struct RequestPasswordResetRequest {
+    email: CompactString,
+}
+
+/// Does not reveal whether the email belongs to anyone.
+async fn request_password_reset(headers: HeaderMap, req: JsonReqult<RequestPasswordResetRequest>) -> JkmxJsonResponse {
+    let Json(RequestPasswordResetRequest { email }) = req?;
+
+    if check_email(&email).is_none() { bad!(BYTES_NULL) }
+    if let Some(address) = ratelimit::address(&headers) && let Err(t) = ratelimit::hit(&RESET_ADDRESS, address) {
+        return ratelimit::limited(t);
+    }
+
+    // before the lookup, so that being rate limited does not tell either.
+    if !mail::acquire(&email) { return RATE_LIMITED; }
+    let mut conn = get_connection().await?;
+    if let Some(user) = User::by_email(&email, &mut conn).await? {
+        mail::send_password_reset(&user.uid, &user.username, &user.email, &user.password);
+    }
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+struct ResetPasswordRequest {
+    token: CompactString,
+    password: String,
+}
+
+/// Also signs the user out everywhere.
+async fn reset_password(req: JsonReqult<ResetPasswordRequest>) -> JkmxJsonResponse {
+    const SQL: &str = "update lean4oj.users set password = $1, rsync_verifier = $4 where uid = $2 and password = $3";
+
+    let Json(ResetPasswordRequest { token, password }) = req?;
+
+    if password.len() != PASSWORD_LENGTH || !password.is_ascii() { bad!(BYTES_NULL) }
+    let Some(claims) = Claims::parse(Purpose::ResetPassword, &token) else { return INVALID_TOKEN };
+
+    let mut conn = get_connection().await?;
+    let Some(user) = User::by_uid(&claims.uid, &mut conn).await? else { return INVALID_TOKEN };
+    if !claims.verify(user.password.as_bytes()) { return INVALID_TOKEN; }
+
+    let hashed = password::hash(&password).await?;
+    let verifier = password::rsync_verifier(password.as_bytes()).map(Vec::from);
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    // the token is bound to the old hash, so this also makes it single-use.
+    if conn.execute(&stmt, &[&hashed, &&*user.uid, &&*user.password, &verifier]).await? != 1 { return INVALID_TOKEN; }
+    session::revoke_all(&user.uid, None, &mut conn).await?;
+    audit::log(&user.uid, "auth.resetPassword", Some(&*user.uid), audit::Object::None, Value::Null, &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+const INVALID_CODE: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"INVALID_CODE"}"#));
+const NOT_ENROLLING: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NOT_ENROLLING"}"#));
+const TOTP_ALREADY_ENABLED: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"TOTP_ALREADY_ENABLED"}"#));
+const TOTP_NOT_ENABLED: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"TOTP_NOT_ENABLED"}"#));
+
+/// The user halfway through a login, from the challenge issued by `login` (which dies with a password change).
+async fn challenged(challenge: &str, conn: &mut Client) -> DBResult<Option<User>> {
+    let Some(claims) = Claims::parse(Purpose::Login, challenge) else { return Ok(None) };
+    let Some(user) = User::by_uid(&claims.uid, conn).await? else { return Ok(None) };
+    Ok(claims.verify(user.password.as_bytes()).then_some(user))
+}
+
+/// The user enrolling in TOTP: signed in, or halfway through a login that requires it.
+async fn enrollee(
+    session: Option<&Session<GlobalStore>>,
+    challenge: Option<&str>,
+    conn: &mut Client,
+) -> DBResult<Result<User, JkmxJsonResponse>> {
+    if let Some(challenge) = challenge {
+        return Ok(challenged(challenge, conn).await?.ok_or(INVALID_TOKEN));
+    }
+    let user = match session {
+        Some(session) => User::from_session(session, conn).await?,
+        None => None,
+    };
+    Ok(user.ok_or(JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL)))
+}
+
+/// Checks a code (or recovery code) of `uid`, allowing only a few failures in a row.
+async fn second_factor(
+    uid: &str,
+    totp: &Totp,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+    conn: &mut Client,
+) -> DBResult<Result<(), JkmxJsonResponse>> {
+    if let Err(t) = ratelimit::check(&TOTP_ACCOUNT, uid) { return Ok(Err(ratelimit::limited(t))); }
+    if !totp.check(uid, code, recovery_code, conn).await? {
+        ratelimit::fail(&TOTP_ACCOUNT, uid);
+        return Ok(Err(INVALID_CODE));
+    }
+    ratelimit::reset(&TOTP_ACCOUNT, uid);
+    Ok(Ok(()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginTotpRequest {
+    challenge: CompactString,
+    code: Option<CompactString>,
+    recovery_code: Option<CompactString>,
+}
+
+async fn login_totp(headers: HeaderMap, req: JsonReqult<LoginTotpRequest>) -> JkmxJsonResponse {
+    let Json(LoginTotpRequest { challenge, code, recovery_code }) = req?;
+
+    let mut conn = get_connection().await?;
+    let Some(user) = challenged(&challenge, &mut conn).await? else { return INVALID_TOKEN };
+    if let Some(res) = suspended(&user.uid, &mut conn).await? { return res; }
+    let Some(totp) = Totp::of(&user.uid, &mut conn).await?.filter(|totp| totp.enabled) else { return TOTP_NOT_ENABLED };
+    if let Err(res) = second_factor(&user.uid, &totp, code.as_deref(), recovery_code.as_deref(), &mut conn).await? {
+        return res;
+    }
+
+    let session = session::create(user.uid.to_string(), &headers).await?;
+    let via = if code.is_some() { "totp" } else { "recoveryCode" };
+    audit::log(&user.uid, "auth.login", None, audit::Object::None, Value::from(via), &mut conn).await;
+    let res = format!(r#"{{"token":"{}","username":"{}"}}"#, private::encode(&session)?, user.username);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+#[derive(Deserialize)]
+struct BeginTotpEnrollmentRequest {
+    challenge: Option<CompactString>,
+}
+
+#[derive(Serialize)]
+struct BeginTotpEnrollmentResponse {
+    secret: String,
+    uri: String,
+}
+
+/// Restarting discards the previous secret, until the enrollment is confirmed.
+async fn begin_totp_enrollment(
+    Session_(session): Session_,
+    req: JsonReqult<BeginTotpEnrollmentRequest>,
+) -> JkmxJsonResponse {
+    let Json(BeginTotpEnrollmentRequest { challenge }) = req?;
+
+    let mut conn = get_connection().await?;
+    let user = match enrollee(session.as_ref(), challenge.as_deref(), &mut conn).await? {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+
+    let secret = otp::gen_secret();
+    if !Totp::begin(&user.uid, &secret, &mut conn).await? { return TOTP_ALREADY_ENABLED; }
+
+    let res = BeginTotpEnrollmentResponse { secret: otp::base32(&secret), uri: otp::uri(&user.uid, &secret) };
+    JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
+}
+
+#[derive(Deserialize)]
+struct ConfirmTotpEnrollmentRequest {
+    challenge: Option<CompactString>,
+    code: CompactString,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecoveryCodesResponse {
+    recovery_codes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<CompactString>,
+}
+
+/// Through a `challenge`, this also completes the login.
+async fn confirm_totp_enrollment(
+    Session_(session): Session_,
+    headers: HeaderMap,
+    req: JsonReqult<ConfirmTotpEnrollmentRequest>,
+) -> JkmxJsonResponse {
+    let Json(ConfirmTotpEnrollmentRequest { challenge, code }) = req?;
+
+    let mut conn = get_connection().await?;
+    let user = match enrollee(session.as_ref(), challenge.as_deref(), &mut conn).await? {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    if challenge.is_some() && let Some(res) = suspended(&user.uid, &mut conn).await? { return res; }
+
+    let Some(totp) = Totp::of(&user.uid, &mut conn).await? else { return NOT_ENROLLING };
+    if totp.enabled { return TOTP_ALREADY_ENABLED; }
+    if let Err(t) = ratelimit::check(&TOTP_ACCOUNT, &user.uid) { return ratelimit::limited(t); }
+    let Some(step) = otp::verify(&totp.secret, &code, totp.last_step) else {
+        ratelimit::fail(&TOTP_ACCOUNT, &user.uid);
+        return INVALID_CODE;
+    };
+    ratelimit::reset(&TOTP_ACCOUNT, &user.uid);
+
+    let (recovery_codes, digests) = otp::gen_recovery_codes();
+    // the enrollment may have been restarted with another secret meanwhile.
+    if !Totp::enable(&user.uid, &totp.secret, step, &digests, &mut conn).await? { return NOT_ENROLLING; }
+    audit::log(&user.uid, "auth.enableTotp", Some(&*user.uid), audit::Object::None, Value::Null, &mut conn).await;
+
+    let mut res = RecoveryCodesResponse { recovery_codes, token: None, username: None };
+    if challenge.is_some() {
+        let session = session::create(user.uid.to_string(), &headers).await?;
+        audit::log(&user.uid, "auth.login", None, audit::Object::None, Value::from("totp"), &mut conn).await;
+        res.token = Some(private::encode(&session)?);
+        res.username = Some(user.username);
+    }
+    JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DisableTotpRequest {
+    user_id: CompactString,
+    code: Option<CompactString>,
+    recovery_code: Option<CompactString>,
+}
+
+/// Disabling one's own needs a code, `ManageUser` can disable anyone's (e.g. of a lost device) without, but only
+/// `Admin` can disable that of someone holding `ManageUser` (or `Admin`).
+async fn disable_totp(
+    Session_(session): Session_,
+    req: JsonReqult<DisableTotpRequest>,
+) -> JkmxJsonResponse {
+    let Json(DisableTotpRequest { user_id, code, recovery_code }) = req?;
+
+    let mut conn = get_connection().await?;
+    let (s_user, user) = match session_target(session.as_ref(), &user_id, &mut conn).await? {
+        Ok(t) => t,
+        Err(res) => return res,
+    };
+
+    if s_user.uid != user.uid
+    && privilege::check(&user.uid, "Lean4OJ.ManageUser", &mut conn).await?
+    && !privilege::check(&s_user.uid, "Lean4OJ.Admin", &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+    let Some(totp) = Totp::of(&user.uid, &mut conn).await? else { return TOTP_NOT_ENABLED };
+    if s_user.uid == user.uid && totp.enabled
+    && let Err(res) = second_factor(&user.uid, &totp, code.as_deref(), recovery_code.as_deref(), &mut conn).await? {
+        return res;
+    }
+    Totp::disable(&user.uid, &mut conn).await?;
+    if totp.enabled {
+        audit::log(&s_user.uid, "auth.disableTotp", Some(&*user.uid), audit::Object::None, Value::Null, &mut conn).await;
+    }
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegenerateRecoveryCodesRequest {
+    code: Option<CompactString>,
+    recovery_code: Option<CompactString>,
+}
+
+/// Invalidates the remaining recovery codes.
+async fn regenerate_recovery_codes(
+    Session_(session): Session_,
+    req: JsonReqult<RegenerateRecoveryCodesRequest>,
+) -> JkmxJsonResponse {
+    let Json(RegenerateRecoveryCodesRequest { code, recovery_code }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+
+    let Some(totp) = Totp::of(&user.uid, &mut conn).await?.filter(|totp| totp.enabled) else { return TOTP_NOT_ENABLED };
+    if let Err(res) = second_factor(&user.uid, &totp, code.as_deref(), recovery_code.as_deref(), &mut conn).await? {
+        return res;
+    }
+
+    let (recovery_codes, digests) = otp::gen_recovery_codes();
+    if !Totp::set_recovery_codes(&user.uid, &digests, &mut conn).await? { return TOTP_NOT_ENABLED; }
+    audit::log(&user.uid, "auth.regenerateRecoveryCodes", Some(&*user.uid), audit::Object::None, Value::Null, &mut conn).await;
+
+    let res = RecoveryCodesResponse { recovery_codes, token: None, username: None };
+    JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
 }
 
-pub fn router(header: &'static Parts) -> Router {
+pub fn router(_header: &'static Parts) -> Router {
     Router::new()
         .route("/getSessionInfo", get(get_session_info))
         .route("/login", post(login))
+        .route("/loginTotp", post(login_totp))
         .route("/logout", post(logout))
         .route("/checkAvailability", get(check_availability))
         .route("/register", post(register))
-        .route("/listUserSessions", post_service(list_user_sessions(header)))
+        .route("/listUserSessions", post(list_user_sessions))
+        .route("/revokeUserSession", post(revoke_user_session))
+        .route("/revokeAllUserSessions", post(revoke_all_user_sessions))
+        .route("/sendEmailVerification", post(send_email_verification))
+        .route("/verifyEmail", post(verify_email))
+        .route("/requestPasswordReset", post(request_password_reset))
+        .route("/resetPassword", post(reset_password))
+        .route("/beginTotpEnrollment", post(begin_totp_enrollment))
+        .route("/confirmTotpEnrollment", post(confirm_totp_enrollment))
+        .route("/disableTotp", post(disable_totp))
+        .route("/regenerateRecoveryCodes", post(regenerate_recovery_codes))
 }
@@ -1,5 +1,5 @@
-use core::{fmt::Write, mem};
-use std::time::SystemTime;
+use core::fmt::Write;
+use std::{collections::BTreeMap, time::SystemTime};
 
 use axum::{
     Extension, Json, Router,
@@ -13,14 +13,15 @@ use serde::{
     Deserialize, Serialize, Serializer,
     ser::{SerializeMap, SerializeSeq},
 };
+use serde_json::json;
 use smallvec::SmallVec;
-use tokio_postgres::types::ToSql;
+use tokio_postgres::types::{Json as QJson, ToSql};
 
 use crate::{
-    exs,
+    bad, exs,
     libs::{
         auth::Session_,
-        constants::BYTES_EMPTY,
+        constants::{BYTES_EMPTY, BYTES_NULL},
         db::{DBError, get_connection},
         emoji,
         lquery::𝑒𝑠𝑐𝑎𝑝𝑒,
@@ -32,14 +33,21 @@ use crate::{
     },
     models::{
         discussion::{
-            Discussion, DiscussionReactionAOE, DiscussionReactionType, DiscussionReply,
-            DiscussionReplyAOE, QueryRepliesType, REPLY_PERMISSION_DEFAULT, reaction_aoe,
+            Discussion, DiscussionInner, DiscussionReactionAOE, DiscussionReactionType,
+            DiscussionReply, DiscussionReplyAOE, QueryRepliesType, REPLY_PERMISSION_DEFAULT,
+            reaction_aoe,
         },
+        localedict::{DEFAULT_LOCALE, LocaleDict},
+        notification::{self, notify},
         problem::Problem,
         user::{User, UserAOE},
     },
 };
 
+const EMAIL_NOT_VERIFIED: JkmxJsonResponse = JkmxJsonResponse::Response(
+    StatusCode::OK,
+    Bytes::from_static(br#"{"error":"EMAIL_NOT_VERIFIED"}"#),
+);
 const INVALID_EMOJI: JkmxJsonResponse = JkmxJsonResponse::Response(
     StatusCode::OK,
     Bytes::from_static(br#"{"error":"INVALID_EMOJI"}"#),
@@ -56,10 +64,22 @@ const NO_SUCH_DISCUSSION_REPLY: JkmxJsonResponse = JkmxJsonResponse::Response(
     StatusCode::OK,
     Bytes::from_static(br#"{"error":"NO_SUCH_DISCUSSION_REPLY"}"#),
 );
+/// Or the only one, which cannot be deleted.
+const NO_SUCH_TRANSLATION: JkmxJsonResponse = JkmxJsonResponse::Response(
+    StatusCode::OK,
+    Bytes::from_static(br#"{"error":"NO_SUCH_TRANSLATION"}"#),
+);
 const NO_SUCH_USER: JkmxJsonResponse = JkmxJsonResponse::Response(
     StatusCode::OK,
     Bytes::from_static(br#"{"error":"NO_SUCH_USER"}"#),
 );
+const SUSPENDED: JkmxJsonResponse = JkmxJsonResponse::Response(
+    StatusCode::OK,
+    Bytes::from_static(br#"{"error":"SUSPENDED"}"#),
+);
+
+/// In characters, of each translation.
+const TITLE_LENGTH: usize = 256;
 
 mod private {
     pub(super) fn err() -> super::JkmxJsonResponse {
@@ -72,6 +92,7 @@ mod private {
 #[serde(rename_all = "camelCase")]
 struct CreateDiscussionRequest {
     problem_id: Option<i32>,
+    locale: Option<CompactString>,
     title: CompactString,
     content: CompactString,
 }
@@ -81,12 +102,22 @@ async fn create_discussion(
     Session_(session): Session_,
     req: JsonReqult<CreateDiscussionRequest>,
 ) -> JkmxJsonResponse {
-    let Json(CreateDiscussionRequest { problem_id, title, content }) = req?;
+    let Json(CreateDiscussionRequest { problem_id, locale, title, content }) = req?;
+
+    if title.chars().count() > TITLE_LENGTH { bad!(BYTES_NULL) }
+    let locale = locale.unwrap_or_else(|| DEFAULT_LOCALE.into());
+    let content = LocaleDict(BTreeMap::from([(locale, DiscussionInner { title, content })]));
 
     let mut conn = get_connection().await?;
     exs!(user, &session, &mut conn);
+    if !user.is_email_verified(&mut conn).await? { return EMAIL_NOT_VERIFIED; }
+    if user.is_suspended(&mut conn).await? { return SUSPENDED; }
 
-    let id = Discussion::create(problem_id, &title, &content, now, &user.uid, &mut conn).await?;
+    let id = Discussion::create(problem_id, &content, now, &user.uid, &mut conn).await?;
+    if let Some(pid) = problem_id && let Some(problem) = Problem::by_pid(pid, &mut conn).await? && problem.owner != user.uid {
+        let details = json!({ "problemId": pid, "discussionId": id, "userId": user.uid });
+        notify(&problem.owner, notification::Kind::ProblemDiscussion, details, &mut conn).await;
+    }
     let res = format!(r#"{{"discussionId":{id}}}"#);
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
@@ -95,6 +126,7 @@ async fn create_discussion(
 #[serde(rename_all = "camelCase")]
 struct CreateReplyRequest {
     discussion_id: u32,
+    locale: Option<CompactString>,
     content: CompactString,
 }
 
@@ -103,27 +135,37 @@ async fn create_reply(
     Session_(session): Session_,
     req: JsonReqult<CreateReplyRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_CREATE_REPLY: &str = "insert into lean4oj.discussion_replies (content, publish, edit, did, publisher) values ($1, $2, $2, $3, $4) returning id";
-    const SQL_UPDATE_PARENT: &str = "update lean4oj.discussions set update = $1, reply_count = reply_count + 1 where id = $2";
+    const SQL_CREATE_REPLY: &str = "insert into lean4oj.discussion_replies (rcontent, publish, edit, did, publisher) values ($1, $2, $2, $3, $4) returning id";
+    const SQL_UPDATE_PARENT: &str = "update lean4oj.discussions set update = $1, reply_count = reply_count + 1 where id = $2 returning publisher";
 
-    let Json(CreateReplyRequest { discussion_id, content }) = req?;
+    let Json(CreateReplyRequest { discussion_id, locale, content }) = req?;
+
+    let locale = locale.unwrap_or_else(|| DEFAULT_LOCALE.into());
+    let content = LocaleDict(BTreeMap::from([(locale.clone(), content)]));
 
     let mut conn = get_connection().await?;
     exs!(user, &session, &mut conn);
+    if !user.is_email_verified(&mut conn).await? { return EMAIL_NOT_VERIFIED; }
+    if user.is_suspended(&mut conn).await? { return SUSPENDED; }
 
     let stmt_create_reply = conn.prepare_static(SQL_CREATE_REPLY.into()).await?;
     let stmt_update_parent = conn.prepare_static(SQL_UPDATE_PARENT.into()).await?;
     let txn = conn.transaction().await?;
-    let row = txn.query_one(&stmt_create_reply, &[&&*content, &now, &discussion_id.cast_signed(), &&*user.uid]).await?;
+    let row = txn.query_one(&stmt_create_reply, &[&QJson(&content.0), &now, &discussion_id.cast_signed(), &&*user.uid]).await?;
     let id = row.try_get::<_, i32>(0)?.cast_unsigned();
-    let n = txn.execute(&stmt_update_parent, &[&now, &discussion_id.cast_signed()]).await?;
-    if n != 1 { return private::err(); }
+    let Some(row) = txn.query_opt(&stmt_update_parent, &[&now, &discussion_id.cast_signed()]).await? else { return private::err() };
+    let parent_publisher = row.try_get::<_, &str>(0)?;
     txn.commit().await?;
+    if parent_publisher != user.uid {
+        let details = json!({ "discussionId": discussion_id, "replyId": id, "userId": user.uid });
+        notify(parent_publisher, notification::Kind::DiscussionReply, details, &mut conn).await;
+    }
 
     let privi = privilege::all(&user.uid, &mut conn).await?;
     let reply = DiscussionReply {
         id,
         content,
+        locale,
         publish: now,
         edit: now,
         did: discussion_id,
@@ -257,9 +299,7 @@ async fn query_discussions(
 
     let publisher__inner___ = publisher_id.as_deref();
     let buf;
-    let mut magic = false;
     let keyword__inner___ = if let Some(ref kw) = keyword {
-        magic = kw.contains(Discussion::MAGIC_PREFIX);
         buf = 𝑒𝑠𝑐𝑎𝑝𝑒(kw);
         Some(&*buf)
     } else { None };
@@ -285,7 +325,7 @@ async fn query_discussions(
             }
         }
         if let Some(ref kw) = keyword__inner___ {
-            let _ = write!(&mut sql, " and title ilike ${}", args.len() + 1);
+            let _ = write!(&mut sql, " and exists (select from jsonb_each(dcontent) where value->>'title' ilike ${})", args.len() + 1);
             args.push(
                 unsafe { core::mem::transmute::<&&str, &'static &str>(kw) } as _
             );
@@ -314,32 +354,27 @@ async fn query_discussions(
 
     let mut res = r#"{"discussions":"#.to_owned();
     if title_only == Some(true) {
-        let discussions = if magic {
-            Vec::new()
-        } else {
-            let mut discussions = Discussion::search(skip, take, extend, &mut conn).await?;
-            for d in &mut discussions { d.backdoor(locale.as_deref()); }
-            #[allow(clippy::transmute_undefined_repr)]
-            unsafe { core::mem::transmute::<Vec<Discussion>, Vec<Inner1>>(discussions) }
-        };
+        let mut discussions = Discussion::search(skip, take, extend, &mut conn).await?;
+        for d in &mut discussions { d.localize(locale.as_deref()); }
+        #[allow(clippy::transmute_undefined_repr)]
+        let discussions = unsafe { core::mem::transmute::<Vec<Discussion>, Vec<Inner1>>(discussions) };
         serde_json::to_writer(unsafe { res.as_mut_vec() }, &discussions)?;
     } else {
-        let mut discussions = Vec::new();
-        if !magic {
-            discussions = Discussion::search_aoe(skip, take, extend, &mut conn)
-                .await?
-                .into_iter()
-                .map(|(meta, problem, publisher)| Inner3 {
+        let discussions = Discussion::search_aoe(skip, take, extend, &mut conn)
+            .await?
+            .into_iter()
+            .map(|(mut meta, problem, publisher)| {
+                meta.localize(locale.as_deref());
+                Inner3 {
                     meta,
                     problem: problem.map(|problem| Inner2 {
                         problem,
                         locale: locale.as_deref(),
                     }),
                     publisher,
-                })
-                .collect::<Vec<Inner3>>();
-            for d in &mut discussions { d.meta.backdoor(locale.as_deref()); }
-        }
+                }
+            })
+            .collect::<Vec<Inner3>>();
         serde_json::to_writer(unsafe { res.as_mut_vec() }, &discussions)?;
         let count = Discussion::count_aoe(extend, &mut conn).await?;
         write!(&mut res, r#","permissions":{{"createDiscussion":true,"filterNonpublic":true}},"count":{count}"#)?;
@@ -390,12 +425,15 @@ struct GetDiscussionRequest {
     #[serde(flatten)]
     query_replies_type: Option<QueryRepliesType>,
     get_discussion: Option<bool>,
+    localized_contents_of_all_locales: Option<bool>,
 }
 
 #[derive(Serialize)]
 struct Inner4<'a> {
     meta: Discussion,
     content: CompactString,
+    #[serde(rename = "localizedContents", skip_serializing_if = "Option::is_none")]
+    localized_contents: Option<LocaleDict<DiscussionInner>>,
     problem: Option<Inner2<'a>>,
     publisher: UserAOE,
     reactions: DiscussionReactionAOE,
@@ -480,7 +518,7 @@ async fn get_discussion(
     Session_(session): Session_,
     req: JsonReqult<GetDiscussionRequest>,
 ) -> JkmxJsonResponse {
-    let Json(GetDiscussionRequest { locale, discussion_id, query_replies_type, get_discussion }) = req?;
+    let Json(GetDiscussionRequest { locale, discussion_id, query_replies_type, get_discussion, localized_contents_of_all_locales }) = req?;
 
     let mut res = GetDiscussionResponse {
         discussion: None,
@@ -502,7 +540,8 @@ async fn get_discussion(
         Some(QueryRepliesType::HeadTail { head_take_count, tail_take_count }) => {
             let head = head_take_count.min(50);
             let tail = tail_take_count.min(50);
-            let replies = DiscussionReply::stat_head_tail(discussion_id, head, tail, &mut conn).await?;
+            let mut replies = DiscussionReply::stat_head_tail(discussion_id, head, tail, &mut conn).await?;
+            for r in &mut replies { r.localize(locale.as_deref()); }
             let lookup = privilege::get_area_of_effect(replies.iter().map(|r| &*r.publisher), &mut conn).await?;
             let lookup2 = reaction_aoe(replies.iter().map(|r| (!r.id).cast_signed()).chain(𝑘), uid, &mut conn).await?;
             res.replies = Some(Inner6 {
@@ -515,7 +554,8 @@ async fn get_discussion(
         }
         Some(QueryRepliesType::IdRange { before_id, after_id, id_range_take_count }) => {
             let count = id_range_take_count.min(100);
-            let replies = DiscussionReply::stat_interval(discussion_id, before_id, after_id, count, &mut conn).await?;
+            let mut replies = DiscussionReply::stat_interval(discussion_id, before_id, after_id, count, &mut conn).await?;
+            for r in &mut replies { r.localize(locale.as_deref()); }
             let lookup = privilege::get_area_of_effect(replies.iter().map(|r| &*r.publisher), &mut conn).await?;
             let lookup2 = reaction_aoe(replies.iter().map(|r| (!r.id).cast_signed()).chain(𝑘), uid, &mut conn).await?;
             res.replies = Some(Inner6 {
@@ -531,8 +571,9 @@ async fn get_discussion(
 
     if let Some(did) = 𝑘 {
         let Some((mut discussion, publisher)) = Discussion::by_id_aoe(did.cast_unsigned(), &mut conn).await? else { return NO_SUCH_DISCUSSION };
-        discussion.backdoor(locale.as_deref());
-        let content = mem::take(&mut discussion.content);
+        discussion.localize(locale.as_deref());
+        let content = discussion.content.0.get(&discussion.locale).map_or_default(|x| x.content.clone());
+        let localized_contents = (localized_contents_of_all_locales == Some(true)).then(|| discussion.content.clone());
         let privi = privilege::all(&publisher.uid, &mut conn).await?;
 
         // count
@@ -559,6 +600,7 @@ async fn get_discussion(
         res.discussion = Some(Inner4 {
             meta: discussion,
             content,
+            localized_contents,
             problem: problem.map(|problem| Inner2 {
                 problem,
                 locale: locale.as_deref(),
@@ -582,6 +624,8 @@ async fn get_discussion(
 #[serde(rename_all = "camelCase")]
 struct UpdateDiscussionRequest {
     discussion_id: u32,
+    /// Of the translation to add or replace, by default the one shown by default.
+    locale: Option<CompactString>,
     title: CompactString,
     content: CompactString,
 }
@@ -591,22 +635,17 @@ async fn update_discussion(
     Session_(session): Session_,
     req: JsonReqult<UpdateDiscussionRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_PRIV: &str = "update lean4oj.discussions set title = $1, content = $2, edit = $3, update = $3 where id = $4";
-    const SQL: &str = "update lean4oj.discussions set title = $1, content = $2, edit = $3 where id = $4 and publisher = $5";
+    let Json(UpdateDiscussionRequest { discussion_id, locale, title, content }) = req?;
 
-    let Json(UpdateDiscussionRequest { discussion_id, title, content }) = req?;
+    if title.chars().count() > TITLE_LENGTH { bad!(BYTES_NULL) }
 
     let mut conn = get_connection().await?;
     exs!(user, &session, &mut conn);
 
-    let n = if privilege::check(&user.uid, "Lean4OJ.ManageDiscussion", &mut conn).await? {
-        let stmt = conn.prepare_static(SQL_PRIV.into()).await?;
-        conn.execute(&stmt, &[&&*title, &&*content, &now, &discussion_id.cast_signed()]).await
-    } else {
-        let stmt = conn.prepare_static(SQL.into()).await?;
-        conn.execute(&stmt, &[&&*title, &&*content, &now, &discussion_id.cast_signed(), &&*user.uid]).await
-    }?;
-    if n != 1 { return private::err(); }
+    let privi = privilege::check(&user.uid, "Lean4OJ.ManageDiscussion", &mut conn).await?;
+    let publisher = if privi { None } else { Some(&*user.uid) };
+    let inner = DiscussionInner { title, content };
+    if !Discussion::translate(discussion_id, publisher, locale.as_deref(), inner, now, privi, &mut conn).await? { return private::err(); }
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -615,6 +654,8 @@ async fn update_discussion(
 #[serde(rename_all = "camelCase")]
 struct UpdateReplyRequest {
     discussion_reply_id: u32,
+    /// Of the translation to add or replace, by default the one shown by default.
+    locale: Option<CompactString>,
     content: CompactString,
 }
 
@@ -623,31 +664,64 @@ async fn update_reply(
     Session_(session): Session_,
     req: JsonReqult<UpdateReplyRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_PRIV: &str = "update lean4oj.discussion_replies set content = $1, edit = $2 where id = $3 returning did";
-    const SQL: &str = "update lean4oj.discussion_replies set content = $1, edit = $2 where id = $3 and publisher = $4 returning did";
-    const SQL_UPDATE_PARENT: &str = "update lean4oj.discussions set update = $1 where id = $2";
-
-    let Json(UpdateReplyRequest { discussion_reply_id, content }) = req?;
+    let Json(UpdateReplyRequest { discussion_reply_id, locale, content }) = req?;
 
     let mut conn = get_connection().await?;
     exs!(user, &session, &mut conn);
 
-    let row = if privilege::check(&user.uid, "Lean4OJ.ManageDiscussion", &mut conn).await? {
-        let stmt = conn.prepare_static(SQL_PRIV.into()).await?;
-        conn.query_one(&stmt, &[&&*content, &now, &discussion_reply_id.cast_signed()]).await
-    } else {
-        let stmt = conn.prepare_static(SQL.into()).await?;
-        conn.query_one(&stmt, &[&&*content, &now, &discussion_reply_id.cast_signed(), &&*user.uid]).await
-    }?;
-    let did = row.try_get::<_, i32>(0)?;
-    let stmt = conn.prepare_static(SQL_UPDATE_PARENT.into()).await?;
-    let n = conn.execute(&stmt, &[&now, &did]).await?;
-    if n != 1 { return private::err(); }
+    let publisher = if privilege::check(&user.uid, "Lean4OJ.ManageDiscussion", &mut conn).await? { None } else { Some(&*user.uid) };
+    if !DiscussionReply::translate(discussion_reply_id, publisher, locale.as_deref(), content, now, &mut conn).await? { return private::err(); }
 
     let res = format!(r#"{{"editTime":{}}}"#, get_millis(now));
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeleteDiscussionTranslationRequest {
+    discussion_id: u32,
+    locale: CompactString,
+}
+
+async fn delete_discussion_translation(
+    Extension(now): Extension<SystemTime>,
+    Session_(session): Session_,
+    req: JsonReqult<DeleteDiscussionTranslationRequest>,
+) -> JkmxJsonResponse {
+    let Json(DeleteDiscussionTranslationRequest { discussion_id, locale }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+
+    let publisher = if privilege::check(&user.uid, "Lean4OJ.ManageDiscussion", &mut conn).await? { None } else { Some(&*user.uid) };
+    if !Discussion::untranslate(discussion_id, publisher, &locale, now, &mut conn).await? { return NO_SUCH_TRANSLATION; }
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeleteReplyTranslationRequest {
+    discussion_reply_id: u32,
+    locale: CompactString,
+}
+
+async fn delete_reply_translation(
+    Extension(now): Extension<SystemTime>,
+    Session_(session): Session_,
+    req: JsonReqult<DeleteReplyTranslationRequest>,
+) -> JkmxJsonResponse {
+    let Json(DeleteReplyTranslationRequest { discussion_reply_id, locale }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+
+    let publisher = if privilege::check(&user.uid, "Lean4OJ.ManageDiscussion", &mut conn).await? { None } else { Some(&*user.uid) };
+    if !DiscussionReply::untranslate(discussion_reply_id, publisher, &locale, now, &mut conn).await? { return NO_SUCH_TRANSLATION; }
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct DeleteDiscussionRequest {
@@ -726,6 +800,8 @@ pub fn router(header: &'static Parts) -> Router {
         .route("/getDiscussionAndReplies", post(get_discussion))
         .route("/updateDiscussion", post(update_discussion))
         .route("/updateDiscussionReply", post(update_reply))
+        .route("/deleteDiscussionTranslation", post(delete_discussion_translation))
+        .route("/deleteDiscussionReplyTranslation", post(delete_reply_translation))
         .route("/deleteDiscussion", post(delete_discussion))
         .route("/deleteDiscussionReply", post(delete_reply))
 }
@@ -9,6 +9,7 @@ use http::{HeaderValue, StatusCode};
 use crate::{
     libs::{
         auth::Session_,
+        avatar,
         constants::{X_ACCEL_KITSUNE, X_ACCEL_REDIRECT},
         db::{DBResult, get_connection},
         privilege,
@@ -69,3 +70,23 @@ pub async fn submission(
     );
     res
 }
+
+/// `local:<hash>` avatars, public and immutable.
+pub async fn avatar(params: RawPathParams) -> Response {
+    let Some((deref!("hash"), hash)) = params.into_iter().next() else { return (StatusCode::INTERNAL_SERVER_ERROR, "Invalid route (proxy).").into_response() };
+    if !avatar::is_hash(hash) { return StatusCode::NOT_FOUND.into_response() }
+
+    let redirect = format!("{}/{}", avatar::REDIRECT, avatar::path(hash));
+    let kitsune = format!("/lean/avatar/{hash}");
+
+    let mut res = Response::new(Body::empty());
+    res.headers_mut().insert(
+        X_ACCEL_REDIRECT,
+        unsafe { HeaderValue::from_maybe_shared_unchecked(Bytes::from(redirect)) },
+    );
+    res.headers_mut().insert(
+        X_ACCEL_KITSUNE,
+        unsafe { HeaderValue::from_maybe_shared_unchecked(Bytes::from(kitsune)) },
+    );
+    res
+}
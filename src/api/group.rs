@@ -6,19 +6,23 @@ use axum::{
 use compact_str::CompactString;
 use http::{StatusCode, response::Parts};
 use serde::{Deserialize, Serializer, ser::SerializeSeq};
-use serde_json::ser::Serializer as JSerializer;
+use serde_json::{Value, json, ser::Serializer as JSerializer};
 use tokio_postgres::Client;
+use tower_sessions_core::Session;
 
 use crate::{
     bad, exs,
     libs::{
+        audit,
         auth::Session_,
         constants::{BYTES_EMPTY, BYTES_NULL},
         db::{DBError, DBResult, get_connection},
         lquery::𝑒𝑠𝑐𝑎𝑝𝑒_𝚕𝚊𝚣𝚢,
+        privilege,
         request::{JsonReqult, Repult},
         response::JkmxJsonResponse,
         serde::WithJson,
+        session,
         validate::{check_groupname, is_admin_group, is_system_group},
     },
     models::{
@@ -110,6 +114,7 @@ async fn create_group(
     let n = txn.execute(&stmt_link, &[&&*user.uid, &&*group_name]).await?;
     if n != 1 { return private::err(); }
     txn.commit().await?;
+    audit::log(&user.uid, "group.create", None, audit::Object::Group(&group_name), Value::Null, &mut conn).await;
 
     let res = format!(r#"{{"groupId":"{group_name}"}}"#);
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
@@ -138,6 +143,7 @@ async fn delete_group(
     let stmt = conn.prepare_static(SQL.into()).await?;
     let n = conn.execute(&stmt, &[&&*group_id]).await?;
     if n != 1 { return private::err(); }
+    audit::log(&s_user.uid, "group.delete", None, audit::Object::Group(&group_id), Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -166,6 +172,7 @@ async fn rename_group(
     let stmt = conn.prepare_static(SQL.into()).await?;
     let n = conn.execute(&stmt, &[&&*name, &&*group_id]).await?;
     if n != 1 { return private::err(); }
+    audit::log(&s_user.uid, "group.rename", None, audit::Object::Group(&name), Value::from(&*group_id), &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -198,6 +205,7 @@ async fn add_member(
     let n = txn.execute(&stmt_update, &[&&*group_id]).await?;
     if n != 1 { return private::err(); }
     txn.commit().await?;
+    audit::log(&s_user.uid, "group.addMember", Some(&*user_id), audit::Object::Group(&group_id), Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -223,6 +231,7 @@ async fn remove_member(
     let n = txn.execute(&stmt_update, &[&&*group_id]).await?;
     if n != 1 { return private::err(); }
     txn.commit().await?;
+    audit::log(&s_user.uid, "group.removeMember", Some(&*user_id), audit::Object::Group(&group_id), Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -251,6 +260,66 @@ async fn set_group_admin(
     let stmt_set_admin = conn.prepare_static(SQL_SET_ADMIN.into()).await?;
     let n = conn.execute(&stmt_set_admin, &[&is_group_admin, &&*user_id, &&*group_id]).await?;
     if n != 1 { return private::err(); }
+    audit::log(&s_user.uid, "group.setAdmin", Some(&*t_user.uid), audit::Object::Group(&group_id), Value::Bool(is_group_admin), &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetGroupStorageQuotaRequest {
+    group_id: CompactString,
+    quota: Option<u64>,
+}
+
+async fn set_group_storage_quota(
+    Session_(session): Session_,
+    req: JsonReqult<SetGroupStorageQuotaRequest>,
+) -> JkmxJsonResponse {
+    const SQL: &str = "update lean4oj.groups set storage_quota = $1 where gid = $2";
+
+    let Json(SetGroupStorageQuotaRequest { group_id, quota }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    if !privilege::check(&user.uid, "Lean4OJ.ManageUserGroup", &mut conn).await? { return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL); }
+
+    let quota = quota.map(|q| q.min(i64::MAX.cast_unsigned()).cast_signed());
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    let n = conn.execute(&stmt, &[&quota, &&*group_id]).await?;
+    if n != 1 { return JkmxJsonResponse::Response(StatusCode::NOT_FOUND, BYTES_NULL); }
+    audit::log(&user.uid, "group.setStorageQuota", None, audit::Object::Group(&group_id), Value::from(quota), &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetGroupRequireTotpRequest {
+    group_id: CompactString,
+    require_totp: bool,
+}
+
+/// Members of such a group must pass TOTP to log in. As it guards privileges, only `Admin` may change it.
+async fn set_group_require_totp(
+    Session_(session): Session_,
+    req: JsonReqult<SetGroupRequireTotpRequest>,
+) -> JkmxJsonResponse {
+    const SQL: &str = "update lean4oj.groups set require_totp = $1 where gid = $2";
+
+    let Json(SetGroupRequireTotpRequest { group_id, require_totp }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    if !privilege::check(&user.uid, "Lean4OJ.Admin", &mut conn).await? { return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL); }
+
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    let n = conn.execute(&stmt, &[&require_totp, &&*group_id]).await?;
+    if n != 1 { return JkmxJsonResponse::Response(StatusCode::NOT_FOUND, BYTES_NULL); }
+    // sessions from before passed no second factor; the one making the change stays.
+    let revoked = if require_totp { session::revoke_members(&group_id, session.as_ref().and_then(Session::id), &mut conn).await? } else { 0 };
+    let details = json!({ "requireTotp": require_totp, "revokedSessions": revoked });
+    audit::log(&user.uid, "group.setRequireTotp", None, audit::Object::Group(&group_id), details, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -301,6 +370,8 @@ pub fn router(_header: &'static Parts) -> Router {
         .route("/addMember", post(add_member))
         .route("/removeMember", post(remove_member))
         .route("/setGroupAdmin", post(set_group_admin))
+        .route("/setGroupStorageQuota", post(set_group_storage_quota))
+        .route("/setGroupRequireTotp", post(set_group_require_totp))
         .route("/getGroupList", get(get_group_list))
         .route("/getGroupMemberList", post(get_group_member_list))
 }
@@ -142,7 +142,7 @@ async fn get_homepage(
     ).await?;
 
     let mut announcements = Discussion::by_ids(ANNOUNCEMENT_IDS.into_iter(), &mut conn).await?;
-    for d in &mut announcements { d.backdoor(locale.as_deref()); }
+    for d in &mut announcements { d.localize(locale.as_deref()); }
     let links = links::friend_links(locale.as_deref());
     let mut latest_updated_problems = get_latest_updated_problems(locale.as_deref(), &mut conn).await?;
 
@@ -0,0 +1,114 @@
+use axum::{
+    Json, Router,
+    response::{IntoResponse, Response, Sse},
+    routing::{get, post},
+};
+use http::{StatusCode, response::Parts};
+use serde::Deserialize;
+
+use crate::{
+    exs,
+    libs::{
+        auth::Session_,
+        constants::BYTES_NULL,
+        db::get_connection,
+        request::JsonReqult,
+        response::JkmxJsonResponse,
+        serde::WithJson,
+    },
+    models::{notification::Notification, user::User},
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListNotificationsRequest {
+    /// `id` of the last one of the previous page.
+    before: Option<u64>,
+    #[serde(default)]
+    unread_only: bool,
+    take_count: u64,
+}
+
+async fn list_notifications(
+    Session_(session): Session_,
+    req: JsonReqult<ListNotificationsRequest>,
+) -> JkmxJsonResponse {
+    let Json(ListNotificationsRequest { before, unread_only, take_count }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    let notifications = Notification::list(&user.uid, before, unread_only, take_count.min(100).cast_signed(), &mut conn).await?;
+    let unread_count = Notification::unread_count(&user.uid, &mut conn).await?;
+
+    let res = format!(r#"{{"notifications":{},"unreadCount":{unread_count}}}"#, WithJson(notifications));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+async fn get_unread_count(Session_(session): Session_) -> JkmxJsonResponse {
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    let unread_count = Notification::unread_count(&user.uid, &mut conn).await?;
+
+    JkmxJsonResponse::Response(StatusCode::OK, format!(r#"{{"unreadCount":{unread_count}}}"#).into())
+}
+
+#[derive(Deserialize)]
+struct MarkReadRequest {
+    /// All of them if omitted.
+    ids: Option<Vec<u64>>,
+}
+
+async fn mark_read(
+    Session_(session): Session_,
+    req: JsonReqult<MarkReadRequest>,
+) -> JkmxJsonResponse {
+    let Json(MarkReadRequest { ids }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    Notification::mark_read(&user.uid, ids.as_deref(), &mut conn).await?;
+    let unread_count = Notification::unread_count(&user.uid, &mut conn).await?;
+
+    JkmxJsonResponse::Response(StatusCode::OK, format!(r#"{{"unreadCount":{unread_count}}}"#).into())
+}
+
+/// Only what comes after the connection is made; anything missed in between is in `listNotifications`.
+async fn subscribe(Session_(session): Session_) -> Response {
+    let mut conn = match get_connection().await {
+        Ok(conn) => conn,
+        Err(err) => return JkmxJsonResponse::Error(StatusCode::INTERNAL_SERVER_ERROR, err.into()).into_response(),
+    };
+    let user = match User::from_maybe_session(&session, &mut conn).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL).into_response(),
+        Err(err) => return JkmxJsonResponse::Error(StatusCode::INTERNAL_SERVER_ERROR, err.into()).into_response(),
+    };
+    drop(conn);
+
+    Sse::new(Notification::subscribe(user.uid)).into_response()
+}
+
+pub fn router(_header: &'static Parts) -> Router {
+    Router::new()
+        .route("/listNotifications", post(list_notifications))
+        .route("/getUnreadCount", post(get_unread_count))
+        .route("/markRead", post(mark_read))
+
+        .route("/subscribe", get(subscribe))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MarkReadRequest;
+
+    #[test]
+    fn mark_read_ids() {
+        let all = serde_json::from_str::<MarkReadRequest>("{}").unwrap();
+        assert!(all.ids.is_none());
+        let none = serde_json::from_str::<MarkReadRequest>(r#"{"ids":[]}"#).unwrap();
+        assert_eq!(none.ids.as_deref(), Some(&[][..]));
+        let some = serde_json::from_str::<MarkReadRequest>(r#"{"ids":[3,1]}"#).unwrap();
+        assert_eq!(some.ids.as_deref(), Some(&[3, 1][..]));
+        assert!(serde_json::from_str::<MarkReadRequest>(r#"{"ids":[-1]}"#).is_err());
+    }
+}
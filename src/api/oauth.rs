@@ -0,0 +1,206 @@
+use axum::{
+    Json, Router,
+    routing::{get, post},
+};
+use bytes::Bytes;
+use compact_str::CompactString;
+use http::{HeaderMap, StatusCode, response::Parts};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio_postgres::Client;
+
+use super::auth::{finish_login, session_target};
+use crate::{
+    exs,
+    libs::{
+        audit,
+        auth::Session_,
+        constants::{BYTES_EMPTY, BYTES_NULL},
+        db::{DBResult, get_connection},
+        error::BoxedStdError,
+        oauth::{self, Identity, Login, Pending, Preset, Provider},
+        request::JsonReqult,
+        response::JkmxJsonResponse,
+        serde::WithJson,
+    },
+    models::{oauth_link::OAuthLink, user::User},
+};
+
+const NO_SUCH_PROVIDER: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NO_SUCH_PROVIDER"}"#));
+const PROVIDER_ERROR: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"PROVIDER_ERROR"}"#));
+const INVALID_STATE: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"INVALID_STATE"}"#));
+const NOT_LINKED: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NOT_LINKED"}"#));
+const ALREADY_LINKED: JkmxJsonResponse = JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"ALREADY_LINKED"}"#));
+
+fn provider_error(provider: &str, e: BoxedStdError) -> JkmxJsonResponse {
+    tracing::warn!(target: "oauth", "{provider}: {e}");
+    PROVIDER_ERROR
+}
+
+#[derive(Serialize)]
+struct ProviderA {
+    id: &'static str,
+    name: &'static str,
+    preset: Option<Preset>,
+}
+
+async fn list_providers() -> JkmxJsonResponse {
+    let providers = oauth::providers().iter().map(|p| ProviderA {
+        id: &p.id,
+        name: &p.name,
+        preset: p.preset,
+    }).collect::<Vec<_>>();
+
+    let res = format!(r#"{{"providers":{}}}"#, WithJson(providers));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+#[derive(Deserialize)]
+struct AuthorizeRequest {
+    provider: CompactString,
+    /// Link the account to the signed-in user instead of logging in with it.
+    #[serde(default)]
+    link: bool,
+}
+
+async fn authorize(Session_(session): Session_, req: JsonReqult<AuthorizeRequest>) -> JkmxJsonResponse {
+    let Json(AuthorizeRequest { provider, link }) = req?;
+
+    let Some(provider) = oauth::provider(&provider) else { return NO_SUCH_PROVIDER };
+    let mut conn = get_connection().await?;
+    let link = if link {
+        exs!(user, &session, &mut conn);
+        Some(user.uid)
+    } else {
+        None
+    };
+
+    let (url, pending) = match oauth::start(provider, link).await {
+        Ok(t) => t,
+        Err(e) => return provider_error(&provider.id, e),
+    };
+    pending.save(&mut conn).await?;
+    let res = format!(r#"{{"url":{}}}"#, WithJson(url));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+/// Only when both sides have verified the address, or anyone could take over an account by claiming its email. Accounts
+/// older than email verification are merely taken as verified, so they have to link by hand.
+async fn user_by_email(identity: &Identity, conn: &mut Client) -> DBResult<Option<User>> {
+    let Some(email) = identity.email.as_deref().filter(|_| identity.email_verified) else { return Ok(None) };
+    let Some(user) = User::by_email(email, conn).await? else { return Ok(None) };
+    Ok(user.is_email_confirmed(conn).await?.then_some(user))
+}
+
+/// Fills in the GitHub handle of the profile, if it is empty.
+async fn fill_github(uid: &str, provider: &Provider, identity: &Identity, conn: &mut Client) -> DBResult<()> {
+    const SQL: &str = "update lean4oj.user_information set github = $2 where uid = $1 and github = ''";
+
+    if provider.preset == Some(Preset::Github)
+    && let Some(username) = identity.username.as_deref().filter(|u| u.len() <= 30) {
+        let stmt = conn.prepare_static(SQL.into()).await?;
+        conn.execute(&stmt, &[&uid, &username]).await?;
+    }
+    Ok(())
+}
+
+async fn link(actor: &str, uid: &str, Login { provider, identity, .. }: &Login, conn: &mut Client) -> DBResult<bool> {
+    if !OAuthLink::link(uid, &provider.id, &identity.subject, identity.email.as_deref(), conn).await? {
+        return Ok(false);
+    }
+    fill_github(uid, provider, identity, conn).await?;
+    let details = json!({ "provider": provider.id, "subject": identity.subject });
+    audit::log(actor, "auth.linkOAuth", Some(uid), audit::Object::None, details, conn).await;
+    Ok(true)
+}
+
+#[derive(Deserialize)]
+struct CallbackRequest {
+    state: CompactString,
+    code: String,
+}
+
+/// Where the provider sends the user back to. Logs in with a linked account (or one linked here by email), or
+/// completes linking.
+async fn callback(Session_(session): Session_, headers: HeaderMap, req: JsonReqult<CallbackRequest>) -> JkmxJsonResponse {
+    let Json(CallbackRequest { state, code }) = req?;
+
+    let mut conn = get_connection().await?;
+    let Some(pending) = Pending::take(&state, &mut conn).await? else { return INVALID_STATE };
+    let login = match oauth::complete(pending, &code).await {
+        Ok(login) => login,
+        Err(e) => return provider_error("callback", e),
+    };
+    let provider = login.provider;
+
+    if let Some(uid) = &login.link {
+        // the same user must finish the link, lest they be tricked into linking their account to someone else.
+        exs!(user, &session, &mut conn);
+        if user.uid != *uid { return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL) }
+        if !link(uid, uid, &login, &mut conn).await? { return ALREADY_LINKED }
+        return JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY);
+    }
+
+    let uid = match OAuthLink::login(&provider.id, &login.identity.subject, &mut conn).await? {
+        Some(uid) => uid,
+        None => {
+            let Some(user) = user_by_email(&login.identity, &mut conn).await? else { return NOT_LINKED };
+            if !link(&user.uid, &user.uid, &login, &mut conn).await? { return ALREADY_LINKED }
+            user.uid.into_string()
+        }
+    };
+    let Some(user) = User::by_uid(&uid, &mut conn).await? else { return NOT_LINKED };
+    finish_login(&user.uid, &user.username, Value::from(format!("oauth:{}", provider.id)), &headers, &mut conn).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListLinksRequest {
+    user_id: CompactString,
+}
+
+async fn list_links(Session_(session): Session_, req: JsonReqult<ListLinksRequest>) -> JkmxJsonResponse {
+    let Json(ListLinksRequest { user_id }) = req?;
+
+    let mut conn = get_connection().await?;
+    let (_, user) = match session_target(session.as_ref(), &user_id, &mut conn).await? {
+        Ok(t) => t,
+        Err(res) => return res,
+    };
+
+    let links = OAuthLink::list(&user.uid, &mut conn).await?;
+    let res = format!(r#"{{"links":{}}}"#, WithJson(links));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnlinkRequest {
+    user_id: CompactString,
+    provider: CompactString,
+}
+
+async fn unlink(Session_(session): Session_, req: JsonReqult<UnlinkRequest>) -> JkmxJsonResponse {
+    let Json(UnlinkRequest { user_id, provider }) = req?;
+
+    let mut conn = get_connection().await?;
+    let (s_user, user) = match session_target(session.as_ref(), &user_id, &mut conn).await? {
+        Ok(t) => t,
+        Err(res) => return res,
+    };
+
+    if !OAuthLink::unlink(&user.uid, &provider, &mut conn).await? { return NOT_LINKED }
+    let details = json!({ "provider": provider });
+    audit::log(&s_user.uid, "auth.unlinkOAuth", Some(&*user.uid), audit::Object::None, details, &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+pub fn router(_header: &'static Parts) -> Router {
+    Router::new()
+        .route("/listProviders", get(list_providers))
+        .route("/authorize", post(authorize))
+        .route("/callback", post(callback))
+        .route("/listLinks", post(list_links))
+        .route("/unlink", post(unlink))
+}
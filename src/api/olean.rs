@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, Permissions},
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::Query,
+    routing::post,
+};
+use bytes::Bytes;
+use compact_str::CompactString;
+use hashbrown::HashSet;
+use http::{StatusCode, response::Parts};
+use serde::{Deserialize, Serialize};
+use tempfile::{Builder, TempPath};
+use tokio::task::spawn_blocking;
+
+use crate::{
+    bad, exs,
+    libs::{
+        auth::Session_,
+        constants::BYTES_NULL,
+        db::get_connection,
+        fs::{do_delete, mkdir, readdir_from_rawfd, size_at},
+        olean::{self, SINGLE_FILE_LIMIT, TOTAL_FILE_NUM, check_path},
+        quota,
+        request::{JsonReqult, Repult},
+        response::JkmxJsonResponse,
+        serde::{JsMaybeTime, WithJson},
+        tar,
+        validate::is_lean_id,
+    },
+};
+
+/// Siblings of `Foo.olean` that belong to module `Foo`.
+const SIBLINGS: [&str; 4] = [".olean", ".olean.private", ".olean.server", ".ir"];
+
+const SUSPENDED: JkmxJsonResponse = JkmxJsonResponse::Response(
+    StatusCode::OK,
+    Bytes::from_static(br#"{"error":"SUSPENDED"}"#),
+);
+
+#[derive(Deserialize)]
+struct UploadRequest {
+    #[serde(default)]
+    delete: bool,
+}
+
+/// Body is a tar of `.lake/build/lib/lean` (or of `<uid>/` directly), validated the same way as the rsync receiver.
+/// Everything is staged first, so a rejected archive leaves the tree untouched.
+async fn upload(
+    Session_(session): Session_,
+    req: Repult<Query<UploadRequest>>,
+    body: Body,
+) -> JkmxJsonResponse {
+    let Query(UploadRequest { delete }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    if user.is_suspended(&mut conn).await? { return SUSPENDED; }
+
+    let limit = quota::limit(&user.uid, &mut conn).await?;
+    let used = quota::used(&user.uid, &mut conn).await?;
+    drop(conn);
+
+    let mut uid_with_slash = String::with_capacity(user.uid.len() + 2);
+    uid_with_slash.push('/');
+    uid_with_slash.push_str(&user.uid);
+    uid_with_slash.push('/');
+
+    /******** staging ********/
+    let mut reader = tar::Reader::new(body);
+    let mut staged = Vec::<(Vec<u8>, TempPath)>::new();
+    let mut acc = 0;
+    let mut ignored = 0usize;
+    while let Some(tar::Entry { mut path, size, regular }) = reader.next().await? {
+        let t = if regular && size <= SINGLE_FILE_LIMIT { check_path(&path, &uid_with_slash) } else { 0 };
+        if t == 0 {
+            reader.skip_data(size).await?;
+            ignored += usize::from(regular);
+            continue;
+        }
+        acc += size;
+        if acc > limit { return JkmxJsonResponse::Error(StatusCode::PAYLOAD_TOO_LARGE, quota::exceeded(limit).into()); }
+        if staged.len() >= TOTAL_FILE_NUM { return JkmxJsonResponse::Error(StatusCode::BAD_REQUEST, "too many files".into()); }
+
+        let data = reader.data(size).await?;
+        if olean::lean_version(&data).is_none() {
+            let msg = format!("{}: not a valid olean file", String::from_utf8_lossy(&path));
+            return JkmxJsonResponse::Error(StatusCode::BAD_REQUEST, msg.into());
+        }
+        let temp = spawn_blocking(move || stage(&data)).await??;
+        path.drain(..t);
+        path.push(0); // make it NUL-terminated to be friendly with C.
+        staged.push((path, temp));
+    }
+
+    /******** commit ********/
+    let received = staged.len();
+    let uid = user.uid.clone();
+    let Some(purged) = spawn_blocking(move || commit(&uid, &mut staged, delete, acc, used, limit)).await?? else {
+        return JkmxJsonResponse::Error(StatusCode::PAYLOAD_TOO_LARGE, quota::exceeded(limit).into());
+    };
+
+    let mut conn = get_connection().await?;
+    quota::refresh(&user.uid, &mut conn).await?;
+
+    let res = format!(r#"{{"received":{received},"ignored":{ignored},"purged":{purged}}}"#);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+fn stage(data: &[u8]) -> io::Result<TempPath> {
+    let mut tb = Builder::new();
+    tb.permissions(Permissions::from_mode(0o666));
+    let mut f = tb.tempfile_in(env!("LEAN4OJ_RSYNC_TMPDIR"))?;
+    f.write_all(data)?;
+    Ok(f.into_temp_path())
+}
+
+/// Moves the staged files into place, returns how many files were purged, or `None` if the result would exceed `limit`.
+/// `used` is the recorded usage, of which the files about to be replaced no longer count.
+fn commit(uid: &str, staged: &mut [(Vec<u8>, TempPath)], delete: bool, acc: usize, used: usize, limit: usize) -> io::Result<Option<usize>> {
+    let mut buf = String::with_capacity(env!("OLEAN_ROOT").len() + uid.len() + 7);
+    buf.push_str(env!("OLEAN_ROOT"));
+    buf.push_str("/lean/");
+    buf.push_str(uid);
+    buf.push('\0');
+    if unsafe { libc::mkdir(buf.as_ptr().cast(), 0o770) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EEXIST) { return Err(err); }
+    }
+    let base_dir_fd = unsafe { libc::open(buf.as_ptr().cast(), libc::O_DIRECTORY) };
+    if base_dir_fd == -1 { return Err(io::Error::last_os_error()); }
+
+    // This fd is now managed by 🌰!
+    let mut chestnut = readdir_from_rawfd(base_dir_fd)?;
+
+    let purged = {
+        let exempt = staged.iter()
+            .map(|(target, _)| unsafe { target.get_unchecked(..target.len() - 1) })
+            .collect::<HashSet<_>>();
+        if delete {
+            do_delete(&mut PathBuf::new(), &exempt, base_dir_fd, true, &mut chestnut)?.0
+        } else {
+            let kept = used.saturating_sub(size_at(base_dir_fd, exempt.iter().copied())?);
+            if acc + kept > limit { return Ok(None); }
+            0
+        }
+    };
+
+    for (target, temp) in staged {
+        mkdir(target, base_dir_fd)?;
+        let mut src = temp.as_os_str().as_encoded_bytes().to_vec();
+        src.push(0);
+        if unsafe { libc::renameat(libc::AT_FDCWD, src.as_ptr().cast(), base_dir_fd, target.as_ptr().cast()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    drop(chestnut);
+
+    Ok(Some(purged))
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ModuleNode {
+    /// Of the `.olean` and its siblings only, submodules not included.
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lean_version: Option<CompactString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_module: Option<bool>,
+    #[serde(serialize_with = "JsMaybeTime", skip_serializing_if = "Option::is_none")]
+    upload_time: Option<SystemTime>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    children: BTreeMap<CompactString, ModuleNode>,
+}
+
+fn scan(dir: &Path, node: &mut ModuleNode) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let type_ = entry.file_type()?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        if type_.is_dir() {
+            scan(&entry.path(), node.children.entry(name.into()).or_default())?;
+        } else if type_.is_file() {
+            // lean identifiers never contain a dot.
+            let Some((stem, ext)) = name.split_once('.') else { continue };
+            let child = node.children.entry(stem.into()).or_default();
+            let meta = entry.metadata()?;
+            child.size += meta.len();
+            if ext == "olean" {
+                child.upload_time = meta.modified().ok();
+                let data = fs::read(entry.path())?;
+                if let Some(meta) = olean::parse_meta(&data) {
+                    child.lean_version = Some(compact_str::format_compact!("4{}", meta.version));
+                    child.is_module = Some(meta.is_module());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn list(Session_(session): Session_) -> JkmxJsonResponse {
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    drop(conn);
+
+    let root = PathBuf::from(format!("{}/lean/{}", env!("OLEAN_ROOT"), user.uid));
+    let tree = spawn_blocking(move || {
+        let mut tree = ModuleNode::default();
+        match scan(&root, &mut tree) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(tree.children),
+        }
+    }).await??;
+
+    let res = format!(r#"{{"modules":{}}}"#, WithJson(tree));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+/// Returns the number of files removed.
+fn remove_tree(path: &Path) -> io::Result<usize> {
+    let mut n = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            n += remove_tree(&entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+            n += 1;
+        }
+    }
+    fs::remove_dir(path)?;
+    Ok(n)
+}
+
+/// Removes module `name` (e.g. `Foo.Bar`) with its siblings and submodules, then the directories left empty.
+fn remove_module(root: &Path, name: &str) -> io::Result<usize> {
+    let mut path = root.to_path_buf();
+    path.extend(name.split('.'));
+    let mut n = 0;
+    for ext in SIBLINGS {
+        let mut file = path.clone().into_os_string();
+        file.push(ext);
+        match fs::remove_file(file) {
+            Ok(()) => n += 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+    }
+    match remove_tree(&path) {
+        Ok(m) => n += m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+    while path.pop() && path != root && fs::remove_dir(&path).is_ok() {}
+    Ok(n)
+}
+
+#[derive(Deserialize)]
+struct DeleteRequest {
+    modules: Vec<CompactString>,
+}
+
+async fn delete(
+    Session_(session): Session_,
+    req: JsonReqult<DeleteRequest>,
+) -> JkmxJsonResponse {
+    let Json(DeleteRequest { modules }) = req?;
+
+    if !modules.iter().all(|m| m.split('.').all(is_lean_id)) { bad!(BYTES_NULL) }
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    if user.is_suspended(&mut conn).await? { return SUSPENDED; }
+
+    let root = PathBuf::from(format!("{}/lean/{}", env!("OLEAN_ROOT"), user.uid));
+    let deleted = spawn_blocking(move || {
+        modules.iter().try_fold(0, |n, m| Ok::<_, io::Error>(n + remove_module(&root, m)?))
+    }).await??;
+
+    quota::refresh(&user.uid, &mut conn).await?;
+
+    let res = format!(r#"{{"deleted":{deleted}}}"#);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+pub fn router(_header: &'static Parts) -> Router {
+    Router::new()
+        .route("/upload", post(upload))
+        .route("/list", post(list))
+        .route("/delete", post(delete))
+}
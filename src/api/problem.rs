@@ -7,13 +7,14 @@ use compact_str::CompactString;
 use hashbrown::HashMap;
 use http::{StatusCode, response::Parts};
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{Value, json};
 use smallvec::SmallVec;
 use tokio_postgres::types::{Json as QJson, ToSql};
 
 use crate::{
     bad, exs,
     libs::{
+        audit,
         auth::Session_,
         constants::{BYTES_EMPTY, BYTES_NULL},
         db::{DBError, JsonChecked, get_connection},
@@ -25,6 +26,7 @@ use crate::{
     models::{
         discussion::Discussion,
         localedict::{LocaleDict, LocaleDictEntryFlatten, LocaleDictEntryOwnedFlatten},
+        notification::{self, notify},
         problem::{Problem, ProblemInner},
         submission::{Submission, SubmissionStatus},
         tag::{LTags, Tag},
@@ -506,7 +508,7 @@ async fn set_problem_publicness(
     Session_(session): Session_,
     req: JsonReqult<SetProblemPublicnessRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_PUBLIC: &str = "update lean4oj.problems set is_public = true, public_at = $1 where pid = $2 and not is_public";
+    const SQL_PUBLIC: &str = "update lean4oj.problems set is_public = true, public_at = $1 where pid = $2 and not is_public returning owner";
     const SQL_PRIVATE: &str = "update lean4oj.problems set is_public = false where pid = $1 and is_public";
 
     let Json(SetProblemPublicnessRequest { problem_id, is_public }) = req?;
@@ -517,14 +519,21 @@ async fn set_problem_publicness(
         return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_EMPTY);
     }
 
-    let n = if is_public {
+    let owner = if is_public {
         let stmt = conn.prepare_static(SQL_PUBLIC.into()).await?;
-        conn.execute(&stmt, &[&now, &problem_id]).await
+        let Some(row) = conn.query_opt(&stmt, &[&now, &problem_id]).await? else { return private::err() };
+        Some(CompactString::from(row.try_get::<_, &str>(0)?))
     } else {
         let stmt = conn.prepare_static(SQL_PRIVATE.into()).await?;
-        conn.execute(&stmt, &[&problem_id]).await
-    }?;
-    if n != 1 { return private::err(); }
+        let n = conn.execute(&stmt, &[&problem_id]).await?;
+        if n != 1 { return private::err(); }
+        None
+    };
+    audit::log(&user.uid, "problem.setPublicness", None, audit::Object::Problem(problem_id), Value::Bool(is_public), &mut conn).await;
+    if let Some(owner) = owner && owner != user.uid {
+        let details = json!({ "problemId": problem_id, "userId": user.uid });
+        notify(&owner, notification::Kind::ProblemPublished, details, &mut conn).await;
+    }
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -557,6 +566,7 @@ async fn update_judge_info(
         conn.execute(&stmt, &[&QJson(judge_info), &submittable, &problem_id, &&*user.uid]).await
     }?;
     if n != 1 { return private::err(); }
+    audit::log(&user.uid, "problem.updateJudgeInfo", None, audit::Object::Problem(problem_id), Value::Bool(submittable), &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -587,6 +597,7 @@ async fn delete_problem(
         conn.execute(&stmt, &[&problem_id, &&*user.uid]).await
     }?;
     if n != 1 { return private::err(); }
+    audit::log(&user.uid, "problem.delete", None, audit::Object::Problem(problem_id), Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -1,7 +1,7 @@
 #![allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
 
 use core::{fmt::Write, future::ready, mem::MaybeUninit, str};
-use std::time::SystemTime;
+use std::{sync::LazyLock, time::SystemTime};
 
 use axum::{
     Extension, Json, Router,
@@ -12,21 +12,29 @@ use axum::{
 };
 use bytes::Bytes;
 use compact_str::CompactString;
+use dashmap::DashMap;
 use futures_util::TryStreamExt;
+use hashbrown::{DefaultHashBuilder, HashMap};
 use http::{StatusCode, header, response::Parts};
 use openssl::sha::Sha256;
 use serde::Deserialize;
+use serde_json::Value;
 use smallvec::SmallVec;
-use tokio_postgres::types::{Json as QJson, ToSql};
+use tokio_postgres::{
+    Client,
+    types::{Json as QJson, ToSql},
+};
 
 use crate::{
     bad, exs,
     libs::{
+        audit,
         auth::Session_,
         constants::{APPLICATION_JSON_UTF_8, BYTES_EMPTY, BYTES_NULL},
         db::{DBError, DBResult, ToSqlIter, get_connection},
+        error::BoxedStdError,
         judger::task::{LeanAxiom, Task},
-        olean, privilege,
+        lean_header, olean, password, privilege,
         request::{JsonReqult, Repult},
         response::JkmxJsonResponse,
         serde::WithJson,
@@ -44,6 +52,10 @@ use crate::{
     service::submission_deposit,
 };
 
+const EMAIL_NOT_VERIFIED: JkmxJsonResponse = JkmxJsonResponse::Response(
+    StatusCode::OK,
+    Bytes::from_static(br#"{"error":"EMAIL_NOT_VERIFIED"}"#),
+);
 const NO_SUCH_PROBLEM: JkmxJsonResponse = JkmxJsonResponse::Response(
     StatusCode::OK,
     Bytes::from_static(br#"{"error":"NO_SUCH_PROBLEM"}"#),
@@ -52,6 +64,10 @@ const NO_SUCH_SUBMISSION: JkmxJsonResponse = JkmxJsonResponse::Response(
     StatusCode::OK,
     Bytes::from_static(br#"{"error":"NO_SUCH_SUBMISSION"}"#),
 );
+const SUSPENDED: JkmxJsonResponse = JkmxJsonResponse::Response(
+    StatusCode::OK,
+    Bytes::from_static(br#"{"error":"SUSPENDED"}"#),
+);
 
 mod private {
     pub(super) fn err() -> super::JkmxJsonResponse {
@@ -119,6 +135,8 @@ async fn submit(
 
     let mut conn = get_connection().await?;
     exs!(user, &session, &mut conn);
+    if !user.is_email_verified(&mut conn).await? { return EMAIL_NOT_VERIFIED; }
+    if user.is_suspended(&mut conn).await? { return SUSPENDED; }
 
     let problem: Problem = if privilege::check(&user.uid, "Lean4OJ.ManageProblem", &mut conn).await? {
         let stmt = conn.prepare_static(SQL_SEL_PRIV.into()).await?;
@@ -160,6 +178,7 @@ async fn submit(
         version: meta.version,
         hash: answer_hash,
         checker: problem.jb,
+        source: false,
     };
     submission_deposit::transmit(task)?;
 
@@ -167,6 +186,141 @@ async fn submit(
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
 
+/// The row must already be `Compiling`, fall back to `Pending` (picked up by `judger__get__task`) if no judger is waiting.
+async fn dispatch_compile(sid: u32, version: &str, uid: &str, sources: &[CompactString], conn: &mut Client) -> DBResult<()> {
+    let task = submission_deposit::compile_task(sid, version, uid, sources);
+    if !submission_deposit::dispatch(task) {
+        Submission::report_status(sid, SubmissionStatus::Pending, SubmissionMessageAction::NoAction, conn).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SourceFile {
+    module_name: CompactString,
+    content: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Inner2 {
+    lean_version: CompactString,
+    module_name: CompactString,
+    const_name: CompactString,
+    sources: Vec<SourceFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmitSourceRequest {
+    problem_id: i32,
+    content: Inner2,
+}
+
+async fn submit_source(
+    Extension(now): Extension<SystemTime>,
+    Session_(session): Session_,
+    req: JsonReqult<SubmitSourceRequest>,
+) -> JkmxJsonResponse {
+    const SQL_SEL_PRIV: &str = "select * from lean4oj.problems where pid = $1 and submittable";
+    const SQL_SEL: &str = "select * from lean4oj.problems where pid = $1 and (owner = $2 or is_public) and submittable";
+    const SQL_CREATE: &str = "insert into lean4oj.submissions (pid, submitter, submit_time, module_name, const_name, lean_toolchain, status, answer_size, answer_hash, sources) values ($1, $2, $3, $4, $5, $6, '\x0c', $7, $8, $9) returning sid";
+    const SQL_ADD_SUB: &str = "update lean4oj.problems set sub = sub + 1 where pid = $1";
+    const SOURCE_FILE_NUM: usize = 32;
+    const SOURCE_SIZE_LIMIT: usize = 0x10_0000; // 1 MB
+
+    let Json(SubmitSourceRequest { problem_id, content: Inner2 { lean_version, module_name, const_name, sources } }) = req?;
+
+    if !module_name.split('.').all(is_lean_id) || !const_name.split('.').all(is_lean_id) { bad!(BYTES_NULL); }
+    let Some(version) = olean::acceptable_version(&lean_version) else { bad!(BYTES_NULL) };
+    let size = sources.iter().map(|s| s.content.len()).sum::<usize>();
+    if sources.is_empty() || sources.len() > SOURCE_FILE_NUM || size > SOURCE_SIZE_LIMIT { bad!(BYTES_NULL); }
+
+    let mut index = HashMap::<&str, usize>::with_capacity(sources.len());
+    for (i, source) in sources.iter().enumerate() {
+        if !source.module_name.split('.').all(is_lean_id) || index.insert(&source.module_name, i).is_some() { bad!(BYTES_NULL); }
+    }
+    if !index.contains_key(&*module_name) { bad!(BYTES_NULL); }
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    if !user.is_email_verified(&mut conn).await? { return EMAIL_NOT_VERIFIED; }
+    if user.is_suspended(&mut conn).await? { return SUSPENDED; }
+
+    let _: Problem = if privilege::check(&user.uid, "Lean4OJ.ManageProblem", &mut conn).await? {
+        let stmt = conn.prepare_static(SQL_SEL_PRIV.into()).await?;
+        conn.query_one(&stmt, &[&problem_id]).await
+    } else {
+        let stmt = conn.prepare_static(SQL_SEL.into()).await?;
+        conn.query_one(&stmt, &[&problem_id, &&*user.uid]).await
+    }?.try_into()?;
+
+    /******** topological order of local imports ********/
+    let mut degree = vec![0usize; sources.len()];
+    let mut dependents = vec![SmallVec::<[usize; 4]>::new(); sources.len()];
+    for (i, source) in sources.iter().enumerate() {
+        for import in lean_header::imports(&source.content) {
+            if let Some(local) = import.strip_prefix(&*user.uid) && let Some(local) = local.strip_prefix('.') {
+                let Some(&j) = index.get(local) else { bad!(BYTES_NULL) };
+                degree[i] += 1;
+                dependents[j].push(i);
+            } else if !olean::is_std(import) {
+                bad!(BYTES_NULL);
+            }
+        }
+    }
+    let mut order = (0..sources.len()).filter(|&i| degree[i] == 0).collect::<Vec<_>>();
+    let mut k = 0;
+    while k < order.len() {
+        let i = order[k];
+        k += 1;
+        for &j in &dependents[i] {
+            degree[j] -= 1;
+            if degree[j] == 0 { order.push(j); }
+        }
+    }
+    if order.len() != sources.len() { bad!(BYTES_NULL); } // cyclic imports
+
+    let mut sources = sources.into_iter().map(|s| Some((s.module_name, s.content))).collect::<Vec<_>>();
+    let sources = order.into_iter().map(|i| unsafe { sources.get_unchecked_mut(i).take().unwrap_unchecked() }).collect::<Vec<_>>();
+    let names = sources.iter().map(|(module, _)| module.clone()).collect::<Vec<_>>();
+
+    let mut sha256 = Sha256::new();
+    for (module, content) in &sources {
+        sha256.update(module.as_bytes());
+        sha256.update(b"\0");
+        sha256.update(content.as_bytes());
+        sha256.update(b"\0");
+    }
+    let answer_hash = sha256.finish();
+
+    // Nothing is left behind unless the sources are in place.
+    let stmt_create = conn.prepare_static(SQL_CREATE.into()).await?;
+    let stmt_add_sub = conn.prepare_static(SQL_ADD_SUB.into()).await?;
+    let txn = conn.transaction().await?;
+    let row = txn.query_one(&stmt_create, &[
+        &problem_id, &&*user.uid, &now,
+        &&*module_name, &&*const_name, &version,
+        &(size as i64), &answer_hash.as_slice(), &names.iter().map(|s| &**s).collect::<Vec<_>>(),
+    ]).await?;
+    let sid = row.try_get::<_, i32>(0)?.cast_unsigned();
+    let n = txn.execute(&stmt_add_sub, &[&problem_id]).await?;
+    if n != 1 { return private::err(); }
+
+    let uid = user.uid.clone();
+    tokio::task::spawn_blocking(move || {
+        submission_deposit::write_sources(sid, &uid, &sources)?;
+        submission_deposit::prepare_scratch(sid, &uid, sources.iter().map(|(module, _)| module))
+    }).await??;
+    txn.commit().await?;
+
+    dispatch_compile(sid, version, &user.uid, &names, &mut conn).await?;
+
+    let res = format!(r#"{{"submissionId":{sid}}}"#);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct QuerySubmissionRequest {
@@ -405,12 +559,9 @@ async fn rejudge_submission(
     Session_(session): Session_,
     req: JsonReqult<SingleSubmissionRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_PRIV: &str = "select sid, pid, submitter, submit_time, module_name, const_name, lean_toolchain, status, message, answer_size, answer_hash, answer_obj, is_public, public_at, owner, pcontent, sub, pac, submittable, jb from lean4oj.submissions natural join lean4oj.problems where sid = $1 and status::integer >= 7";
-    const SQL: &str = "select sid, pid, submitter, submit_time, module_name, const_name, lean_toolchain, status, message, answer_size, answer_hash, answer_obj, is_public, public_at, owner, pcontent, sub, pac, submittable, jb from lean4oj.submissions natural join lean4oj.problems where sid = $1 and status::integer >= 7 and owner = $2";
-    const SQL_REJUDGE: &str = "update lean4oj.submissions set lean_toolchain = $1, status = 0::\"char\", message = '', answer_size = $2, answer_hash = $3, answer_obj = '' where sid = $4";
-    const SQL_REJUDGE_FAIL: &str = "update lean4oj.submissions set status = '\x07', message = $1 where sid = $2";
-    const SQL_REDUCE_AC: &str = "update lean4oj.problems set pac = pac - 1 where pid = $1";
-    const SQL_USER_AC: &str = "update lean4oj.users set ac = (select count(distinct pid) from lean4oj.submissions where submitter = $1 and status = '\x09') where uid = $1";
+    const SQL_PRIV: &str = "select sid, pid, submitter, submit_time, module_name, const_name, lean_toolchain, status, message, answer_size, answer_hash, answer_obj, sources, is_public, public_at, owner, pcontent, sub, pac, submittable, jb from lean4oj.submissions natural join lean4oj.problems where sid = $1 and status = any($2)";
+    const SQL: &str = "select sid, pid, submitter, submit_time, module_name, const_name, lean_toolchain, status, message, answer_size, answer_hash, answer_obj, sources, is_public, public_at, owner, pcontent, sub, pac, submittable, jb from lean4oj.submissions natural join lean4oj.problems where sid = $1 and status = any($2) and owner = $3";
+    const SQL_REJUDGE: &str = "update lean4oj.submissions set lean_toolchain = $1, answer_size = $2, answer_hash = $3 where sid = $4";
 
     let Json(SingleSubmissionRequest { submission_id }) = req?;
 
@@ -419,17 +570,32 @@ async fn rejudge_submission(
 
     let row = if privilege::check(&user.uid, "Lean4OJ.ManageProblem", &mut conn).await? {
         let stmt = conn.prepare_static(SQL_PRIV.into()).await?;
-        conn.query_opt(&stmt, &[&submission_id.cast_signed()]).await
+        conn.query_opt(&stmt, &[&submission_id.cast_signed(), &SubmissionStatus::FINAL.as_slice()]).await
     } else {
         let stmt = conn.prepare_static(SQL.into()).await?;
-        conn.query_opt(&stmt, &[&submission_id.cast_signed(), &&*user.uid]).await
+        conn.query_opt(&stmt, &[&submission_id.cast_signed(), &SubmissionStatus::FINAL.as_slice(), &&*user.uid]).await
     }?;
-    let (submission, problem) = match row {
-        Some(row) => (Submission::try_from(row.clone())?, Problem::try_from(row)?),
+    let (submission, problem, sources) = match row {
+        Some(row) => {
+            let sources = row.try_get::<_, Option<Vec<&str>>>("sources")?
+                .map(|v| v.into_iter().map(CompactString::from).collect::<Vec<_>>());
+            (Submission::try_from(row.clone())?, Problem::try_from(row)?, sources)
+        }
         None => return NO_SUCH_SUBMISSION,
     };
-
-    let is_ac = submission.status == SubmissionStatus::Accepted;
+    let submitter = submission.submitter.clone();
+
+    // `report_status` takes care of the accepted counts and tells subscribers.
+    /******** source submission: compile again ********/
+    if let Some(sources) = sources {
+        Submission::report_answer(submission_id, CompactString::default(), &mut conn).await?;
+        Submission::report_status(submission_id, SubmissionStatus::Compiling, SubmissionMessageAction::Replace("".into()), &mut conn).await?;
+        let (uid, modules) = (submission.submitter.clone(), sources.clone());
+        tokio::task::spawn_blocking(move || submission_deposit::prepare_scratch(submission_id, &uid, modules)).await??;
+        dispatch_compile(submission_id, &submission.lean_toolchain, &submission.submitter, &sources, &mut conn).await?;
+        audit::log(&user.uid, "submission.rejudge", Some(&*submitter), audit::Object::Submission(submission_id), Value::Null, &mut conn).await;
+        return JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY);
+    }
 
     /******** re-fetch files ********/
     let olean_path = olean::𝑔𝑒𝑡_𝑜𝑙𝑒𝑎𝑛_𝑝𝑎𝑡ℎ(&submission.submitter, &submission.module_name);
@@ -445,15 +611,8 @@ async fn rejudge_submission(
         (olean, version, is_module, imports)
     };
     let Some((olean, version, is_module, imports)) = w else {
-        let stmt = conn.prepare_static(SQL_REJUDGE_FAIL.into()).await?;
-        let n = conn.execute(&stmt, &[&"Rejudge fail.", &submission_id.cast_signed()]).await?;
-        if n != 1 { return private::err(); }
-        if is_ac {
-            let stmt_reduce_ac = conn.prepare_static(SQL_REDUCE_AC.into()).await?;
-            conn.execute(&stmt_reduce_ac, &[&submission.pid]).await?;
-            let stmt_user_ac = conn.prepare_static(SQL_USER_AC.into()).await?;
-            conn.execute(&stmt_user_ac, &[&&*submission.submitter]).await?;
-        }
+        Submission::report_status(submission_id, SubmissionStatus::InvalidImport, SubmissionMessageAction::Replace("Rejudge fail.".into()), &mut conn).await?;
+        audit::log(&user.uid, "submission.rejudge", Some(&*submitter), audit::Object::Submission(submission_id), Value::Null, &mut conn).await;
         return JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY);
     };
 
@@ -471,6 +630,7 @@ async fn rejudge_submission(
         version,
         hash: answer_hash,
         checker: problem.jb,
+        source: false,
     };
 
     let mut path0 = String::with_capacity(env!("OLEAN_ROOT").len() + 24);
@@ -485,14 +645,11 @@ async fn rejudge_submission(
         &version, &(olean.len() as i64), &answer_hash.as_slice(), &submission_id.cast_signed(),
     ]).await?;
     if n != 1 { return private::err(); }
-    if is_ac {
-        let stmt_reduce_ac = conn.prepare_static(SQL_REDUCE_AC.into()).await?;
-        conn.execute(&stmt_reduce_ac, &[&submission.pid]).await?;
-        let stmt_user_ac = conn.prepare_static(SQL_USER_AC.into()).await?;
-        conn.execute(&stmt_user_ac, &[&&*task.uid]).await?;
-    }
+    Submission::report_answer(submission_id, CompactString::default(), &mut conn).await?;
+    Submission::report_status(submission_id, SubmissionStatus::Pending, SubmissionMessageAction::Replace("".into()), &mut conn).await?;
 
     submission_deposit::transmit(task)?;
+    audit::log(&user.uid, "submission.rejudge", Some(&*submitter), audit::Object::Submission(submission_id), Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -501,7 +658,7 @@ async fn cancel_submission(
     Session_(session): Session_,
     req: JsonReqult<SingleSubmissionRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_CANCEL: &str = "update lean4oj.submissions set status = '\x0b' where sid = $1 and status::integer >= 7 returning old.status, pid, submitter";
+    const SQL_CANCEL: &str = "update lean4oj.submissions set status = '\x0b' where sid = $1 and status = any($2) returning old.status, pid, submitter";
     const SQL_REDUCE_AC: &str = "update lean4oj.problems set pac = pac - 1 where pid = $1";
     const SQL_USER_AC: &str = "update lean4oj.users set ac = (select count(distinct pid) from lean4oj.submissions where submitter = $1 and status = '\x09') where uid = $1";
 
@@ -514,16 +671,17 @@ async fn cancel_submission(
         return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_EMPTY);
     }
 
-    let row = conn.query_one(SQL_CANCEL, &[&submission_id.cast_signed()]).await?;
+    let row = conn.query_one(SQL_CANCEL, &[&submission_id.cast_signed(), &SubmissionStatus::FINAL.as_slice()]).await?;
     let status = row.try_get::<_, SubmissionStatus>(0)?;
+    let submitter = row.try_get::<_, &str>(2)?;
     if status == SubmissionStatus::Accepted {
         let pid = row.try_get::<_, i32>(1)?;
-        let submitter = row.try_get::<_, &str>(2)?;
         let stmt_reduce_ac = conn.prepare_static(SQL_REDUCE_AC.into()).await?;
         conn.execute(&stmt_reduce_ac, &[&pid]).await?;
         let stmt_user_ac = conn.prepare_static(SQL_USER_AC.into()).await?;
         conn.execute(&stmt_user_ac, &[&submitter]).await?;
     }
+    audit::log(&user.uid, "submission.cancel", Some(submitter), audit::Object::Submission(submission_id), Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -554,16 +712,17 @@ async fn delete_submission(
     let row = txn.query_one(&stmt_delete, &[&submission_id.cast_signed()]).await?;
     let status = row.try_get::<_, SubmissionStatus>(0)?;
     let pid = row.try_get::<_, i32>(1)?;
+    let submitter = row.try_get::<_, &str>(2)?;
     let n = if status == SubmissionStatus::Accepted {
         let n = txn.execute(&stmt_reduce_ac, &[&pid]).await?;
         if n != 1 { return private::err(); }
-        let submitter = row.try_get::<_, &str>(2)?;
         txn.execute(&stmt_user_ac, &[&submitter]).await
     } else {
         txn.execute(&stmt_reduce, &[&pid]).await
     }?;
     if n != 1 { return private::err(); }
     txn.commit().await?;
+    audit::log(&user.uid, "submission.delete", Some(submitter), audit::Object::Submission(submission_id), Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -618,6 +777,26 @@ async fn subscribe_submissions(
     Sse::new(st).into_response()
 }
 
+/// Judgers authenticate on every request, so credentials that passed are remembered (by digest) until the stored
+/// hash changes, instead of running the KDF each time.
+///
+/// There is no second factor here, so `Admin` only counts while that group does not require TOTP.
+async fn judger_auth(uid: &str, password: &str, conn: &mut Client) -> Result<bool, BoxedStdError> {
+    const SQL_AUTH: &str = "select password from lean4oj.users natural join lean4oj.user_groups join lean4oj.groups using (gid) where uid = $1 and (gid = 'Lean4OJ.Judger' or (gid = 'Lean4OJ.Admin' and not require_totp)) limit 1";
+    static VERIFIED: LazyLock<DashMap<CompactString, ([u8; 32], CompactString), DefaultHashBuilder>> = LazyLock::new(|| DashMap::with_hasher(DefaultHashBuilder::default()));
+
+    let stmt = conn.prepare_static(SQL_AUTH.into()).await?;
+    let Some(row) = conn.query_opt(&stmt, &[&uid]).await? else { return Ok(false) };
+    let stored = row.try_get::<_, &str>(0)?;
+
+    let digest = openssl::sha::sha256(password.as_bytes());
+    if VERIFIED.get(uid).is_some_and(|v| v.0 == digest && v.1 == stored) { return Ok(true); }
+    if !password::verify(uid, password, stored, conn).await? { return Ok(false); }
+    // a legacy row has just been rehashed, it will be re-verified once.
+    VERIFIED.insert(uid.into(), (digest, stored.into()));
+    Ok(true)
+}
+
 #[derive(Deserialize)]
 struct JudgerGetTaskRequest {
     uid: CompactString,
@@ -630,15 +809,12 @@ pub struct JbAxioms {
 }
 
 async fn judger_get_task_inner(req: JsonReqult<JudgerGetTaskRequest>) -> JkmxJsonResponse {
-    const SQL_AUTH: &str = "select from lean4oj.users natural join lean4oj.user_groups where uid = $1 and password = $2 and (gid = 'Lean4OJ.Admin' or gid = 'Lean4OJ.Judger') limit 1";
-    const SQL_TASK: &str = "select sid, lean_toolchain, jb from lean4oj.submissions natural join lean4oj.problems where status = '\x02' order by sid limit 1";
+    const SQL_TASK: &str = "select sid, lean_toolchain, jb, status, submitter, sources from lean4oj.submissions natural join lean4oj.problems where status = '\x02' or (status = '\x00' and sources is not null) order by sid limit 1";
 
     let Json(JudgerGetTaskRequest { uid, password }) = req?;
 
     let mut conn = get_connection().await?;
-    let stmt = conn.prepare_static(SQL_AUTH.into()).await?;
-    let n = conn.execute(&stmt, &[&&*uid, &&*password]).await?;
-    if n != 1 { return JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL); }
+    if !judger_auth(&uid, &password, &mut conn).await? { return JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL); }
 
     let stmt = conn.prepare_static(SQL_TASK.into()).await?;
     let Some(row) = conn.query_opt(&stmt, &[]).await? else {
@@ -649,6 +825,17 @@ async fn judger_get_task_inner(req: JsonReqult<JudgerGetTaskRequest>) -> JkmxJso
     };
     let sid = row.try_get::<_, i32>(0)?.cast_unsigned();
     let version_without_four = row.try_get::<_, &str>(1)?;
+
+    if row.try_get::<_, SubmissionStatus>(3)? == SubmissionStatus::Pending {
+        let submitter = row.try_get::<_, &str>(4)?;
+        let sources = row.try_get::<_, Vec<&str>>(5)?;
+        let (uid, modules) = (CompactString::from(submitter), sources.iter().copied().map(CompactString::from).collect::<Vec<_>>());
+        tokio::task::spawn_blocking(move || submission_deposit::prepare_scratch(sid, &uid, modules)).await??;
+        let res = submission_deposit::compile_task(sid, version_without_four, submitter, sources);
+        Submission::report_status(sid, SubmissionStatus::Compiling, SubmissionMessageAction::NoAction, &mut conn).await?;
+        return JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into());
+    }
+
     let QJson(JbAxioms { axioms }) = row.try_get(2)?;
     let mut version = CompactString::with_capacity(version_without_four.len() + 1);
     version.push('4');
@@ -658,7 +845,7 @@ async fn judger_get_task_inner(req: JsonReqult<JudgerGetTaskRequest>) -> JkmxJso
 
     Submission::report_status(sid, SubmissionStatus::JudgerReceived, SubmissionMessageAction::NoAction, &mut conn).await?;
 
-    let res = Task { sid, version, axioms };
+    let res = Task { sid, version, axioms, sources: Vec::new() };
     JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
 }
 
@@ -692,14 +879,11 @@ struct JudgerReportStatusRequest {
 async fn judger_report_status(
     req: JsonReqult<JudgerReportStatusRequest>,
 ) -> JkmxJsonResponse {
-    const SQL_AUTH: &str = "select from lean4oj.users natural join lean4oj.user_groups where uid = $1 and password = $2 and (gid = 'Lean4OJ.Admin' or gid = 'Lean4OJ.Judger') limit 1";
 
     let Json(JudgerReportStatusRequest { uid, password, sid, status, message, answer }) = req?;
 
     let mut conn = get_connection().await?;
-    let stmt = conn.prepare_static(SQL_AUTH.into()).await?;
-    let n = conn.execute(&stmt, &[&&*uid, &&*password]).await?;
-    if n != 1 { return JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL); }
+    if !judger_auth(&uid, &password, &mut conn).await? { return JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL); }
 
     Submission::report_status(sid, status, message, &mut conn).await?;
     if let Some(answer) = answer {
@@ -709,10 +893,70 @@ async fn judger_report_status(
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_NULL)
 }
 
+#[derive(Deserialize)]
+struct JudgerReportCompiledRequest {
+    uid: CompactString,
+    password: CompactString,
+    sid: u32,
+}
+
+async fn judger_report_compiled(
+    req: JsonReqult<JudgerReportCompiledRequest>,
+) -> JkmxJsonResponse {
+    const SQL: &str = "select sid, pid, submitter, submit_time, module_name, const_name, lean_toolchain, status, message, answer_size, answer_hash, answer_obj, is_public, public_at, owner, pcontent, sub, pac, submittable, jb from lean4oj.submissions natural join lean4oj.problems where sid = $1 and status = '\x0c'";
+
+    let Json(JudgerReportCompiledRequest { uid, password, sid }) = req?;
+
+    let mut conn = get_connection().await?;
+    if !judger_auth(&uid, &password, &mut conn).await? { return JkmxJsonResponse::Response(StatusCode::UNAUTHORIZED, BYTES_NULL); }
+
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    let (submission, problem) = match conn.query_opt(&stmt, &[&sid.cast_signed()]).await? {
+        Some(row) => (Submission::try_from(row.clone())?, Problem::try_from(row)?),
+        None => return NO_SUCH_SUBMISSION,
+    };
+
+    let (uid, module) = (submission.submitter.clone(), submission.module_name.clone());
+    let olean = tokio::task::spawn_blocking(move || submission_deposit::read_compiled(sid, &uid, &module)).await?;
+
+    let w: Option<_> = try {
+        let olean = olean.ok()?;
+        let meta = olean::parse_meta(&olean)?;
+        let consts = olean::parse_consts(meta)?;
+        let imports = olean::parse_imports(meta)?;
+        if *meta.version != *submission.lean_toolchain || !consts.contains(&submission.const_name) { do yeet; }
+        let mut sha256 = Sha256::new();
+        sha256.update(&olean);
+        (meta.version, meta.is_module(), imports, sha256.finish())
+    };
+    let Some((version, is_module, imports, hash)) = w else {
+        let msg = format!("{}: compiled olean is missing, invalid or does not contain `{}`", submission.module_name, submission.const_name);
+        Submission::report_status(sid, SubmissionStatus::CompilationError, SubmissionMessageAction::Append(msg.into()), &mut conn).await?;
+        return JkmxJsonResponse::Response(StatusCode::OK, BYTES_NULL);
+    };
+
+    let task = submission_deposit::Task {
+        sid,
+        uid: submission.submitter,
+        module_name: submission.module_name,
+        const_name: submission.const_name,
+        is_module,
+        imports,
+        version,
+        hash,
+        checker: problem.jb,
+        source: true,
+    };
+    submission_deposit::transmit(task)?;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_NULL)
+}
+
 pub fn router(_header: &'static Parts) -> Router {
     Router::new()
         .route("/getOleanMeta", post(get_olean_meta))
         .route("/submit", post(submit))
+        .route("/submitSource", post(submit_source))
         .route("/querySubmission", post(query_submission))
         .route("/getSubmissionDetail", post(get_submission))
         .route("/querySubmissionStatistics", post(query_submission_statistics))
@@ -724,4 +968,5 @@ pub fn router(_header: &'static Parts) -> Router {
 
         .route("/judger__get__task", post(judger_get_task))
         .route("/judger__report__status", post(judger_report_status))
+        .route("/judger__report__compiled", post(judger_report_compiled))
 }
@@ -1,36 +1,50 @@
 use core::future::ready;
+use std::time::SystemTime;
 
 use axum::{
-    Json, Router,
+    Extension, Json, Router,
+    body::{Body, to_bytes},
     extract::Query,
-    routing::{get, post, post_service},
+    routing::{get, post},
 };
 use bytes::Bytes;
 use compact_str::CompactString;
 use futures_util::TryStreamExt;
 use http::{StatusCode, response::Parts};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Value, json};
 use smallvec::SmallVec;
 use tokio_postgres::{
     Client,
     types::{Json as QJson, ToSql},
 };
+use tower_sessions_core::Session;
 
 use crate::{
     bad, exs,
     libs::{
+        audit,
         auth::Session_,
+        avatar,
         constants::{BYTES_EMPTY, BYTES_NULL, PASSWORD_LENGTH},
         db::{DBError, DBResult, JsonChecked, get_connection},
-        lquery, privilege,
-        request::{JsonReqult, RawPayload, Repult},
+        lquery, mail, password, privilege, quota,
+        ratelimit::{self, PASSWORD_ACCOUNT},
+        request::{JsonReqult, Repult},
         response::JkmxJsonResponse,
         serde::WithJson,
+        session::{self, GlobalStore},
         util::from_millis,
         validate::{check_email, check_username},
     },
-    models::user::{User, UserA, UserInformation},
+    models::{
+        access_token::{self, AccessToken},
+        ranklist,
+        suspension::Suspension,
+        totp::Totp,
+        upload_token::UploadToken,
+        user::{User, UserA, UserInformation, UserStatistics},
+    },
 };
 
 const NO_SUCH_USER: JkmxJsonResponse = JkmxJsonResponse::Response(
@@ -135,6 +149,7 @@ async fn update_user_profile(
     let Json(UpdateUserProfileRequest { user_id, username, email, avatar_info, nickname, bio, information }) = req?;
 
     if !check_username(&username) { bad!(BYTES_NULL) }
+    if let Some(hash) = avatar_info.strip_prefix("local:") && !avatar::is_hash(hash) { bad!(BYTES_NULL) }
 
     let mut conn = get_connection().await?;
     exs!(s_user, &session, &mut conn);
@@ -142,6 +157,8 @@ async fn update_user_profile(
     if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
         return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
     }
+    // a local avatar can only be kept, others' are set through `uploadAvatar`.
+    if avatar_info.starts_with("local:") && avatar_info != t_user.avatar_info { bad!(BYTES_NULL) }
 
     let stmt_update_user = conn.prepare_static(SQL_UPDATE_USER.into()).await?;
     let stmt_update_information = conn.prepare_static(SQL_UPDATE_INFORMATION.into()).await?;
@@ -155,6 +172,48 @@ async fn update_user_profile(
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadAvatarRequest {
+    user_id: CompactString,
+}
+
+/// Body is a PNG, JPEG, GIF or WebP image, which becomes the avatar (`local:<hash>`) once re-encoded.
+async fn upload_avatar(
+    Session_(session): Session_,
+    req: Repult<Query<UploadAvatarRequest>>,
+    body: Body,
+) -> JkmxJsonResponse {
+    const SQL: &str = "update lean4oj.users set avatar_info = $1 where uid = $2";
+
+    let Query(UploadAvatarRequest { user_id }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+    drop(conn);
+
+    let Ok(data) = to_bytes(body, avatar::MAX_UPLOAD).await else {
+        return JkmxJsonResponse::Error(StatusCode::PAYLOAD_TOO_LARGE, "image too large".into());
+    };
+    let Some(hash) = avatar::store(data.into()).await? else {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"INVALID_IMAGE"}"#));
+    };
+
+    let avatar_info = format!("local:{hash}");
+    let mut conn = get_connection().await?;
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    let n = conn.execute(&stmt, &[&avatar_info, &&*t_user.uid]).await?;
+    if n != 1 { return private::err() }
+    audit::log(&s_user.uid, "user.uploadAvatar", Some(&*t_user.uid), audit::Object::None, Value::from(&*hash), &mut conn).await;
+
+    let res = format!(r#"{{"avatar":"{avatar_info}"}}"#);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GetUserListRequest {
@@ -176,6 +235,38 @@ async fn get_user_list(req: JsonReqult<GetUserListRequest>) -> JkmxJsonResponse
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetRanklistRequest {
+    /// Both in ms, the window defaulting to all time.
+    from: Option<u64>,
+    to: Option<u64>,
+    group_id: Option<CompactString>,
+    /// `nextCursor` of the previous page.
+    cursor: Option<CompactString>,
+    take_count: u64,
+}
+
+async fn get_ranklist(req: JsonReqult<GetRanklistRequest>) -> JkmxJsonResponse {
+    let Json(GetRanklistRequest { from, to, group_id, cursor, take_count }) = req?;
+
+    let from = from.map_or(SystemTime::UNIX_EPOCH, from_millis);
+    let to = to.map_or_else(SystemTime::now, from_millis);
+    let after = match cursor.as_deref().map(ranklist::Cursor::parse) {
+        Some(None) => bad!(BYTES_NULL),
+        Some(Some(cursor)) => Some(cursor),
+        None => None,
+    };
+    let take = take_count.min(100);
+
+    let mut conn = get_connection().await?;
+    let entries = ranklist::list(from, to, group_id.as_deref(), after.as_ref(), take.cast_signed(), &mut conn).await?;
+    let next_cursor = entries.last().filter(|_| entries.len() as u64 == take).map(|e| ranklist::Cursor::from(e).to_string());
+
+    let res = format!(r#"{{"ranklist":{},"nextCursor":{}}}"#, WithJson(entries), WithJson(next_cursor));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
 const SUBMISSION_COUNT_PER_DAY_COUNT: usize = 53 * 7;
 
 #[derive(Deserialize)]
@@ -193,26 +284,27 @@ struct GetUserDetailResponse {
     submission_count_per_day: Box<[u32]>,
     rank: u64,
     hasPrivilege: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suspension: Option<Suspension>,
+}
+
+/// The suspension of `uid`, for those with `ManageUser` only.
+#[allow(clippy::ref_option)]
+async fn suspension_for(session: &Option<Session<GlobalStore>>, uid: &str, conn: &mut Client) -> DBResult<Option<Suspension>> {
+    let Some(viewer) = User::from_maybe_session(session, conn).await? else { return Ok(None) };
+    if !privilege::check(&viewer.uid, "Lean4OJ.ManageUser", conn).await? { return Ok(None) }
+    Suspension::of(uid, conn).await
 }
 
+/// Submissions of `uid` on each of the last [`SUBMISSION_COUNT_PER_DAY_COUNT`] days up to `now` in `timezone`, oldest first.
 #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
-async fn get_user_detail(req: JsonReqult<GetUserDetailRequest>) -> JkmxJsonResponse {
-    const SQL_RANK: &str = "select count(*) from lean4oj.users where ac > $1";
+async fn submission_count_per_day(uid: &str, timezone: &str, now: SystemTime, conn: &mut Client) -> DBResult<Box<[u32]>> {
     const SQL_PER_DAY: &str = "select (($1::timestamp at time zone 'UTC' at time zone $2)::date - (submit_time at time zone 'UTC' at time zone $2)::date) as d, count(*) from lean4oj.submissions where submitter = $3 and submit_time between ((($1::timestamp at time zone 'UTC' at time zone $2)::date - $4::integer)::timestamp at time zone $2 at time zone 'UTC') and (($1::timestamp at time zone 'UTC' at time zone $2)::date + 1)::timestamp at time zone $2 at time zone 'UTC' group by d";
 
-    let Json(GetUserDetailRequest { uid, timezone, now }) = req?;
-    let now = from_millis(now);
-
-    let mut conn = get_connection().await?;
-    let Some(user) = User::by_uid(&uid, &mut conn).await? else { return NO_SUCH_USER };
-    let stmt = conn.prepare_static(SQL_RANK.into()).await?;
-    let row = conn.query_one(&stmt, &[&user.ac.cast_signed()]).await?;
-    let information = UserInformation::of(&uid, &mut conn).await?;
-
     let mut submission_count_per_day = unsafe { Box::new_zeroed_slice(SUBMISSION_COUNT_PER_DAY_COUNT).assume_init() };
 
     let stmt = conn.prepare_static(SQL_PER_DAY.into()).await?;
-    let params: [&(dyn ToSql + Sync); 4] = [&now, &&*timezone, &&*uid, &(SUBMISSION_COUNT_PER_DAY_COUNT as i32 - 1)];
+    let params: [&(dyn ToSql + Sync); 4] = [&now, &timezone, &uid, &(SUBMISSION_COUNT_PER_DAY_COUNT as i32 - 1)];
     let stream = conn.query_raw(&stmt, params).await?;
     stream.try_for_each(|row| ready(try {
         let d = row.try_get::<_, i32>(0)?;
@@ -221,6 +313,22 @@ async fn get_user_detail(req: JsonReqult<GetUserDetailRequest>) -> JkmxJsonRespo
             *r = c as u32;
         }
     })).await?;
+    Ok(submission_count_per_day)
+}
+
+async fn get_user_detail(Session_(session): Session_, req: JsonReqult<GetUserDetailRequest>) -> JkmxJsonResponse {
+    const SQL_RANK: &str = "select count(*) from lean4oj.users where ac > $1";
+
+    let Json(GetUserDetailRequest { uid, timezone, now }) = req?;
+    let now = from_millis(now);
+
+    let mut conn = get_connection().await?;
+    let Some(user) = User::by_uid(&uid, &mut conn).await? else { return NO_SUCH_USER };
+    let stmt = conn.prepare_static(SQL_RANK.into()).await?;
+    let row = conn.query_one(&stmt, &[&user.ac.cast_signed()]).await?;
+    let information = UserInformation::of(&uid, &mut conn).await?;
+    let suspension = suspension_for(&session, &user.uid, &mut conn).await?;
+    let submission_count_per_day = submission_count_per_day(&uid, &timezone, now, &mut conn).await?;
 
     let res = GetUserDetailResponse {
         meta: user,
@@ -228,8 +336,37 @@ async fn get_user_detail(req: JsonReqult<GetUserDetailRequest>) -> JkmxJsonRespo
         submission_count_per_day,
         rank: row.try_get::<_, i64>(0)?.cast_unsigned() + 1,
         hasPrivilege: true,
+        suspension,
+    };
+
+    JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetUserStatisticsResponse {
+    #[serde(flatten)]
+    statistics: UserStatistics,
+    submission_count_per_day: Box<[u32]>,
+}
+
+/// Takes the same request as `getUserDetail`. Problems the viewer cannot see are left out.
+async fn get_user_statistics(Session_(session): Session_, req: JsonReqult<GetUserDetailRequest>) -> JkmxJsonResponse {
+    let Json(GetUserDetailRequest { uid, timezone, now }) = req?;
+    let now = from_millis(now);
+
+    let mut conn = get_connection().await?;
+    let Some(user) = User::by_uid(&uid, &mut conn).await? else { return NO_SUCH_USER };
+    let viewer = User::from_maybe_session(&session, &mut conn).await?;
+    let all = match &viewer {
+        Some(viewer) => privilege::check(&viewer.uid, "Lean4OJ.ManageProblem", &mut conn).await?,
+        None => false,
     };
 
+    let statistics = UserStatistics::of(&user.uid, viewer.as_ref().map(|v| &*v.uid), all, &mut conn).await?;
+    let submission_count_per_day = submission_count_per_day(&user.uid, &timezone, now, &mut conn).await?;
+
+    let res = GetUserStatisticsResponse { statistics, submission_count_per_day };
     JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
 }
 
@@ -245,14 +382,17 @@ struct GetUserProfileResponse {
     information: UserInformation,
     public_email: bool,
     avatar_info: CompactString,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suspension: Option<Suspension>,
 }
 
-async fn get_user_profile(req: JsonReqult<GetSingleUserRequest>) -> JkmxJsonResponse {
+async fn get_user_profile(Session_(session): Session_, req: JsonReqult<GetSingleUserRequest>) -> JkmxJsonResponse {
     let Json(GetSingleUserRequest { uid }) = req?;
 
     let mut conn = get_connection().await?;
     let Some(user) = User::by_uid(&uid, &mut conn).await? else { return NO_SUCH_USER };
     let information = UserInformation::of(&uid, &mut conn).await?;
+    let suspension = suspension_for(&session, &user.uid, &mut conn).await?;
 
     let avatar_info = user.avatar_info.clone();
     let res = GetUserProfileResponse {
@@ -260,6 +400,7 @@ async fn get_user_profile(req: JsonReqult<GetSingleUserRequest>) -> JkmxJsonResp
         information,
         public_email: true,
         avatar_info,
+        suspension,
     };
 
     JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
@@ -317,18 +458,70 @@ async fn update_user_preference(
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
 
-async fn get_user_security_settings(req: JsonReqult<GetSingleUserRequest>) -> JkmxJsonResponse {
+async fn get_user_security_settings(
+    Session_(session): Session_,
+    req: JsonReqult<GetSingleUserRequest>,
+) -> JkmxJsonResponse {
     let Json(GetSingleUserRequest { uid }) = req?;
 
     let mut conn = get_connection().await?;
-    let Some(user) = User::by_uid(&uid, &mut conn).await? else { return NO_SUCH_USER };
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&uid, &mut conn).await? else { return NO_SUCH_USER };
+    if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
 
-    let res = format!(r#"{{"meta":{}}}"#, WithJson(user));
+    let totp = Totp::of(&t_user.uid, &mut conn).await?.filter(|totp| totp.enabled);
+    let required = Totp::required(&t_user.uid, &mut conn).await?;
+    let res = format!(
+        r#"{{"meta":{},"totp":{{"enabled":{},"required":{required},"recoveryCodesLeft":{}}}}}"#,
+        WithJson(t_user),
+        totp.is_some(),
+        totp.map_or(0, |totp| totp.recovery_codes.len()),
+    );
     JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
 
-const fn query_audit_logs(header: &'static Parts) -> RawPayload {
-    RawPayload { header, body: br#"{"count":0,"results":[]}"# }
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryAuditLogsRequest {
+    user_id: Option<CompactString>,
+    target_user_id: Option<CompactString>,
+    action: Option<CompactString>,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+    skip_count: u64,
+    take_count: u64,
+}
+
+/// Without `ManageUser`, one can only query one's own actions.
+async fn query_audit_logs(
+    Session_(session): Session_,
+    req: JsonReqult<QueryAuditLogsRequest>,
+) -> JkmxJsonResponse {
+    let Json(QueryAuditLogsRequest { user_id, target_user_id, action, start_time, end_time, skip_count, take_count }) = req?;
+
+    let skip = skip_count.min(i64::MAX.cast_unsigned()).cast_signed();
+    let take = take_count.min(100).cast_signed();
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+    if user_id.as_deref() != Some(&*user.uid) && !privilege::check(&user.uid, "Lean4OJ.ManageUser", &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    let filter = audit::Filter {
+        actor: user_id.as_deref(),
+        target_uid: target_user_id.as_deref(),
+        action: action.as_deref(),
+        since: start_time.map(from_millis),
+        until: end_time.map(from_millis),
+    };
+    let results = filter.query(skip, take, &mut conn).await?;
+    let count = filter.count(&mut conn).await?;
+
+    let res = format!(r#"{{"results":{},"count":{count}}}"#, WithJson(results));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
 }
 
 #[derive(Deserialize)]
@@ -343,7 +536,7 @@ async fn update_password(
     Session_(session): Session_,
     req: JsonReqult<UpdatePasswordRequest>,
 ) -> JkmxJsonResponse {
-    const SQL: &str = "update lean4oj.users set password = $1 where uid = $2";
+    const SQL: &str = "update lean4oj.users set password = $1, rsync_verifier = $3 where uid = $2";
 
     let Json(UpdatePasswordRequest { user_id, old_password, password }) = req?;
 
@@ -352,17 +545,25 @@ async fn update_password(
     let mut conn = get_connection().await?;
     exs!(s_user, &session, &mut conn);
     let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
-    if !private::λ(
-        &s_user.uid,
-        *s_user.uid == *t_user.uid && old_password.is_some_and(|p| *p.as_bytes() == t_user.password),
-        &mut conn,
-    ).await? {
+    let old_password_ok = match old_password {
+        Some(old) if *s_user.uid == *t_user.uid => {
+            if let Err(t) = ratelimit::check(&PASSWORD_ACCOUNT, &t_user.uid) { return ratelimit::limited(t); }
+            let ok = password::verify(&t_user.uid, &old, &t_user.password, &mut conn).await?;
+            if ok { ratelimit::reset(&PASSWORD_ACCOUNT, &t_user.uid); } else { ratelimit::fail(&PASSWORD_ACCOUNT, &t_user.uid); }
+            ok
+        }
+        _ => false,
+    };
+    if !private::λ(&s_user.uid, old_password_ok, &mut conn).await? {
         return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
     }
 
+    let hashed = password::hash(&password).await?;
+    let verifier = password::rsync_verifier(password.as_bytes()).map(Vec::from);
     let stmt = conn.prepare_static(SQL.into()).await?;
-    let n = conn.execute(&stmt, &[&&*password, &&*t_user.uid]).await?;
+    let n = conn.execute(&stmt, &[&hashed, &&*t_user.uid, &verifier]).await?;
     if n != 1 { return private::err() }
+    audit::log(&s_user.uid, "user.updatePassword", Some(&*t_user.uid), audit::Object::None, Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
@@ -370,40 +571,372 @@ async fn update_password(
 #[derive(Deserialize)]
 struct UpdateEmailRequest {
     email: CompactString,
+    password: CompactString,
 }
 
 async fn update_email(
     Session_(session): Session_,
     req: JsonReqult<UpdateEmailRequest>,
 ) -> JkmxJsonResponse {
-    const SQL: &str = "update lean4oj.users set email = $1 where uid = $2";
+    const SQL: &str = "update lean4oj.users set email = $1, email_verified = false, email_confirmed = false where uid = $2";
 
-    let Json(UpdateEmailRequest { email }) = req?;
+    let Json(UpdateEmailRequest { email, password }) = req?;
 
     if check_email(&email).is_none() { bad!(BYTES_NULL) }
 
     let mut conn = get_connection().await?;
     exs!(user, &session, &mut conn);
 
+    // whoever holds a stolen session must not be able to take the account over through a password reset.
+    if let Err(t) = ratelimit::check(&PASSWORD_ACCOUNT, &user.uid) { return ratelimit::limited(t); }
+    if !password::verify(&user.uid, &password, &user.password, &mut conn).await? {
+        ratelimit::fail(&PASSWORD_ACCOUNT, &user.uid);
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+    ratelimit::reset(&PASSWORD_ACCOUNT, &user.uid);
+
     let stmt = conn.prepare_static(SQL.into()).await?;
     let n = conn.execute(&stmt, &[&&*email, &&*user.uid]).await?;
     if n != 1 { return private::err() }
+    session::revoke_all(&user.uid, session.as_ref().and_then(Session::id), &mut conn).await?;
+    audit::log(&user.uid, "user.updateEmail", Some(&*user.uid), audit::Object::None, Value::from(&*email), &mut conn).await;
+    mail::send_email_verification(&user.uid, &user.username, &email);
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetStorageUsageRequest {
+    user_id: CompactString,
+}
+
+#[derive(Serialize)]
+struct GetStorageUsageResponse {
+    quota: Option<u64>,
+    size: u64,
+    files: u32,
+    modules: Vec<quota::ModuleUsage>,
+}
+
+async fn get_storage_usage(
+    Session_(session): Session_,
+    req: JsonReqult<GetStorageUsageRequest>,
+) -> JkmxJsonResponse {
+    let Json(GetStorageUsageRequest { user_id }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    let limit = quota::limit(&t_user.uid, &mut conn).await?;
+    let modules = quota::usage(&t_user.uid, &mut conn).await?;
+    let res = GetStorageUsageResponse {
+        quota: (limit != usize::MAX).then_some(limit as u64),
+        size: modules.iter().map(|m| m.size).sum(),
+        files: modules.iter().map(|m| m.files).sum(),
+        modules,
+    };
+
+    JkmxJsonResponse::Response(StatusCode::OK, serde_json::to_vec(&res)?.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetStorageQuotaRequest {
+    user_id: CompactString,
+    /// `None` to fall back to the groups / the default.
+    quota: Option<u64>,
+}
+
+async fn set_storage_quota(
+    Session_(session): Session_,
+    req: JsonReqult<SetStorageQuotaRequest>,
+) -> JkmxJsonResponse {
+    const SQL: &str = "update lean4oj.users set storage_quota = $1 where uid = $2";
+
+    let Json(SetStorageQuotaRequest { user_id, quota }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    if !privilege::check(&s_user.uid, "Lean4OJ.ManageUser", &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+
+    let quota = quota.map(|q| q.min(i64::MAX.cast_unsigned()).cast_signed());
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    let n = conn.execute(&stmt, &[&quota, &&*t_user.uid]).await?;
+    if n != 1 { return private::err() }
+    audit::log(&s_user.uid, "user.setStorageQuota", Some(&*t_user.uid), audit::Object::None, Value::from(quota), &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListUploadTokensRequest {
+    user_id: CompactString,
+}
+
+async fn list_upload_tokens(
+    Session_(session): Session_,
+    req: JsonReqult<ListUploadTokensRequest>,
+) -> JkmxJsonResponse {
+    let Json(ListUploadTokensRequest { user_id }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    let tokens = UploadToken::list(&t_user.uid, &mut conn).await?;
+
+    let res = format!(r#"{{"tokens":{}}}"#, WithJson(tokens));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateUploadTokenRequest {
+    name: CompactString,
+    #[serde(default)]
+    read_only: bool,
+}
+
+/// Tokens are always created for oneself, the secret is never shown again.
+async fn create_upload_token(
+    Session_(session): Session_,
+    req: JsonReqult<CreateUploadTokenRequest>,
+) -> JkmxJsonResponse {
+    let Json(CreateUploadTokenRequest { name, read_only }) = req?;
+
+    if !check_username(&name) { bad!(BYTES_NULL) }
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+
+    let Some(secret) = UploadToken::create(&user.uid, &name, read_only, &mut conn).await? else {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"DUPLICATE_NAME_OR_TOO_MANY_TOKENS"}"#));
+    };
+
+    let res = format!(r#"{{"secret":"{secret}"}}"#);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokeUploadTokenRequest {
+    user_id: CompactString,
+    name: CompactString,
+}
+
+async fn revoke_upload_token(
+    Session_(session): Session_,
+    req: JsonReqult<RevokeUploadTokenRequest>,
+) -> JkmxJsonResponse {
+    let Json(RevokeUploadTokenRequest { user_id, name }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    if !UploadToken::revoke(&t_user.uid, &name, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NO_SUCH_TOKEN"}"#));
+    }
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListAccessTokensRequest {
+    user_id: CompactString,
+}
+
+async fn list_access_tokens(
+    Session_(session): Session_,
+    req: JsonReqult<ListAccessTokensRequest>,
+) -> JkmxJsonResponse {
+    let Json(ListAccessTokensRequest { user_id }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    let tokens = AccessToken::list(&t_user.uid, &mut conn).await?;
+
+    let res = format!(r#"{{"tokens":{}}}"#, WithJson(tokens));
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateAccessTokenRequest {
+    name: CompactString,
+    scopes: Vec<CompactString>,
+    expire_time: Option<u64>,
+}
+
+/// Tokens are always created for oneself, the token is never shown again.
+async fn create_access_token(
+    Extension(now): Extension<SystemTime>,
+    Session_(session): Session_,
+    req: JsonReqult<CreateAccessTokenRequest>,
+) -> JkmxJsonResponse {
+    let Json(CreateAccessTokenRequest { name, mut scopes, expire_time }) = req?;
+
+    let expire_time = expire_time.map(from_millis);
+    scopes.sort_unstable();
+    scopes.dedup();
+    if !check_username(&name)
+    || scopes.is_empty()
+    || !scopes.iter().all(|s| access_token::SCOPES.contains(&&**s))
+    || expire_time.is_some_and(|t| t <= now) {
+        bad!(BYTES_NULL)
+    }
+
+    let mut conn = get_connection().await?;
+    exs!(user, &session, &mut conn);
+
+    let scopes = scopes.iter().map(CompactString::as_str).collect::<Vec<_>>();
+    let Some(token) = AccessToken::create(&user.uid, &name, &scopes, expire_time, &mut conn).await? else {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"DUPLICATE_NAME_OR_TOO_MANY_TOKENS"}"#));
+    };
+    audit::log(&user.uid, "user.createAccessToken", Some(&*user.uid), audit::Object::None, Value::from(&*name), &mut conn).await;
+
+    let res = format!(r#"{{"token":"{token}"}}"#);
+    JkmxJsonResponse::Response(StatusCode::OK, res.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokeAccessTokenRequest {
+    user_id: CompactString,
+    name: CompactString,
+}
+
+async fn revoke_access_token(
+    Session_(session): Session_,
+    req: JsonReqult<RevokeAccessTokenRequest>,
+) -> JkmxJsonResponse {
+    let Json(RevokeAccessTokenRequest { user_id, name }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if !private::γ(&s_user.uid, &t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    if !AccessToken::revoke(&t_user.uid, &name, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NO_SUCH_TOKEN"}"#));
+    }
+    audit::log(&s_user.uid, "user.revokeAccessToken", Some(&*t_user.uid), audit::Object::None, Value::from(&*name), &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SuspendUserRequest {
+    user_id: CompactString,
+    reason: String,
+    /// In milliseconds, `None` to suspend for good.
+    until: Option<u64>,
+}
+
+/// Also ends every session of the user. Only admins can suspend admins, and nobody themselves.
+async fn suspend_user(
+    Session_(session): Session_,
+    req: JsonReqult<SuspendUserRequest>,
+) -> JkmxJsonResponse {
+    let Json(SuspendUserRequest { user_id, reason, until: until_millis }) = req?;
+
+    let reason = reason.trim();
+    let until = until_millis.map(from_millis);
+    if reason.is_empty() || reason.len() > 1000 || until.is_some_and(|t| t <= SystemTime::now()) { bad!(BYTES_NULL) }
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if s_user.uid == t_user.uid
+    || !privilege::check(&s_user.uid, "Lean4OJ.ManageUser", &mut conn).await?
+    || (privilege::check(&t_user.uid, "Lean4OJ.Admin", &mut conn).await? && !privilege::check(&s_user.uid, "Lean4OJ.Admin", &mut conn).await?) {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    if !Suspension::suspend(&t_user.uid, &s_user.uid, reason, until, &mut conn).await? { return private::err() }
+    let revoked = session::revoke_all(&t_user.uid, None, &mut conn).await?;
+    let details = json!({ "reason": reason, "until": until_millis, "revokedSessions": revoked });
+    audit::log(&s_user.uid, "user.suspend", Some(&*t_user.uid), audit::Object::None, details, &mut conn).await;
+
+    JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnsuspendUserRequest {
+    user_id: CompactString,
+}
+
+async fn unsuspend_user(
+    Session_(session): Session_,
+    req: JsonReqult<UnsuspendUserRequest>,
+) -> JkmxJsonResponse {
+    let Json(UnsuspendUserRequest { user_id }) = req?;
+
+    let mut conn = get_connection().await?;
+    exs!(s_user, &session, &mut conn);
+    let Some(t_user) = User::by_uid(&user_id, &mut conn).await? else { return NO_SUCH_USER };
+    if !privilege::check(&s_user.uid, "Lean4OJ.ManageUser", &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::FORBIDDEN, BYTES_NULL);
+    }
+
+    if !Suspension::lift(&t_user.uid, &mut conn).await? {
+        return JkmxJsonResponse::Response(StatusCode::OK, Bytes::from_static(br#"{"error":"NOT_SUSPENDED"}"#));
+    }
+    audit::log(&s_user.uid, "user.unsuspend", Some(&*t_user.uid), audit::Object::None, Value::Null, &mut conn).await;
 
     JkmxJsonResponse::Response(StatusCode::OK, BYTES_EMPTY)
 }
 
-pub fn router(header: &'static Parts) -> Router {
+pub fn router(_header: &'static Parts) -> Router {
     Router::new()
         .route("/searchUser", get(search_user))
         .route("/getUserMeta", post(get_user_meta))
         .route("/updateUserProfile", post(update_user_profile))
+        .route("/uploadAvatar", post(upload_avatar))
         .route("/getUserList", post(get_user_list))
+        .route("/getRanklist", post(get_ranklist))
         .route("/getUserDetail", post(get_user_detail))
+        .route("/getUserStatistics", post(get_user_statistics))
         .route("/getUserProfile", post(get_user_profile))
         .route("/getUserPreference", post(get_user_preference))
         .route("/updateUserPreference", post(update_user_preference))
         .route("/getUserSecuritySettings", post(get_user_security_settings))
-        .route("/queryAuditLogs", post_service(query_audit_logs(header)))
+        .route("/queryAuditLogs", post(query_audit_logs))
         .route("/updateUserPassword", post(update_password))
         .route("/updateUserSelfEmail", post(update_email))
+        .route("/getStorageUsage", post(get_storage_usage))
+        .route("/setStorageQuota", post(set_storage_quota))
+        .route("/listUploadTokens", post(list_upload_tokens))
+        .route("/createUploadToken", post(create_upload_token))
+        .route("/revokeUploadToken", post(revoke_upload_token))
+        .route("/listAccessTokens", post(list_access_tokens))
+        .route("/createAccessToken", post(create_access_token))
+        .route("/revokeAccessToken", post(revoke_access_token))
+        .route("/suspendUser", post(suspend_user))
+        .route("/unsuspendUser", post(unsuspend_user))
 }
@@ -9,3 +9,6 @@ pub const DUMMY_HOST: HeaderValue = HeaderValue::from_static("judger");
 pub const APPLICATION_JSON_UTF_8: HeaderValue = HeaderValue::from_static("application/json; charset=utf-8");
 
 pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+pub const COMPILE_TIMEOUT: Duration = Duration::from_secs(300);
+pub const COMPILE_OUTPUT_LIMIT: usize = 0x10000; // 64 KB
@@ -14,7 +14,7 @@ use tokio::{
 };
 
 use crate::{
-    constants::{APPLICATION_JSON_UTF_8, DUMMY_HOST, PASSWORD, USERNAME},
+    constants::{APPLICATION_JSON_UTF_8, COMPILE_OUTPUT_LIMIT, COMPILE_TIMEOUT, DUMMY_HOST, PASSWORD, USERNAME},
     task,
 };
 
@@ -66,6 +66,103 @@ pub async fn report(
     }
 }
 
+pub async fn report_compiled(sid: u32, sender: &mut SendRequest<String>) -> io::Result<()> {
+    #[cfg(debug_assertions)]
+    tracing::debug!("[submission #{sid}] compiled");
+
+    let req = Request::post("/api/submission/judger__report__compiled")
+        .header(header::HOST, DUMMY_HOST)
+        .header(header::CONTENT_TYPE, APPLICATION_JSON_UTF_8)
+        .body(format!(r#"{{"uid":"{USERNAME}","password":"{PASSWORD}","sid":{sid}}}"#))
+        .unwrap();
+
+    let res = sender.try_send_request(req).await
+        .map_err(|e| io::Error::other(e.into_error()))?;
+
+    match res.into_body().collect().await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(io::Error::other(e)),
+    }
+}
+
+/// Compile `task.sources` one by one (in dependency order) inside the submission root, under the same sandbox user as judging.
+async fn compile(task: &task::Task, sender: &mut SendRequest<String>) {
+    // sources are read-only to the sandbox, oleans go to a scratch tree the backend never trusts.
+    let bytes = task.sid.to_le_bytes();
+    let sources = format!(
+        "{}/sources/{:02x}/{:02x}/{:02x}/{:02x}",
+        env!("OLEAN_ROOT"),
+        bytes[3], bytes[2], bytes[1], bytes[0],
+    );
+    let scratch = format!(
+        "{}/scratch/{:02x}/{:02x}/{:02x}/{:02x}",
+        env!("OLEAN_ROOT"),
+        bytes[3], bytes[2], bytes[1], bytes[0],
+    );
+    let lean = format!("{}/leanprover--lean4---v{}/bin/lean", env!("LEAN4_TOOLCHAIN_DIR"), task.version);
+    let lean_path = format!(
+        "{0}/leanprover--lean4---v{2}/lib/lean:{1}/std/{2}:{1}/lean/Lean4OJ/{2}:{scratch}",
+        env!("LEAN4_TOOLCHAIN_DIR"),
+        env!("OLEAN_ROOT"),
+        task.version,
+    );
+
+    for source in &task.sources {
+        let mut cmd = Command::new(&*lean);
+        cmd.current_dir(&*sources);
+        cmd.env("LEAN_PATH", &*lean_path);
+        cmd.arg("-o");
+        cmd.arg(format!("{scratch}/{source}.olean"));
+        cmd.arg(format!("{source}.lean"));
+        cmd.stdin(Stdio::null());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        cmd.kill_on_drop(true);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::process::CommandExt;
+
+            cmd.uid(0x10000 + task.sid);
+            cmd.gid(0xdeadbeef);
+            cmd.as_std_mut().groups(&[]);
+        }
+        cmd.process_group(0);
+        let child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!("Failed to spawn lean: {e}");
+                let _ = report(task.sid, status::Status::JudgementFailed, message::Action::Replace(Cow::Owned(e.to_string())), None, sender).await;
+                return;
+            }
+        };
+        let pgid = child.id();
+
+        let res = tokio::time::timeout(COMPILE_TIMEOUT, child.wait_with_output()).await;
+        // whatever the compiled code has spawned must not outlive the compilation.
+        #[cfg(target_os = "linux")]
+        if let Some(pgid) = pgid {
+            unsafe { libc::kill(-pgid.cast_signed(), libc::SIGKILL); }
+        }
+        let err = match res {
+            Ok(Ok(output)) if output.status.success() => continue,
+            Ok(Ok(output)) => {
+                let mut out = output.stdout;
+                out.extend_from_slice(&output.stderr);
+                out.truncate(COMPILE_OUTPUT_LIMIT);
+                format!("{source}.lean: {}\n{}", output.status, String::from_utf8_lossy(&out))
+            }
+            Ok(Err(e)) => format!("{source}.lean: {e}"),
+            Err(_) => format!("{source}.lean: compilation timed out"),
+        };
+        let _ = report(task.sid, status::Status::CompilationError, message::Action::Replace(Cow::Owned(err)), None, sender).await;
+        return;
+    }
+
+    if let Err(e) = report_compiled(task.sid, sender).await {
+        tracing::warn!("Failed to report compilation of submission #{}: {e}", task.sid);
+    }
+}
+
 pub async fn read_string<R>(reader: &mut R) -> io::Result<String>
 where
     R: AsyncRead + Unpin,
@@ -96,6 +193,11 @@ where
         #[cfg(debug_assertions)]
         tracing::debug!("Received task: {task:?}");
 
+        if !task.sources.is_empty() {
+            compile(&task, &mut sender).await;
+            continue;
+        }
+
         let bytes = task.sid.to_le_bytes();
         let lean_path = format!(
             "{0}/leanprover--lean4---v{2}/lib/lean:{1}/std/{2}:{1}/lean/Lean4OJ/{2}:{1}/submissions/{6:02x}/{5:02x}/{4:02x}/{3:02x}/main.lean",
@@ -1,23 +1,36 @@
+pub mod audit;
 pub mod auth;
+pub mod avatar;
+pub mod broadcast;
 #[rustfmt::skip]
 pub mod constants;
 pub mod db;
 pub mod emoji;
 pub mod error;
+pub mod fetch;
 pub mod fs;
 pub mod judger {
     pub mod task;
 }
+pub mod lean_header;
 pub mod logger;
+pub mod mail;
 pub mod lquery;
+pub mod oauth;
 pub mod olean;
+pub mod otp;
+pub mod password;
 pub mod preference {
     pub mod server;
 }
 pub mod privilege;
+pub mod quota;
+pub mod ratelimit;
 pub mod request;
 pub mod response;
 pub mod serde;
 pub mod session;
+pub mod tar;
+pub mod token;
 pub mod util;
 pub mod validate;
@@ -0,0 +1,158 @@
+use core::{
+    fmt::{self, Write},
+    future::ready,
+};
+use std::time::SystemTime;
+
+use compact_str::{CompactString, ToCompactString};
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use smallvec::SmallVec;
+use tokio_postgres::{Client, Row, types::ToSql};
+
+use super::{
+    db::{DBError, DBResult},
+    serde::JsTime,
+};
+
+/// What an action was done to, besides the target user.
+#[derive(Clone, Copy)]
+pub enum Object<'a> {
+    None,
+    Group(&'a str),
+    Problem(i32),
+    Submission(u32),
+}
+
+impl Object<'_> {
+    fn split(self) -> (Option<&'static str>, Option<CompactString>) {
+        match self {
+            Self::None => (None, None),
+            Self::Group(gid) => (Some("group"), Some(gid.into())),
+            Self::Problem(pid) => (Some("problem"), Some(pid.to_compact_string())),
+            Self::Submission(sid) => (Some("submission"), Some(sid.to_compact_string())),
+        }
+    }
+}
+
+/// The actor of what the server does by itself (e.g. lockouts), the system user `Lean4OJ`.
+pub const SYSTEM: &str = "Lean4OJ";
+
+/// Best-effort: the action has already taken effect, so a failure is only logged.
+pub async fn log(actor: &str, action: &str, target_uid: Option<&str>, object: Object<'_>, details: Value, db: &mut Client) {
+    const SQL: &str = "insert into lean4oj.audit_logs (uid, action, target_uid, object_type, object_id, details, time) values ($1, $2, $3, $4, $5, $6, $7)";
+
+    let (object_type, object_id) = object.split();
+    let res: DBResult<u64> = try {
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&actor, &action, &target_uid, &object_type, &object_id.as_deref(), &details, &SystemTime::now()]).await?
+    };
+    if let Err(e) = res {
+        tracing::warn!(target: "audit", "failed to log {action} by {actor}: {e:?}");
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLog {
+    pub id: u64,
+    pub user_id: CompactString,
+    pub action: CompactString,
+    pub target_user_id: Option<CompactString>,
+    pub object_type: Option<CompactString>,
+    pub object_id: Option<CompactString>,
+    pub details: Value,
+    #[serde(serialize_with = "JsTime")]
+    pub time: SystemTime,
+}
+
+impl TryFrom<Row> for AuditLog {
+    type Error = DBError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let id = row.try_get::<_, i64>("id")?.cast_unsigned();
+        let user_id = row.try_get::<_, &str>("uid")?.into();
+        let action = row.try_get::<_, &str>("action")?.into();
+        let target_user_id = row.try_get::<_, Option<&str>>("target_uid")?.map(Into::into);
+        let object_type = row.try_get::<_, Option<&str>>("object_type")?.map(Into::into);
+        let object_id = row.try_get::<_, Option<&str>>("object_id")?.map(Into::into);
+        let details = row.try_get("details")?;
+        let time = row.try_get("time")?;
+        Ok(Self { id, user_id, action, target_user_id, object_type, object_id, details, time })
+    }
+}
+
+/// All filters are optional, `action` ending with `.` matches the whole category (e.g. `group.`).
+#[derive(Default)]
+pub struct Filter<'a> {
+    pub actor: Option<&'a str>,
+    pub target_uid: Option<&'a str>,
+    pub action: Option<&'a str>,
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+}
+
+impl Filter<'_> {
+    /// The `where` clause (empty without any filter) and its parameters, from `$1` on, so that only what is filtered
+    /// on reaches the planner.
+    fn cond(&self) -> (String, SmallVec<[&(dyn ToSql + Sync); 8]>) {
+        fn push(sql: &mut String, cond: fmt::Arguments) {
+            sql.push_str(if sql.is_empty() { " where " } else { " and " });
+            let _ = sql.write_fmt(cond);
+        }
+
+        let mut sql = String::new();
+        let mut args: SmallVec<[&(dyn ToSql + Sync); 8]> = SmallVec::new();
+        if let Some(ref actor) = self.actor {
+            args.push(actor);
+            push(&mut sql, format_args!("uid = ${}", args.len()));
+        }
+        if let Some(ref target_uid) = self.target_uid {
+            args.push(target_uid);
+            push(&mut sql, format_args!("target_uid = ${}", args.len()));
+        }
+        if let Some(ref action) = self.action {
+            args.push(action);
+            if action.ends_with('.') {
+                push(&mut sql, format_args!("starts_with(action, ${})", args.len()));
+            } else {
+                push(&mut sql, format_args!("action = ${}", args.len()));
+            }
+        }
+        if let Some(ref since) = self.since {
+            args.push(since);
+            push(&mut sql, format_args!("time >= ${}", args.len()));
+        }
+        if let Some(ref until) = self.until {
+            args.push(until);
+            push(&mut sql, format_args!("time < ${}", args.len()));
+        }
+        (sql, args)
+    }
+
+    /// Newest first.
+    pub async fn query(&self, skip: i64, take: i64, db: &mut Client) -> DBResult<Vec<AuditLog>> {
+        let (cond, mut args) = self.cond();
+        let sql = format!(
+            "select id, uid, action, target_uid, object_type, object_id, details, time from lean4oj.audit_logs{cond} order by id desc offset ${} limit ${}",
+            args.len() + 1,
+            args.len() + 2,
+        );
+        args.push(&skip);
+        args.push(&take);
+
+        let stmt = db.prepare_static(sql.into()).await?;
+        let stream = db.query_raw(&stmt, args).await?;
+        stream.and_then(|row| ready(AuditLog::try_from(row))).try_collect().await
+    }
+
+    pub async fn count(&self, db: &mut Client) -> DBResult<u64> {
+        let (cond, args) = self.cond();
+        let sql = format!("select count(*) from lean4oj.audit_logs{cond}");
+
+        let stmt = db.prepare_static(sql.into()).await?;
+        let row = db.query_one(&stmt, &args).await?;
+        row.try_get::<_, i64>(0).map(i64::cast_unsigned)
+    }
+}
@@ -1,14 +1,18 @@
 use core::{convert::Infallible, mem, ptr};
 use std::{fs, sync::OnceLock};
 
-use axum::extract::FromRequestParts;
+use axum::extract::{FromRequestParts, OriginalUri};
 use base64::{Engine, prelude::BASE64_STANDARD};
 use futures_util::{FutureExt, future::Map};
 use http::{header::AUTHORIZATION, request::Parts};
 use openssl::{bn::BigNum, ec::EcKey, ecdsa::EcdsaSig, pkey::Private};
 use tower_sessions_core::{Session, session::Id};
 
-use super::session::{self, GlobalStore};
+use super::{
+    db::get_connection,
+    session::{self, GlobalStore},
+};
+use crate::models::access_token::{self, AccessToken};
 
 pub mod availability;
 
@@ -32,11 +36,146 @@ impl<S> FromRequestParts<S> for Session_ {
 async fn decode(parts: &Parts) -> Option<Session<GlobalStore>> {
     let header = parts.headers.get(AUTHORIZATION)?.as_bytes();
     let base64 = header.strip_prefix(b"Bearer ")?;
+    if base64.starts_with(access_token::PREFIX.as_bytes()) {
+        return decode_access_token(parts, base64).await;
+    }
     let encoded = Encoded::try_from(base64).ok()?;
     if !encoded.verify() { return None }
     session::load(encoded.id).await.ok()
 }
 
+/// The scope an access token needs on each route, by `<area>/<action>`. Every route is listed (see the test), with
+/// `None` for those never open to access tokens: signing in, account and security settings, and the judgers' own.
+const ROUTE_SCOPES: [(&str, Option<&str>); 101] = [
+    ("auth/getSessionInfo", None),
+    ("auth/login", None),
+    ("auth/loginTotp", None),
+    ("auth/logout", None),
+    ("auth/checkAvailability", None),
+    ("auth/register", None),
+    ("auth/listUserSessions", None),
+    ("auth/revokeUserSession", None),
+    ("auth/revokeAllUserSessions", None),
+    ("auth/sendEmailVerification", None),
+    ("auth/verifyEmail", None),
+    ("auth/requestPasswordReset", None),
+    ("auth/resetPassword", None),
+    ("auth/beginTotpEnrollment", None),
+    ("auth/confirmTotpEnrollment", None),
+    ("auth/disableTotp", None),
+    ("auth/regenerateRecoveryCodes", None),
+
+    ("discussion/createDiscussion", Some("discussion:write")),
+    ("discussion/createDiscussionReply", Some("discussion:write")),
+    ("discussion/toggleReaction", Some("discussion:write")),
+    ("discussion/queryDiscussion", Some("discussion:read")),
+    ("discussion/getDiscussionAndReplies", Some("discussion:read")),
+    ("discussion/getDiscussionPermissions", Some("discussion:read")),
+    ("discussion/updateDiscussion", Some("discussion:write")),
+    ("discussion/updateDiscussionReply", Some("discussion:write")),
+    ("discussion/deleteDiscussionTranslation", Some("discussion:write")),
+    ("discussion/deleteDiscussionReplyTranslation", Some("discussion:write")),
+    ("discussion/deleteDiscussion", Some("discussion:write")),
+    ("discussion/deleteDiscussionReply", Some("discussion:write")),
+
+    ("group/searchGroup", Some("group:read")),
+    ("group/createGroup", Some("group:write")),
+    ("group/deleteGroup", Some("group:write")),
+    ("group/renameGroup", Some("group:write")),
+    ("group/addMember", Some("group:write")),
+    ("group/removeMember", Some("group:write")),
+    ("group/setGroupAdmin", Some("group:write")),
+    ("group/setGroupStorageQuota", Some("group:write")),
+    ("group/setGroupRequireTotp", None),
+    ("group/getGroupList", Some("group:read")),
+    ("group/getGroupMemberList", Some("group:read")),
+
+    ("homepage/getHomepage", None),
+    ("homepage/getHomepageSettings", None),
+
+    ("judgeClient/listJudgeClients", None),
+
+    ("notification/listNotifications", None),
+    ("notification/getUnreadCount", None),
+    ("notification/markRead", None),
+    ("notification/subscribe", None),
+
+    ("oauth/listProviders", None),
+    ("oauth/authorize", None),
+    ("oauth/callback", None),
+    ("oauth/listLinks", None),
+    ("oauth/unlink", None),
+
+    ("olean/upload", Some("olean:write")),
+    ("olean/list", Some("olean:read")),
+    ("olean/delete", Some("olean:write")),
+
+    ("problem/queryProblemSet", Some("problem:read")),
+    ("problem/createProblem", Some("problem:write")),
+    ("problem/updateStatement", Some("problem:write")),
+    ("problem/getProblem", Some("problem:read")),
+    ("problem/setProblemDisplayId", Some("problem:write")),
+    ("problem/setProblemPublic", Some("problem:write")),
+    ("problem/updateProblemJudgeInfo", Some("problem:write")),
+    ("problem/deleteProblem", Some("problem:write")),
+
+    ("submission/getOleanMeta", Some("submission:read")),
+    ("submission/submit", Some("submission:write")),
+    ("submission/submitSource", Some("submission:write")),
+    ("submission/querySubmission", Some("submission:read")),
+    ("submission/getSubmissionDetail", Some("submission:read")),
+    ("submission/querySubmissionStatistics", Some("submission:read")),
+    ("submission/rejudgeSubmission", Some("submission:write")),
+    ("submission/cancelSubmission", Some("submission:write")),
+    ("submission/deleteSubmission", Some("submission:write")),
+    ("submission/subscribeSubmissions", Some("submission:read")),
+    ("submission/judger__get__task", None),
+    ("submission/judger__report__status", None),
+    ("submission/judger__report__compiled", None),
+
+    ("user/searchUser", Some("user:read")),
+    ("user/getUserMeta", Some("user:read")),
+    ("user/updateUserProfile", None),
+    ("user/uploadAvatar", None),
+    ("user/getUserList", Some("user:read")),
+    ("user/getRanklist", Some("user:read")),
+    ("user/getUserDetail", Some("user:read")),
+    ("user/getUserStatistics", Some("user:read")),
+    ("user/getUserProfile", Some("user:read")),
+    ("user/getUserPreference", Some("user:read")),
+    ("user/updateUserPreference", None),
+    ("user/getUserSecuritySettings", None),
+    ("user/queryAuditLogs", None),
+    ("user/updateUserPassword", None),
+    ("user/updateUserSelfEmail", None),
+    ("user/getStorageUsage", Some("user:read")),
+    ("user/setStorageQuota", None),
+    ("user/listUploadTokens", None),
+    ("user/createUploadToken", None),
+    ("user/revokeUploadToken", None),
+    ("user/listAccessTokens", None),
+    ("user/createAccessToken", None),
+    ("user/revokeAccessToken", None),
+    ("user/suspendUser", None),
+    ("user/unsuspendUser", None),
+];
+
+fn required_scope(path: &str) -> Option<&'static str> {
+    let area_start = path[..path.rfind('/')?].rfind('/')? + 1;
+    let route = &path[area_start..];
+    ROUTE_SCOPES.iter().find(|(r, _)| *r == route)?.1
+}
+
+/// Access tokens get a session that is never stored, and only on routes within their scopes.
+async fn decode_access_token(parts: &Parts, token: &[u8]) -> Option<Session<GlobalStore>> {
+    let path = parts.extensions.get::<OriginalUri>().map_or_else(|| parts.uri.path(), |uri| uri.0.path());
+    let scope = required_scope(path)?;
+    let mut conn = get_connection().await.ok()?;
+    let (uid, scopes) = AccessToken::authenticate(token, &mut conn).await.ok()??;
+    if !scopes.iter().any(|s| s == scope) { return None; }
+    session::ephemeral(uid).await.ok()
+}
+
 #[repr(C)]
 pub struct Encoded {
     pub id: Id,
@@ -109,3 +248,60 @@ pub fn init() {
     let key_pem = fs::read(PRIVATE_KEY_PATH).unwrap();
     ECKEY.get_or_init(|| EcKey::private_key_from_pem(&key_pem).unwrap());
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::models::access_token::SCOPES;
+
+    /// `(area, action)` of every `.route("/<action>", ...)` in the module of every `.nest("/<area>", <module>::router(...))`.
+    fn routes() -> BTreeSet<String> {
+        const SRC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+        fn literals<'a>(src: &'a str, call: &str) -> impl Iterator<Item = (&'a str, &'a str)> {
+            src.split(call).skip(1).map(|s| {
+                let (literal, rest) = s.split_once('"').unwrap();
+                (literal.strip_prefix('/').unwrap(), rest)
+            })
+        }
+
+        let api = fs::read_to_string(format!("{SRC}/api.rs")).unwrap();
+        let mut routes = BTreeSet::new();
+        for (area, rest) in literals(&api, ".nest(\"") {
+            let module = rest.trim_start_matches([',', ' ']).split_once("::").unwrap().0;
+            let src = fs::read_to_string(format!("{SRC}/api/{module}.rs")).unwrap();
+            for (action, _) in literals(&src, ".route(\"") {
+                assert!(routes.insert(format!("{area}/{action}")), "{area}/{action} is routed twice");
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn test_route_scopes() {
+        let listed = ROUTE_SCOPES.iter().map(|(r, _)| (*r).to_owned()).collect::<BTreeSet<_>>();
+        assert_eq!(listed.len(), ROUTE_SCOPES.len(), "a route is listed twice");
+        let routes = routes();
+        assert_eq!(routes.difference(&listed).collect::<Vec<_>>(), Vec::<&String>::new(), "routes without a scope");
+        assert_eq!(listed.difference(&routes).collect::<Vec<_>>(), Vec::<&String>::new(), "scopes of no route");
+
+        for (route, scope) in ROUTE_SCOPES {
+            let area = route.split_once('/').unwrap().0;
+            if let Some(scope) = scope {
+                assert!(SCOPES.contains(&scope), "{route}: {scope} cannot be granted");
+                assert_eq!(scope.split_once(':').unwrap().0, area, "{route}: {scope} is of another area");
+            }
+            if matches!(area, "auth" | "oauth") {
+                assert_eq!(scope, None, "{route} is account management");
+            }
+            assert_eq!(required_scope(&format!("/api/{route}")), scope);
+        }
+        for scope in SCOPES {
+            assert!(ROUTE_SCOPES.iter().any(|(_, s)| *s == Some(scope)), "{scope} is of no route");
+        }
+        assert_eq!(required_scope("/api/user/noSuchRoute"), None);
+        assert_eq!(required_scope("/getUserMeta"), None);
+    }
+}
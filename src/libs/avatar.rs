@@ -0,0 +1,84 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, imageops::FilterType};
+use openssl::sha::sha256;
+use tokio::task::spawn_blocking;
+
+use super::{error::BoxedStdError, util::hex_digit};
+
+/// Where `local:<hash>` avatars live, as `<hash[..2]>/<hash>.png`. nginx serves it at [`REDIRECT`].
+pub const DIR: &str = option_env!("AVATAR_DIR").unwrap_or("/var/lib/lean4oj/avatar");
+pub const REDIRECT: &str = "/internal-avatar";
+
+/// Avatars are squares of this side.
+pub const SIZE: u32 = 256;
+pub const MAX_UPLOAD: usize = 4 << 20;
+/// Of the input image, bigger ones are refused before they are decoded.
+const MAX_DIMENSION: u32 = 8192;
+/// What decoding may allocate.
+const MAX_ALLOC: u64 = 256 << 20;
+
+/// The format of `data`. Only these formats are decoded, whatever else the decoder supports.
+fn sniff(data: &[u8]) -> Option<ImageFormat> {
+    match data {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some(ImageFormat::Png),
+        [0xff, 0xd8, 0xff, ..] => Some(ImageFormat::Jpeg),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageFormat::Gif),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+/// Whether `s` is what follows `local:` in `avatar_info`.
+pub fn is_hash(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// The file of avatar `hash`, relative to [`DIR`] (or [`REDIRECT`]).
+pub fn path(hash: &str) -> String {
+    format!("{}/{hash}.png", &hash[..2])
+}
+
+/// The first frame of `data`, oriented, stripped of metadata, cropped to the center square and scaled to [`SIZE`].
+/// `None` if it does not decode. Blocking.
+fn convert(format: ImageFormat, data: &[u8]) -> Option<Vec<u8>> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_ALLOC);
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder().ok()?;
+    let orientation = decoder.orientation().ok()?;
+    let mut image = DynamicImage::from_decoder(decoder).ok()?;
+    image.apply_orientation(orientation);
+
+    let mut png = Vec::new();
+    image.resize_to_fill(SIZE, SIZE, FilterType::Lanczos3).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).ok()?;
+    Some(png)
+}
+
+/// Re-encodes and stores an uploaded image, returns its hash. `None` if it is not a valid image of a supported format.
+pub async fn store(data: Vec<u8>) -> Result<Option<String>, BoxedStdError> {
+    let Some(format) = sniff(&data) else { return Ok(None) };
+    let Some(png) = spawn_blocking(move || convert(format, &data)).await? else { return Ok(None) };
+
+    let mut hash = String::with_capacity(64);
+    for x in sha256(&png) {
+        hash.push(hex_digit(x >> 4).into());
+        hash.push(hex_digit(x & 15).into());
+    }
+
+    let target = format!("{DIR}/{}", path(&hash));
+    if tokio::fs::try_exists(&target).await? { return Ok(Some(hash)); }
+    tokio::fs::create_dir_all(format!("{DIR}/{}", &hash[..2])).await?;
+    // renamed into place, so that a file under its hash is always complete.
+    let temp = format!("{target}.{}", rand::random::<u64>());
+    tokio::fs::write(&temp, &png).await?;
+    if let Err(e) = tokio::fs::rename(&temp, &target).await {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(e.into());
+    }
+    Ok(Some(hash))
+}
@@ -0,0 +1,117 @@
+use core::{fmt, hash::Hash, ptr};
+
+use dashmap::{DashMap, Entry};
+use hashbrown::DefaultHashBuilder;
+use tokio::sync::broadcast;
+
+/// A broadcast channel per key, made on the first subscription and dropped with its last receiver. Nothing is kept for
+/// keys no one listens to.
+pub struct Channels<K, T> {
+    map: DashMap<K, broadcast::Sender<T>, DefaultHashBuilder>,
+    capacity: usize,
+}
+
+impl<K, T> Channels<K, T>
+where
+    K: Eq + Hash + Clone + fmt::Debug + Send + Sync + 'static,
+    T: Clone + Send + 'static,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self { map: DashMap::with_hasher(DefaultHashBuilder::default()), capacity }
+    }
+
+    async fn wait(&'static self, key: K, tx: broadcast::Sender<T>) {
+        let addr = unsafe { *(&raw const tx).cast::<usize>() };
+        tx.closed().await;
+        self.map.remove_if(&key, |_, tx1| {
+            let ret = unsafe { *ptr::from_ref(tx1).cast::<usize>() } == addr;
+            #[cfg(debug_assertions)]
+            if ret {
+                tracing::info!("channel for {key:?} removed.");
+            }
+            ret
+        });
+    }
+
+    pub fn subscribe(&'static self, key: K) -> broadcast::Receiver<T> {
+        match self.map.entry(key.clone()) {
+            Entry::Occupied(e) => e.get().subscribe(),
+            Entry::Vacant(e) => {
+                let (tx, rx) = broadcast::channel(self.capacity);
+                e.insert(tx.clone());
+                tokio::spawn(self.wait(key, tx));
+                rx
+            }
+        }
+    }
+
+    pub fn is_subscribed(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Dropped if no one is listening.
+    pub fn send(&self, key: &K, value: T) {
+        if let Some(tx) = self.map.get(key) {
+            let _ = tx.send(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{sync::broadcast::error::TryRecvError, task::yield_now};
+
+    use super::Channels;
+
+    fn channels() -> &'static Channels<u32, u32> {
+        Box::leak(Box::new(Channels::new(2)))
+    }
+
+    #[tokio::test]
+    async fn per_key() {
+        let channels = channels();
+        // no one listening.
+        channels.send(&1, 0);
+        assert!(!channels.is_subscribed(&1));
+
+        let mut a = channels.subscribe(1);
+        let mut b = channels.subscribe(1);
+        let mut c = channels.subscribe(2);
+        assert!(channels.is_subscribed(&1));
+        channels.send(&1, 10);
+        channels.send(&2, 20);
+        assert_eq!(a.try_recv(), Ok(10));
+        assert_eq!(b.try_recv(), Ok(10));
+        assert_eq!(c.try_recv(), Ok(20));
+        assert_eq!(a.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[tokio::test]
+    async fn lagged() {
+        let channels = channels();
+        let mut rx = channels.subscribe(1);
+        for i in 0..3 { channels.send(&1, i); }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(1)));
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[tokio::test]
+    async fn dropped_with_last_receiver() {
+        let channels = channels();
+        let a = channels.subscribe(1);
+        let b = channels.subscribe(1);
+        drop(a);
+        yield_now().await;
+        assert!(channels.is_subscribed(&1));
+        drop(b);
+        // the task waiting for the channel to close.
+        for _ in 0..8 { yield_now().await; }
+        assert!(!channels.is_subscribed(&1));
+
+        // and made again.
+        let mut c = channels.subscribe(1);
+        channels.send(&1, 1);
+        assert_eq!(c.try_recv(), Ok(1));
+    }
+}
@@ -3,7 +3,8 @@ use std::sync::OnceLock;
 use bb8_postgres::{PostgresConnectionManager, bb8};
 use bytes::{BufMut, BytesMut};
 use tokio_postgres::{
-    NoTls, Row,
+    Client, Config, Connection, NoTls, Row, Socket,
+    tls::NoTlsStream,
     types::{FromSql, IsNull, Kind, ToSql, Type, to_sql_checked},
 };
 
@@ -17,11 +18,12 @@ pub type BB8Error = bb8::RunError<DBError>;
 pub type DBResult<T> = Result<T, DBError>;
 
 static POOL: OnceLock<Pool> = OnceLock::new();
+static CONFIG: OnceLock<Config> = OnceLock::new();
 
 pub async fn init_db() {
     use super::constants::db::{CONNECTION_TIMEOUT, DBNAME, HOST, PASSWORD, USER};
 
-    let mut config = tokio_postgres::Config::new();
+    let mut config = Config::new();
     config
         .host_path(HOST)
         .user(USER)
@@ -31,7 +33,8 @@ pub async fn init_db() {
         config.password(password);
     }
 
-    let manager = PostgresConnectionManager::new(config, NoTls);
+    let manager = PostgresConnectionManager::new(config.clone(), NoTls);
+    CONFIG.get_or_init(|| config);
 
     #[allow(clippy::unwrap_used)]
     let pool = Pool::builder()
@@ -64,6 +67,12 @@ pub async fn insert_connection(
     })
 }
 
+/// A connection of its own, outside the pool, e.g. to `LISTEN` on. The [`Connection`] has to be polled.
+pub async fn connect() -> DBResult<(Client, Connection<Socket, NoTlsStream>)> {
+    #[allow(clippy::unwrap_used)]
+    CONFIG.get().unwrap().connect(NoTls).await
+}
+
 #[inline]
 pub fn transfer_type<'a, T, U>(row: &'a Row, idx: usize) -> DBResult<U>
 where
@@ -0,0 +1,81 @@
+use core::{pin::Pin, time::Duration};
+
+use bytes::Bytes;
+use http::{Request, Uri, header};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::client::conn::http1;
+use hyper_util::rt::TokioIo;
+use openssl::ssl::{SslConnector, SslMethod};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    time::timeout,
+};
+use tokio_openssl::SslStream;
+
+use super::error::BoxedStdError;
+
+/// For the whole exchange, from connecting to the end of the body.
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RESPONSE: usize = 1 << 20;
+
+pub struct Response {
+    pub status: u16,
+    pub body: Bytes,
+}
+
+impl Response {
+    pub const fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+/// One request over a fresh connection.
+async fn exchange<S>(stream: S, request: Request<Full<Bytes>>) -> Result<Response, BoxedStdError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(conn);
+    let res = sender.send_request(request).await?;
+    let status = res.status().as_u16();
+    let body = Limited::new(res.into_body(), MAX_RESPONSE).collect().await?.to_bytes();
+    Ok(Response { status, body })
+}
+
+async fn fetch_inner(method: &str, url: &str, headers: &[(&str, &str)], body: Option<Vec<u8>>) -> Result<Response, BoxedStdError> {
+    let uri = url.parse::<Uri>()?;
+    let tls = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => return Err(format!("unsupported URL {url}").into()),
+    };
+    let host = uri.host().ok_or("URL without host")?;
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+    let mut request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::HOST, uri.authority().map_or(host, |a| a.as_str()))
+        .header(header::USER_AGENT, "Lean4OJ");
+    for &(name, value) in headers {
+        request = request.header(name, value);
+    }
+    let request = request.body(Full::new(Bytes::from(body.unwrap_or_default())))?;
+
+    let stream = TcpStream::connect((host.trim_start_matches('[').trim_end_matches(']'), port)).await?;
+    if tls {
+        let ssl = SslConnector::builder(SslMethod::tls_client())?.build().configure()?.into_ssl(host)?;
+        let mut stream = SslStream::new(ssl, stream)?;
+        Pin::new(&mut stream).connect().await?;
+        exchange(stream, request).await
+    } else {
+        exchange(stream, request).await
+    }
+}
+
+/// A minimal HTTP(S) client for talking to other services (e.g. OAuth providers).
+pub async fn fetch(method: &'static str, url: &str, headers: &[(&str, &str)], body: Option<Vec<u8>>) -> Result<Response, BoxedStdError> {
+    timeout(TIMEOUT, fetch_inner(method, url, headers, body)).await?
+}
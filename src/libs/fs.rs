@@ -1,6 +1,15 @@
+use core::{mem::MaybeUninit, ptr};
 #[cfg(target_os = "linux")]
 use std::path::Path;
-use std::{io, os::fd::RawFd};
+use std::{
+    fs::ReadDir,
+    io,
+    os::{fd::RawFd, unix::fs::DirEntryExt2},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use hashbrown::HashSet;
 
 pub fn mkdir(path: &mut [u8], dir: RawFd) -> io::Result<()> {
     let p = path.as_ptr().cast();
@@ -20,6 +29,84 @@ pub fn mkdir(path: &mut [u8], dir: RawFd) -> io::Result<()> {
     Ok(())
 }
 
+/// Just Cthulhu.
+#[allow(clippy::arc_with_non_send_sync, clippy::transmute_undefined_repr)]
+pub fn readdir_from_rawfd(fd: RawFd) -> io::Result<ReadDir> {
+    let ptr = unsafe { libc::fdopendir(fd) };
+    if ptr.is_null() { return Err(io::Error::last_os_error()); }
+    Ok(unsafe {
+        core::mem::transmute::<[Option<Arc<[*mut libc::DIR; 4]>>; 2], ReadDir>([
+            Some(Arc::new([ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr])),
+            None,
+        ])
+    })
+}
+
+/// Remove (or with `purge = false`, only measure the size of) everything under `dir` not in `exempt`.
+#[allow(clippy::cast_sign_loss)]
+pub fn do_delete(
+    cwd: &mut PathBuf,
+    exempt: &HashSet<&[u8]>,
+    dir: RawFd,
+    purge: bool,
+    readdir: &mut ReadDir,
+) -> io::Result<(usize, bool)> {
+    let mut delcnt = 0;
+    let mut alived = false;
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    let base_len = cwd.as_os_str().len();
+    for entry in readdir {
+        let entry = entry?;
+        let name = entry.file_name_ref();
+        let type_ = entry.file_type()?;
+        cwd.push(name);
+        let pname = name.as_encoded_bytes().as_ptr();
+        if type_.is_dir() {
+            let fd = unsafe { libc::openat(dir, pname.cast(), libc::O_DIRECTORY) };
+            if fd == -1 { return Err(io::Error::last_os_error()); }
+            let (sub_delcnt, sub_alived) = do_delete(cwd, exempt, fd, purge, &mut readdir_from_rawfd(fd)?)?;
+            delcnt += sub_delcnt;
+            if sub_alived {
+                alived = true;
+            } else if purge {
+                if unsafe { libc::unlinkat(dir, pname.cast(), libc::AT_REMOVEDIR) } != 0 { return Err(io::Error::last_os_error()); }
+                delcnt += 1;
+            }
+        } else if exempt.contains(cwd.as_os_str().as_encoded_bytes()) {
+            alived = true;
+        } else if purge {
+            if unsafe { libc::unlinkat(dir, pname.cast(), 0) } != 0 { return Err(io::Error::last_os_error()); }
+            delcnt += 1;
+        } else {
+            if unsafe { libc::fstatat(dir, pname.cast(), stat.as_mut_ptr(), 0) } != 0 { return Err(io::Error::last_os_error()); }
+            delcnt += unsafe { stat.assume_init_ref() }.st_size as usize;
+        }
+        cwd.as_mut_os_string().truncate(base_len);
+    }
+
+    Ok((delcnt, alived))
+}
+
+/// Total size of those of `paths` (relative to `dir`) that exist.
+#[allow(clippy::cast_sign_loss)]
+pub fn size_at<'a>(dir: RawFd, paths: impl IntoIterator<Item = &'a [u8]>) -> io::Result<usize> {
+    let mut acc = 0;
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    let mut buf = Vec::new();
+    for path in paths {
+        buf.clear();
+        buf.extend_from_slice(path);
+        buf.push(0);
+        if unsafe { libc::fstatat(dir, buf.as_ptr().cast(), stat.as_mut_ptr(), 0) } == 0 {
+            acc += unsafe { stat.assume_init_ref() }.st_size as usize;
+        } else {
+            let e = io::Error::last_os_error();
+            if !matches!(e.raw_os_error(), Some(libc::ENOENT | libc::ENOTDIR)) { return Err(e); }
+        }
+    }
+    Ok(acc)
+}
+
 pub fn unmap_send((ptr, size): (usize, usize)) {
     unsafe { libc::munmap(ptr as _, size); }
 }
@@ -14,4 +14,7 @@ pub struct Task {
     pub sid: u32,
     pub version: CompactString,
     pub axioms: SmallVec<[CompactString; 4]>,
+    /// Non-empty for a compile task: source paths (relative to the submission root, without `.lean`) in dependency order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<CompactString>,
 }
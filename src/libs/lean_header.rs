@@ -0,0 +1,96 @@
+/// Whitespace/comment-aware tokenizer, only good enough for the module header.
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        loop {
+            self.rest = self.rest.trim_start();
+            if let Some(r) = self.rest.strip_prefix("--") {
+                self.rest = r.find('\n').map_or("", |i| unsafe { r.get_unchecked(i..) });
+            } else if self.rest.starts_with("/-") {
+                // block comments nest.
+                let bytes = self.rest.as_bytes();
+                let mut depth = 0usize;
+                let mut i = 0;
+                loop {
+                    let Some(w) = bytes.get(i..i + 2) else {
+                        self.rest = "";
+                        return None;
+                    };
+                    if w == b"/-" {
+                        depth += 1;
+                        i += 2;
+                    } else if w == b"-/" {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 { break; }
+                    } else {
+                        i += 1;
+                    }
+                }
+                self.rest = unsafe { self.rest.get_unchecked(i..) };
+            } else {
+                break;
+            }
+        }
+        let first = self.rest.chars().next()?;
+        let len = match self.rest.find(|c: char| c.is_whitespace() || c == '-' || c == '/') {
+            Some(0) => first.len_utf8(),
+            Some(n) => n,
+            None => self.rest.len(),
+        };
+        let (token, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(token)
+    }
+}
+
+/// Imports declared in the header of a Lean source, in order of appearance.
+///
+/// Accepts `module`, `prelude`, `public`/`meta`/`private` modifiers and `import all`;
+/// stops at the first token that cannot belong to the header.
+pub fn imports(src: &str) -> Vec<&str> {
+    let mut tokens = Tokens { rest: src }.peekable();
+    let mut ret = Vec::new();
+
+    tokens.next_if_eq(&"module");
+    tokens.next_if_eq(&"prelude");
+    loop {
+        while tokens.next_if(|&t| t == "public" || t == "meta" || t == "private").is_some() {}
+        if tokens.next_if_eq(&"import").is_none() { break; }
+        tokens.next_if_eq(&"all");
+        let Some(name) = tokens.next() else { break };
+        ret.push(name);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::imports;
+
+    #[test]
+    fn test_imports() {
+        assert_eq!(imports("import Mathlib.Tactic\nimport Init\n\ntheorem foo : True := trivial\n"), ["Mathlib.Tactic", "Init"]);
+        assert_eq!(imports("prelude\nimport Init.Core\n"), ["Init.Core"]);
+        assert_eq!(imports("module\n\npublic import Foo.Bar\nmeta import all Baz\nprivate import Qux\n"), ["Foo.Bar", "Baz", "Qux"]);
+        assert_eq!(imports("import A\ndef x := 1\nimport B\n"), ["A"]);
+        assert!(imports("theorem foo : True := trivial\n").is_empty());
+        assert!(imports("").is_empty());
+        assert!(imports("import").is_empty());
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(imports("-- header\n/- a /- nested -/ b -/\nimport A -- trailing\n/-- doc -/\nimport B\n"), ["A", "B"]);
+        assert_eq!(imports("import A--no space\nimport/- inline -/B"), ["A", "B"]);
+        // a nested block closed only once is still open.
+        assert!(imports("/- a /- b -/ import A").is_empty());
+        assert_eq!(imports("import A /- never closed\nimport B"), ["A"]);
+        assert_eq!(imports("import A -- never ends"), ["A"]);
+    }
+}
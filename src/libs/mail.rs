@@ -0,0 +1,156 @@
+use core::{fmt::Write as _, time::Duration};
+use std::{process::Stdio, sync::LazyLock, time::SystemTime};
+
+use compact_str::CompactString;
+use dashmap::DashMap;
+use hashbrown::DefaultHashBuilder;
+use smallvec::SmallVec;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    process::Command,
+};
+
+use super::{
+    error::BoxedStdError,
+    token::{self, Purpose},
+};
+
+/// One of
+/// - `smtp://<host>:<port>`, a relay accepting unauthenticated plain SMTP (typically a local MTA);
+/// - `sendmail:<path>`, a `sendmail`-compatible program (e.g. `msmtp` for authenticated or TLS relays);
+/// - `file:<dir>`, drops every message into `<dir>` for development.
+pub const TRANSPORT: &str = option_env!("MAIL_TRANSPORT").unwrap_or(concat!("file:", env!("LEAN4OJ_RSYNC_TMPDIR"), "/mail"));
+pub const FROM: &str = option_env!("MAIL_FROM").unwrap_or("noreply@localhost");
+/// Base of the links in mails, without the trailing `/`.
+pub const SITE_URL: &str = option_env!("SITE_URL").unwrap_or("http://localhost");
+
+const COOLDOWN: Duration = Duration::from_mins(1);
+const WINDOW: Duration = Duration::from_hours(1);
+const PER_WINDOW: usize = 5;
+
+static SENT: LazyLock<DashMap<CompactString, SmallVec<[SystemTime; PER_WINDOW]>, DefaultHashBuilder>> = LazyLock::new(|| DashMap::with_hasher(DefaultHashBuilder::default()));
+
+/// Addresses nothing was sent to within [`WINDOW`] are swept once there are this many.
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Reserves a send to `to`: at most one per [`COOLDOWN`] and [`PER_WINDOW`] per [`WINDOW`].
+pub fn acquire(to: &str) -> bool {
+    let now = SystemTime::now();
+    if SENT.len() >= PRUNE_THRESHOLD {
+        SENT.retain(|_, sent| sent.last().is_some_and(|t| now.duration_since(*t).is_ok_and(|d| d < WINDOW)));
+    }
+    let mut sent = SENT.entry(to.to_ascii_lowercase().into()).or_default();
+    sent.retain(|t| now.duration_since(*t).is_ok_and(|d| d < WINDOW));
+    if sent.len() >= PER_WINDOW || sent.last().is_some_and(|t| now.duration_since(*t).is_ok_and(|d| d < COOLDOWN)) {
+        return false;
+    }
+    sent.push(now);
+    true
+}
+
+fn compose(to: &str, subject: &str, body: &str) -> String {
+    let mut message = String::with_capacity(body.len() + 256);
+    let _ = write!(
+        &mut message,
+        "From: {FROM}\r\nTo: {to}\r\nSubject: {subject}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+    );
+    for line in body.lines() {
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+async fn smtp(addr: &str, to: &str, message: &str) -> Result<(), BoxedStdError> {
+    async fn reply(reader: &mut BufReader<TcpStream>, expect: u8) -> Result<(), BoxedStdError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 { return Err("SMTP connection closed".into()); }
+            if line.len() < 4 || line.as_bytes()[0] != expect { return Err(format!("SMTP: {}", line.trim_end()).into()); }
+            if line.as_bytes()[3] != b'-' { return Ok(()); }
+        }
+    }
+
+    let mut conn = BufReader::new(TcpStream::connect(addr).await?);
+    reply(&mut conn, b'2').await?;
+    for (command, expect) in [
+        ("EHLO localhost\r\n".into(), b'2'),
+        (format!("MAIL FROM:<{FROM}>\r\n"), b'2'),
+        (format!("RCPT TO:<{to}>\r\n"), b'2'),
+        ("DATA\r\n".into(), b'3'),
+    ] {
+        conn.get_mut().write_all(command.as_bytes()).await?;
+        reply(&mut conn, expect).await?;
+    }
+    // dot-stuffing, the message starts with headers so only line starts after a CRLF matter.
+    conn.get_mut().write_all(message.replace("\r\n.", "\r\n..").as_bytes()).await?;
+    conn.get_mut().write_all(b".\r\n").await?;
+    reply(&mut conn, b'2').await?;
+    conn.get_mut().write_all(b"QUIT\r\n").await?;
+    Ok(())
+}
+
+async fn sendmail(program: &str, message: &str) -> Result<(), BoxedStdError> {
+    let mut child = Command::new(program).args(["-t", "-i"]).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes()).await?;
+    }
+    let status = child.wait().await?;
+    if status.success() { Ok(()) } else { Err(format!("sendmail: {status}").into()) }
+}
+
+async fn file(dir: &str, message: &str) -> Result<(), BoxedStdError> {
+    let time = SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default().as_nanos();
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(format!("{dir}/{time}.eml"), message).await?;
+    Ok(())
+}
+
+pub async fn send(to: &str, subject: &str, body: &str) -> Result<(), BoxedStdError> {
+    if to.contains(['\r', '\n', '<', '>']) { return Err("invalid recipient".into()); }
+    let message = compose(to, subject, body);
+    if let Some(addr) = TRANSPORT.strip_prefix("smtp://") {
+        smtp(addr, to, &message).await
+    } else if let Some(program) = TRANSPORT.strip_prefix("sendmail:") {
+        sendmail(program, &message).await
+    } else if let Some(dir) = TRANSPORT.strip_prefix("file:") {
+        file(dir, &message).await
+    } else {
+        Err(format!("unknown mail transport {TRANSPORT}").into())
+    }
+}
+
+/// Sends in the background, failures are only logged.
+pub fn spawn(to: CompactString, subject: &'static str, body: String) {
+    tokio::spawn(async move {
+        if let Err(e) = send(&to, subject, &body).await {
+            tracing::warn!(target: "mail", "failed to send {subject:?} to {to}: {e}");
+        }
+    });
+}
+
+/// Mails a link verifying `email` as that of `uid`, `false` if rate limited.
+pub fn send_email_verification(uid: &str, username: &str, email: &str) -> bool {
+    if !acquire(email) { return false; }
+    let Some(token) = token::issue(Purpose::VerifyEmail, uid, email.as_bytes()) else { return false };
+    let body = format!(
+        "Hello {username},\n\nTo verify the email address of your Lean4OJ account, open\n\n{SITE_URL}/verify-email?token={token}\n\nThe link expires in 24 hours. If you did not register, ignore this mail.\n",
+    );
+    spawn(email.into(), "Verify your email address", body);
+    true
+}
+
+/// Mails a link resetting the password of `uid`, usable once (it is bound to the current `password` hash).
+///
+/// Unlike [`send_email_verification`], the send must already be [`acquire`]d: that has to happen whether or not the
+/// address belongs to anyone.
+pub fn send_password_reset(uid: &str, username: &str, email: &str, password: &str) -> bool {
+    let Some(token) = token::issue(Purpose::ResetPassword, uid, password.as_bytes()) else { return false };
+    let body = format!(
+        "Hello {username},\n\nTo reset the password of your Lean4OJ account, open\n\n{SITE_URL}/reset-password?token={token}\n\nThe link expires in 1 hour. If you did not ask for this, ignore this mail.\n",
+    );
+    spawn(email.into(), "Reset your password", body);
+    true
+}
@@ -0,0 +1,564 @@
+use core::time::Duration;
+use std::{
+    fs,
+    io::ErrorKind,
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use compact_str::CompactString;
+use openssl::sha::sha256;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_postgres::Client;
+
+use super::{
+    db::DBResult,
+    error::BoxedStdError,
+    fetch::fetch,
+    mail::SITE_URL,
+};
+
+/// A JSON array of [`Provider`]s, OAuth login being off if there is no such file.
+const PROVIDERS_PATH: &str = option_env!("OAUTH_PROVIDERS").unwrap_or("/usr/local/nginx/conf/oauth.json");
+/// How long the user may take at the provider.
+const PENDING_TTL: Duration = Duration::from_mins(10);
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// GitHub OAuth apps, which are plain OAuth 2.0 (no ID tokens), with verified emails at `/user/emails`.
+    Github,
+}
+
+struct Endpoints {
+    authorization: String,
+    token: String,
+    userinfo: Option<String>,
+}
+
+/// An OAuth 2.0 authorization server, or an OpenID Connect provider if it has an `issuer`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provider {
+    /// What the provider is known as in requests and `oauth_links`.
+    pub id: CompactString,
+    pub name: CompactString,
+    pub preset: Option<Preset>,
+    /// The `iss` of ID tokens, the endpoints not given being discovered from it.
+    issuer: Option<String>,
+    authorization_endpoint: Option<String>,
+    token_endpoint: Option<String>,
+    userinfo_endpoint: Option<String>,
+    /// A list of `{email, primary, verified}`, for providers whose userinfo does not tell whether the email is verified.
+    emails_endpoint: Option<String>,
+    client_id: String,
+    client_secret: String,
+    /// Space separated.
+    scopes: Option<String>,
+    redirect_uri: Option<String>,
+    #[serde(skip)]
+    endpoints: OnceLock<Endpoints>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+impl Provider {
+    fn fill(&mut self) {
+        if self.preset == Some(Preset::Github) {
+            self.authorization_endpoint.get_or_insert_with(|| "https://github.com/login/oauth/authorize".into());
+            self.token_endpoint.get_or_insert_with(|| "https://github.com/login/oauth/access_token".into());
+            self.userinfo_endpoint.get_or_insert_with(|| "https://api.github.com/user".into());
+            self.emails_endpoint.get_or_insert_with(|| "https://api.github.com/user/emails".into());
+            self.scopes.get_or_insert_with(|| "read:user user:email".into());
+        }
+        self.scopes.get_or_insert_with(|| "openid email profile".into());
+        self.redirect_uri.get_or_insert_with(|| format!("{SITE_URL}/oauth/callback"));
+    }
+
+    fn redirect_uri(&self) -> &str {
+        self.redirect_uri.as_deref().unwrap_or_default()
+    }
+
+    /// The configured endpoints, completed by OpenID Connect discovery (done once, on first use).
+    async fn endpoints(&self) -> Result<&Endpoints, BoxedStdError> {
+        if let Some(endpoints) = self.endpoints.get() { return Ok(endpoints); }
+
+        let endpoints = if let (Some(authorization), Some(token)) = (&self.authorization_endpoint, &self.token_endpoint) {
+            Endpoints { authorization: authorization.clone(), token: token.clone(), userinfo: self.userinfo_endpoint.clone() }
+        } else {
+            let issuer = self.issuer.as_deref().ok_or_else(|| format!("{}: neither endpoints nor an issuer", self.id))?;
+            let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+            let discovery: Discovery = serde_json::from_value(get_json(&url, &[]).await?)?;
+            Endpoints {
+                authorization: self.authorization_endpoint.clone().unwrap_or(discovery.authorization_endpoint),
+                token: self.token_endpoint.clone().unwrap_or(discovery.token_endpoint),
+                userinfo: self.userinfo_endpoint.clone().or(discovery.userinfo_endpoint),
+            }
+        };
+        Ok(self.endpoints.get_or_init(|| endpoints))
+    }
+}
+
+static PROVIDERS: OnceLock<Vec<Provider>> = OnceLock::new();
+
+pub fn init() {
+    let providers = match fs::read(PROVIDERS_PATH) {
+        Ok(json) => {
+            let mut providers = serde_json::from_slice::<Vec<Provider>>(&json).unwrap();
+            providers.iter_mut().for_each(Provider::fill);
+            for (i, provider) in providers.iter().enumerate() {
+                assert!(providers[..i].iter().all(|p| p.id != provider.id), "duplicate OAuth provider {}", provider.id);
+            }
+            providers
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(e) => panic!("{PROVIDERS_PATH}: {e}"),
+    };
+    PROVIDERS.get_or_init(|| providers);
+}
+
+pub fn providers() -> &'static [Provider] {
+    PROVIDERS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+pub fn provider(id: &str) -> Option<&'static Provider> {
+    providers().iter().find(|p| p.id == id)
+}
+
+/// A login sent to the provider. Kept in `oauth_pending` until the provider redirects back, which may be to another
+/// instance.
+pub struct Pending {
+    state: String,
+    provider: &'static Provider,
+    verifier: String,
+    nonce: String,
+    link: Option<CompactString>,
+    created: SystemTime,
+}
+
+impl Pending {
+    fn expired(&self) -> bool {
+        !self.created.elapsed().is_ok_and(|d| d < PENDING_TTL)
+    }
+
+    /// Also sweeps abandoned logins.
+    pub async fn save(&self, db: &mut Client) -> DBResult<()> {
+        pub const SQL_PRUNE: &str = "delete from lean4oj.oauth_pending where create_time < $1";
+        pub const SQL: &str = "insert into lean4oj.oauth_pending (state, provider, verifier, nonce, link, create_time) values ($1, $2, $3, $4, $5, $6)";
+
+        let stmt = db.prepare_static(SQL_PRUNE.into()).await?;
+        db.execute(&stmt, &[&(self.created - PENDING_TTL)]).await?;
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let link = self.link.as_deref();
+        db.execute(&stmt, &[&self.state, &&*self.provider.id, &self.verifier, &self.nonce, &link, &self.created]).await?;
+        Ok(())
+    }
+
+    /// Takes the login of `state` out, each being good for one attempt. `None` if it is unknown, expired, or of a
+    /// provider no longer configured.
+    pub async fn take(state: &str, db: &mut Client) -> DBResult<Option<Self>> {
+        pub const SQL: &str = "delete from lean4oj.oauth_pending where state = $1 returning provider, verifier, nonce, link, create_time";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let Some(row) = db.query_opt(&stmt, &[&state]).await? else { return Ok(None) };
+        let Some(provider) = provider(row.try_get(0)?) else { return Ok(None) };
+        let pending = Self {
+            state: state.into(),
+            provider,
+            verifier: row.try_get(1)?,
+            nonce: row.try_get(2)?,
+            link: row.try_get::<_, Option<&str>>(3)?.map(Into::into),
+            created: row.try_get(4)?,
+        };
+        Ok((!pending.expired()).then_some(pending))
+    }
+}
+
+fn random() -> String {
+    let mut bytes = [0; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The `S256` code challenge of RFC 7636.
+fn pkce_challenge(verifier: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(sha256(verifier.as_bytes()))
+}
+
+/// The URL to send the user to, and the login to [`Pending::save`]. `link` is the user the account is to be linked to,
+/// if not logging in with it.
+pub async fn start(provider: &'static Provider, link: Option<CompactString>) -> Result<(String, Pending), BoxedStdError> {
+    let endpoints = provider.endpoints().await?;
+    let (state, verifier, nonce) = (random(), random(), random());
+
+    let mut url = endpoints.authorization.clone();
+    url.push(if url.contains('?') { '&' } else { '?' });
+    let start = url.len();
+    let mut query = form_urlencoded::Serializer::for_suffix(url, start);
+    query
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", provider.redirect_uri())
+        .append_pair("scope", provider.scopes.as_deref().unwrap_or_default())
+        .append_pair("state", &state)
+        .append_pair("code_challenge", &pkce_challenge(&verifier))
+        .append_pair("code_challenge_method", "S256");
+    if provider.issuer.is_some() { query.append_pair("nonce", &nonce); }
+    let url = query.finish();
+
+    Ok((url, Pending { state, provider, verifier, nonce, link, created: SystemTime::now() }))
+}
+
+/// Who the user is at the provider.
+pub struct Identity {
+    pub subject: String,
+    pub email: Option<String>,
+    /// Whether the provider vouches for `email`.
+    pub email_verified: bool,
+    pub username: Option<String>,
+}
+
+pub struct Login {
+    pub provider: &'static Provider,
+    pub identity: Identity,
+    /// As given to [`start`].
+    pub link: Option<CompactString>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    id_token: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Email {
+    email: String,
+    #[serde(default)]
+    primary: bool,
+    #[serde(default)]
+    verified: bool,
+}
+
+async fn get_json(url: &str, headers: &[(&str, &str)]) -> Result<Value, BoxedStdError> {
+    let mut all = vec![("Accept", "application/json")];
+    all.extend_from_slice(headers);
+    let res = fetch("GET", url, &all, None).await?;
+    if !res.is_success() { return Err(format!("GET {url}: {}", res.status).into()); }
+    Ok(serde_json::from_slice(&res.body)?)
+}
+
+/// `sub` (or GitHub's `id`), `email`, `email_verified` and `preferred_username` (or GitHub's `login`).
+fn identity_of(claims: &Value) -> Option<Identity> {
+    let subject = match claims.get("sub").or_else(|| claims.get("id"))? {
+        Value::String(s) if !s.is_empty() => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    let email = claims.get("email").and_then(Value::as_str).map(Into::into);
+    let email_verified = match claims.get("email_verified") {
+        Some(Value::Bool(b)) => *b,
+        // some providers send it as a string.
+        Some(Value::String(s)) => s == "true",
+        _ => false,
+    };
+    let username = claims.get("preferred_username").or_else(|| claims.get("login")).and_then(Value::as_str).map(Into::into);
+    Some(Identity { subject, email, email_verified, username })
+}
+
+/// The ID token came straight from the token endpoint, so its signature need not be checked (OpenID Connect Core
+/// 3.1.3.7), but everything else is.
+fn id_token(provider: &Provider, issuer: &str, token: &str, nonce: &str) -> Result<Identity, BoxedStdError> {
+    let payload = token.split('.').nth(1).ok_or("malformed ID token")?;
+    let claims: Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload.trim_end_matches('='))?)?;
+
+    if claims["iss"].as_str() != Some(issuer) { return Err("ID token of another issuer".into()); }
+    let audience = match &claims["aud"] {
+        Value::String(aud) => *aud == provider.client_id,
+        Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(provider.client_id.as_str())),
+        _ => false,
+    };
+    if !audience { return Err("ID token for another client".into()); }
+    let now = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+    if claims["exp"].as_u64().is_none_or(|exp| exp <= now) { return Err("expired ID token".into()); }
+    if claims["nonce"].as_str() != Some(nonce) { return Err("ID token of another login".into()); }
+    identity_of(&claims).ok_or_else(|| "ID token without a subject".into())
+}
+
+/// Redeems the `code` the provider redirected back with, for the login taken by [`Pending::take`].
+pub async fn complete(pending: Pending, code: &str) -> Result<Login, BoxedStdError> {
+    let provider = pending.provider;
+    let endpoints = provider.endpoints().await?;
+
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("grant_type", "authorization_code")
+        .append_pair("code", code)
+        .append_pair("redirect_uri", provider.redirect_uri())
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("client_secret", &provider.client_secret)
+        .append_pair("code_verifier", &pending.verifier)
+        .finish();
+    let headers = [("Accept", "application/json"), ("Content-Type", "application/x-www-form-urlencoded")];
+    let res = fetch("POST", &endpoints.token, &headers, Some(body.into_bytes())).await?;
+    // GitHub reports errors with 200.
+    let tokens: TokenResponse = serde_json::from_slice(&res.body)?;
+    let access_token = match tokens {
+        TokenResponse { access_token: Some(token), error: None, .. } if res.is_success() => token,
+        TokenResponse { error, .. } => {
+            return Err(format!("{}: token request failed ({}, {})", provider.id, res.status, error.unwrap_or_default()).into());
+        }
+    };
+
+    let mut identity = match (&tokens.id_token, &provider.issuer) {
+        (Some(token), Some(issuer)) => Some(id_token(provider, issuer, token, &pending.nonce)?),
+        _ => None,
+    };
+    let bearer = format!("Bearer {access_token}");
+    let auth = [("Authorization", &*bearer)];
+
+    if identity.as_ref().is_none_or(|identity| !identity.email_verified) && let Some(userinfo) = &endpoints.userinfo {
+        let info = identity_of(&get_json(userinfo, &auth).await?).ok_or("userinfo without a subject")?;
+        match &mut identity {
+            Some(identity) => {
+                if identity.subject != info.subject { return Err("userinfo of another subject".into()); }
+                if info.email.is_some() {
+                    identity.email = info.email;
+                    identity.email_verified = info.email_verified;
+                }
+                if identity.username.is_none() { identity.username = info.username; }
+            }
+            None => identity = Some(info),
+        }
+    }
+    let mut identity = identity.ok_or_else(|| format!("{}: no ID token nor userinfo", provider.id))?;
+
+    if !identity.email_verified && let Some(emails) = &provider.emails_endpoint {
+        let emails: Vec<Email> = serde_json::from_value(get_json(emails, &auth).await?)?;
+        if let Some(email) = emails.into_iter().filter(|e| e.verified).max_by_key(|e| e.primary) {
+            identity.email = Some(email.email);
+            identity.email_verified = true;
+        }
+    }
+
+    Ok(Login { provider, identity, link: pending.link })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+    use serde_json::{Value, json};
+
+    use super::{Provider, complete, pkce_challenge, start};
+
+    /// What the identity provider remembers of the authorization request, and how it misbehaves.
+    #[derive(Default)]
+    struct Idp {
+        base: String,
+        challenge: String,
+        nonce: String,
+        audience: String,
+    }
+
+    fn id_token(claims: &Value) -> String {
+        let encode = |v: &Value| BASE64_URL_SAFE_NO_PAD.encode(v.to_string());
+        format!("{}.{}.c2ln", encode(&json!({ "alg": "RS256" })), encode(claims))
+    }
+
+    fn respond(idp: &Mutex<Idp>, method: &str, path: &str, headers: &str, body: &[u8]) -> (u16, Value) {
+        let idp = idp.lock().unwrap();
+        let authorized = headers.lines().any(|line| line.eq_ignore_ascii_case("authorization: Bearer access"));
+        match (method, path) {
+            ("GET", "/.well-known/openid-configuration") => (200, json!({
+                "issuer": idp.base,
+                "authorization_endpoint": format!("{}/authorize", idp.base),
+                "token_endpoint": format!("{}/token", idp.base),
+                "userinfo_endpoint": format!("{}/userinfo", idp.base),
+            })),
+            ("POST", "/token") => {
+                let form = form_urlencoded::parse(body).into_owned().collect::<Vec<_>>();
+                let get = |key: &str| form.iter().find(|(k, _)| k == key).map_or("", |(_, v)| v.as_str());
+                if get("grant_type") != "authorization_code"
+                || get("code") != "code"
+                || get("client_id") != "lean4oj"
+                || get("client_secret") != "secret"
+                || get("redirect_uri").is_empty()
+                || pkce_challenge(get("code_verifier")) != idp.challenge {
+                    return (400, json!({ "error": "invalid_grant" }));
+                }
+                let id_token = id_token(&json!({
+                    "iss": idp.base,
+                    "aud": [idp.audience, "another"],
+                    "exp": 4_000_000_000_u64,
+                    "nonce": idp.nonce,
+                    "sub": "alice-sub",
+                    "preferred_username": "alice",
+                }));
+                (200, json!({ "access_token": "access", "token_type": "Bearer", "id_token": id_token }))
+            }
+            ("GET", "/userinfo") if authorized => (200, json!({ "sub": "alice-sub", "email": "alice@example.com", "email_verified": true })),
+            ("GET", "/user") if authorized => (200, json!({ "id": 42, "login": "alice", "email": null })),
+            ("GET", "/user/emails") if authorized => (200, json!([
+                { "email": "old@example.com", "primary": false, "verified": true },
+                { "email": "alice@example.com", "primary": true, "verified": true },
+                { "email": "unverified@example.com", "primary": false, "verified": false },
+            ])),
+            _ => (404, json!({ "error": "not_found" })),
+        }
+    }
+
+    /// A local identity provider, speaking just enough HTTP for [`super::fetch`].
+    fn idp() -> Arc<Mutex<Idp>> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let idp = Arc::new(Mutex::new(Idp { base, audience: "lean4oj".into(), ..Idp::default() }));
+        let shared = idp.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut buf = Vec::new();
+                let mut chunk = [0; 4096];
+                let end = loop {
+                    let Ok(n @ 1..) = stream.read(&mut chunk) else { break None };
+                    buf.extend_from_slice(&chunk[..n]);
+                    let Some(end) = memchr::memmem::find(&buf, b"\r\n\r\n") else { continue };
+                    let head = String::from_utf8_lossy(&buf[..end]).into_owned();
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                        .unwrap_or(0);
+                    if buf.len() >= end + 4 + length { break Some((head, end + 4)); }
+                };
+                let Some((head, end)) = end else { continue };
+                let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+                let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+                let (status, body) = respond(&shared, method, path, &head, &buf[end..]);
+                let body = body.to_string();
+                let _ = write!(stream, "HTTP/1.0 {status} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+            }
+        });
+        idp
+    }
+
+    fn provider(config: Value) -> &'static Provider {
+        let mut provider = serde_json::from_value::<Provider>(config).unwrap();
+        provider.fill();
+        Box::leak(Box::new(provider))
+    }
+
+    fn oidc(idp: &Mutex<Idp>) -> &'static Provider {
+        let issuer = idp.lock().unwrap().base.clone();
+        provider(json!({ "id": "oidc", "name": "OIDC", "issuer": issuer, "clientId": "lean4oj", "clientSecret": "secret" }))
+    }
+
+    /// Plays the user agent: remembers what the provider would of the authorization URL, returns the `state`.
+    fn authorize(idp: &Mutex<Idp>, url: &str) -> String {
+        let mut idp = idp.lock().unwrap();
+        assert!(url.starts_with(&format!("{}/", idp.base)));
+        let query = url.split_once('?').unwrap().1;
+        let mut state = None;
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "state" => state = Some(value.into_owned()),
+                "code_challenge" => idp.challenge = value.into_owned(),
+                "code_challenge_method" => assert_eq!(value, "S256"),
+                "nonce" => idp.nonce = value.into_owned(),
+                _ => {}
+            }
+        }
+        state.unwrap()
+    }
+
+    #[test]
+    fn pkce_rfc7636_example() {
+        assert_eq!(pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+
+    #[tokio::test]
+    async fn oidc_login() {
+        let idp = idp();
+        let provider = oidc(&idp);
+        let (url, pending) = start(provider, Some("bob".into())).await.unwrap();
+        assert!(url.contains("scope=openid+email+profile"));
+        assert_eq!(authorize(&idp, &url), pending.state);
+
+        let login = complete(pending, "code").await.unwrap();
+        assert_eq!(login.provider.id, "oidc");
+        assert_eq!(login.link.as_deref(), Some("bob"));
+        assert_eq!(login.identity.subject, "alice-sub");
+        assert_eq!(login.identity.username.as_deref(), Some("alice"));
+        // not in the ID token, from userinfo.
+        assert_eq!(login.identity.email.as_deref(), Some("alice@example.com"));
+        assert!(login.identity.email_verified);
+    }
+
+    #[tokio::test]
+    async fn wrong_code_verifier() {
+        let idp = idp();
+        let (url, pending) = start(oidc(&idp), None).await.unwrap();
+        authorize(&idp, &url);
+        idp.lock().unwrap().challenge = pkce_challenge("another verifier");
+        assert!(complete(pending, "code").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn wrong_audience() {
+        let idp = idp();
+        let (url, pending) = start(oidc(&idp), None).await.unwrap();
+        authorize(&idp, &url);
+        idp.lock().unwrap().audience = "someone-else".into();
+        assert!(complete(pending, "code").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn wrong_nonce() {
+        let idp = idp();
+        let (url, pending) = start(oidc(&idp), None).await.unwrap();
+        authorize(&idp, &url);
+        idp.lock().unwrap().nonce = "another login".into();
+        assert!(complete(pending, "code").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn github_login() {
+        let idp = idp();
+        let base = idp.lock().unwrap().base.clone();
+        let provider = provider(json!({
+            "id": "github",
+            "name": "GitHub",
+            "preset": "github",
+            "authorizationEndpoint": format!("{base}/login/oauth/authorize"),
+            "tokenEndpoint": format!("{base}/token"),
+            "userinfoEndpoint": format!("{base}/user"),
+            "emailsEndpoint": format!("{base}/user/emails"),
+            "clientId": "lean4oj",
+            "clientSecret": "secret",
+        }));
+        let (url, pending) = start(provider, None).await.unwrap();
+        assert!(url.contains("scope=read%3Auser+user%3Aemail"));
+        assert!(!url.contains("nonce="));
+        authorize(&idp, &url);
+
+        // the ID token the mock sends anyway is ignored without an issuer.
+        let login = complete(pending, "code").await.unwrap();
+        assert_eq!(login.identity.subject, "42");
+        assert_eq!(login.identity.username.as_deref(), Some("alice"));
+        assert_eq!(login.identity.email.as_deref(), Some("alice@example.com"));
+        assert!(login.identity.email_verified);
+    }
+}
@@ -1,13 +1,19 @@
-#![allow(clippy::cast_ptr_alignment)]
 #![cfg(target_pointer_width = "64")]
 
 #[cfg(test)]
 use core::fmt;
+use core::str::pattern::{Pattern, Searcher};
 use std::sync::OnceLock;
 
 use compact_str::CompactString;
 use hashbrown::HashMap;
 
+use super::validate::is_lean_id;
+
+pub const SINGLE_FILE_LIMIT: usize = 0x100_0000; // 16 MB
+pub const TOTAL_FILE_LIMIT: usize = 0x4000_0000; // 1 GB
+pub const TOTAL_FILE_NUM: usize = 0x10_0000; // 1 M
+
 const DATA: [(&[u8], &[u8; 40]); 4] = [
     (b".26.0", b"d8204c9fd894f91bbb2cdfec5912ec8196fd8562"),
     (b".27.0-rc1", b"2fcce7258eeb6e324366bc25f9058293b04b7547"),
@@ -41,11 +47,59 @@ pub fn init() {
     ACCEPTABLE_VERSIONS.get_or_init(|| HashMap::from(DATA));
 }
 
+#[inline(always)]
+fn acceptable_versions() -> &'static HashMap<&'static [u8], &'static [u8; 40]> {
+    #[cfg(feature = "build-std")]
+    unsafe { ACCEPTABLE_VERSIONS.get_unchecked() }
+    #[cfg(not(feature = "build-std"))]
+    unsafe { ACCEPTABLE_VERSIONS.get().unwrap_unchecked() }
+}
+
+/// `version` is the full one (e.g. `4.26.0`), returns the stored form without the leading `4`.
+pub fn acceptable_version(version: &str) -> Option<&'static str> {
+    let (&ver, _) = acceptable_versions().get_key_value(version.strip_prefix('4')?.as_bytes())?;
+    Some(unsafe { core::str::from_utf8_unchecked(ver) })
+}
+
 #[inline]
 pub fn is_std(module: &str) -> bool {
     STD.into_iter().any(|s| *module == *s || module.strip_prefix(s).is_some_and(|t| t.starts_with('.')))
 }
 
+/// `suffix` is the path relative to `<uid>/`, e.g. `Foo/Bar.olean`.
+pub fn check_suffix(suffix: &str) -> bool {
+    if let Some(a) = suffix.strip_suffix(".ir") {
+        return a.split('/').all(is_lean_id)
+    }
+    let b = if let Some(a) = suffix.strip_suffix(".server") { a }
+    else if let Some(a) = suffix.strip_suffix(".private") { a }
+    else { suffix };
+    b.strip_suffix(".olean").is_some_and(|c| c.split('/').all(is_lean_id))
+    // Force `Name.needsNoEscape`, prevent the outrageous case like `import «foo.olean».bar`.
+}
+
+pub fn check_prefix(prefix: &[u8]) -> bool {
+    const B: &[u8] = b"/.lake/build/lib/lean";
+    if prefix.len() <= B.len() { B.ends_with(prefix) } else { prefix.ends_with(B) }
+}
+
+/// `path` is as sent by the client (e.g. `.lake/build/lib/lean/<uid>/Foo.olean`), returns the offset of the part
+/// relative to `<uid>/`, or 0 if the file should be ignored.
+pub fn check_path(path: &[u8], uid_with_slash: &str) -> usize {
+    // names in tar headers and rsync file lists are arbitrary bytes.
+    let Ok(path) = str::from_utf8(path) else { return 0 };
+    let uid_with_trailing_slash = unsafe { uid_with_slash.get_unchecked(1..) };
+    if let Some(suffix) = path.strip_prefix(uid_with_trailing_slash) {
+        if check_suffix(suffix) { uid_with_trailing_slash.len() } else { 0 } // > 0.
+    } else {
+        let mut ss = uid_with_slash.into_searcher(path);
+        let Some((s, t)) = ss.next_match() else { return 0 }; // t > 0.
+        let prefix = unsafe { path.get_unchecked(..s) };
+        let suffix = unsafe { path.get_unchecked(t..) };
+        if check_prefix(prefix.as_bytes()) && check_suffix(suffix) { t } else { 0 }
+    }
+}
+
 pub fn lean_version_80(header: &[u8; 80]) -> Option<&'static str> {
     const MAGIC: &[u8; 8] = b"olean\x02\x014";
     if unsafe { *header.as_ptr().cast_array() != *MAGIC } { return None; }
@@ -53,13 +107,7 @@ pub fn lean_version_80(header: &[u8; 80]) -> Option<&'static str> {
     let tail: &[u8; 40] = unsafe { &*header.as_ptr().add(40).cast_array() };
     let len = middle.iter().rposition(|&x| x != 0).map_or_default(|x| x + 1);
     let ver_shortlived = unsafe { middle.get_unchecked(..len) };
-    let map = {
-        #[cfg(feature = "build-std")]
-        unsafe { ACCEPTABLE_VERSIONS.get_unchecked() }
-        #[cfg(not(feature = "build-std"))]
-        unsafe { ACCEPTABLE_VERSIONS.get().unwrap_unchecked() }
-    };
-    let (&ver_longlived, &hash) = map.get_key_value(ver_shortlived)?;
+    let (&ver_longlived, &hash) = acceptable_versions().get_key_value(ver_shortlived)?;
     (*tail == *hash).then_some(unsafe { core::str::from_utf8_unchecked(ver_longlived) })
 }
 
@@ -86,7 +134,7 @@ pub struct OleanMeta<'a> {
     data: &'a [u8],
     pub version: &'static str,
     base: usize,
-    sections: &'a [usize; 7],
+    sections: [usize; 7],
 }
 
 impl OleanMeta<'_> {
@@ -122,12 +170,13 @@ impl fmt::Debug for OleanMeta<'_> {
 }
 
 mod detail {
-    use core::{slice, str};
 
     use compact_str::CompactString;
 
     use super::super::validate::is_lean_id;
 
+    const MAX_NAME_DEPTH: usize = 64;
+
     #[inline]
     fn is_hcongr_reserved_name_suffix(s: &str) -> bool {
         if let Some(suffix) = s.strip_prefix("hcongr_") {
@@ -148,64 +197,78 @@ mod detail {
         is_hcongr_reserved_name_suffix(s)
     }
 
-    pub(super) fn array(payload: &[u8], offset: usize) -> Option<&[usize]> {
-        let header = unsafe { &*payload.get(offset..offset + 24)?.as_ptr().cast::<[usize; 3]>() };
-        if header[0] != 0xf600_0001_0000_0000 || header[1] != header[2] { return None; }
-        let n = header[1];
+    /// The payload comes from arbitrary users, never assume it is aligned nor in range.
+    #[inline]
+    pub(super) fn word(payload: &[u8], p: usize) -> Option<usize> {
+        payload.get(p..p.checked_add(8)?)?.as_array().copied().map(usize::from_le_bytes)
+    }
+
+    pub(super) fn array(payload: &[u8], offset: usize) -> Option<&[[u8; 8]]> {
+        if word(payload, offset)? != 0xf600_0001_0000_0000 { return None; }
+        let n = word(payload, offset + 8)?;
+        if word(payload, offset + 16)? != n { return None; }
         let start = offset + 24;
-        if start.checked_add(n.checked_mul(8)?)? > payload.len() { return None; }
-        Some(unsafe { slice::from_raw_parts(payload.as_ptr().add(start).cast::<usize>(), n) })
+        let raw = payload.get(start..start.checked_add(n.checked_mul(8)?)?)?;
+        Some(raw.as_chunks().0)
     }
 
     pub(super) fn str(payload: &[u8], base: usize, addr: usize) -> Option<&str> {
-        let p = addr - base;
-        let header = unsafe { &*payload.get(p..p + 24)?.as_ptr().cast::<[usize; 3]>() };
-        if header[0] != 0xf900_0001_0000_0000 || header[1] != header[2] { return None; }
-        let n = header[1];
-        let raw = payload.get(p + 32..(p + 32).checked_add(n)?)?;
+        let p = addr.checked_sub(base)?;
+        if word(payload, p)? != 0xf900_0001_0000_0000 { return None; }
+        let n = word(payload, p + 8)?;
+        if word(payload, p + 16)? != n { return None; }
+        let start = p.checked_add(32)?;
+        let raw = payload.get(start..start.checked_add(n)?)?;
         str::from_utf8(raw.strip_suffix(b"\0")?).ok()
     }
 
-    pub(super) fn name(payload: &[u8], base: usize, addr: usize) -> Option<CompactString> {
-        let p = addr - base;
-        let header = unsafe { &*payload.get(p..p + 32)?.as_ptr().cast::<[usize; 4]>() };
-        if header[0] != 0x102_0020_0000_0000 { return None; }
-        let recur = header[1];
+    fn name_inner(payload: &[u8], base: usize, addr: usize, depth: usize) -> Option<CompactString> {
+        // a crafted prefix may point to itself.
+        if depth == 0 { return None; }
+        let p = addr.checked_sub(base)?;
+        if word(payload, p)? != 0x102_0020_0000_0000 { return None; }
+        let recur = word(payload, p + 8)?;
         let mut ret = if recur == 1 {
             CompactString::default()
         } else {
-            let mut prefix = name(payload, base, recur)?;
+            let mut prefix = name_inner(payload, base, recur, depth - 1)?;
             prefix.push('.');
             prefix
         };
-        let strg = header[2];
+        let strg = word(payload, p + 16)?;
         let last = str(payload, base, strg)?;
         if !is_lean_id(last) || is_internal(last) { return None; }
 
         ret.push_str(last);
         Some(ret)
     }
+
+    #[inline]
+    pub(super) fn name(payload: &[u8], base: usize, addr: usize) -> Option<CompactString> {
+        name_inner(payload, base, addr, MAX_NAME_DEPTH)
+    }
 }
 
 pub fn parse_meta(payload: &[u8]) -> Option<OleanMeta<'_>> {
     let version = lean_version(payload)?;
 
-    let addr = usize::from_le_bytes(*payload.get(88..96)?.as_array()?);
-    let base = usize::from_le_bytes(unsafe { *payload.as_ptr().add(80).cast_array() });
-    let offset = addr - base;
-    if offset + 56 != payload.len() { return None; }
-    let sections = unsafe { &*payload.as_ptr().add(offset).cast::<[usize; 7]>() };
+    let base = detail::word(payload, 80)?;
+    let addr = detail::word(payload, 88)?;
+    let offset = addr.checked_sub(base)?;
+    if offset.checked_add(56)? != payload.len() { return None; }
+    let raw: &[[u8; 8]; 7] = payload.get(offset..)?.as_chunks::<8>().0.try_into().ok()?;
+    let sections = raw.map(usize::from_le_bytes);
     if sections[0] != 0x5_0038_0000_0000 { return None; }
 
     Some(OleanMeta { data: payload, version, base, sections })
 }
 
 pub fn parse_consts(OleanMeta { data, base, sections, .. }: OleanMeta<'_>) -> Option<Vec<CompactString>> {
-    let raw = detail::array(data, sections[2] - base)?;
+    let raw = detail::array(data, sections[2].checked_sub(base)?)?;
 
     let mut consts = Vec::with_capacity(raw.len());
     for &raw_const in raw {
-        if let Some(name) = detail::name(data, base, raw_const) {
+        if let Some(name) = detail::name(data, base, usize::from_le_bytes(raw_const)) {
             consts.push(name);
         }
     }
@@ -215,12 +278,12 @@ pub fn parse_consts(OleanMeta { data, base, sections, .. }: OleanMeta<'_>) -> Op
 }
 
 pub fn parse_imports(OleanMeta { data, base, sections, .. }: OleanMeta<'_>) -> Option<Vec<CompactString>> {
-    let raw = detail::array(data, sections[1] - base)?;
+    let raw = detail::array(data, sections[1].checked_sub(base)?)?;
 
     let mut imports = Vec::with_capacity(raw.len());
     for &raw_import in raw {
-        let p = raw_import - base;
-        let ind = usize::from_le_bytes(*data.get(p + 8..p + 16)?.as_array()?);
+        let p = usize::from_le_bytes(raw_import).checked_sub(base)?;
+        let ind = detail::word(data, p.checked_add(8)?)?;
         if let Some(name) = detail::name(data, base, ind) {
             imports.push(name);
         }
@@ -231,24 +294,161 @@ pub fn parse_imports(OleanMeta { data, base, sections, .. }: OleanMeta<'_>) -> O
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::fs;
 
-    use super::{init, parse_consts, parse_imports, parse_meta};
+    use openssl::sha::sha256;
+
+    use super::{DATA, check_path, init, lean_version, parse_consts, parse_imports, parse_meta};
+
+    const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/olean");
+    const BASE: usize = 0x7f00_0000_0000;
+
+    fn push_words(buf: &mut Vec<u8>, words: &[usize]) -> usize {
+        let addr = BASE + buf.len();
+        for w in words { buf.extend_from_slice(&w.to_le_bytes()); }
+        addr
+    }
+
+    fn push_str(buf: &mut Vec<u8>, s: &str) -> usize {
+        let addr = push_words(buf, &[0xf900_0001_0000_0000, s.len() + 1, s.len() + 1, s.chars().count()]);
+        buf.extend_from_slice(s.as_bytes());
+        buf.push(0);
+        buf.resize(buf.len().next_multiple_of(8), 0);
+        addr
+    }
+
+    fn push_name(buf: &mut Vec<u8>, name: &str) -> usize {
+        name.split('.').fold(1, |prefix, part| {
+            let s = push_str(buf, part);
+            push_words(buf, &[0x102_0020_0000_0000, prefix, s, 0])
+        })
+    }
+
+    fn push_array(buf: &mut Vec<u8>, addrs: &[usize]) -> usize {
+        let addr = push_words(buf, &[0xf600_0001_0000_0000, addrs.len(), addrs.len()]);
+        push_words(buf, addrs);
+        addr
+    }
+
+    /// Mimics the layout of a compacted region, only the parts we actually read.
+    fn synthesize(version: &[u8], hash: &[u8; 40], imports: &[&str], consts: &[&str], is_module: bool) -> Vec<u8> {
+        let mut buf = b"olean\x02\x014".to_vec();
+        buf.extend_from_slice(version);
+        buf.resize(40, 0);
+        buf.extend_from_slice(hash);
+        push_words(&mut buf, &[BASE, 0]);
+        let imports = imports.iter().map(|m| {
+            let name = push_name(&mut buf, m);
+            push_words(&mut buf, &[0, name, 0])
+        }).collect::<Vec<_>>();
+        let consts = consts.iter().map(|c| push_name(&mut buf, c)).collect::<Vec<_>>();
+        let imports = push_array(&mut buf, &imports);
+        let consts = push_array(&mut buf, &consts);
+        let root = push_words(&mut buf, &[0x5_0038_0000_0000, imports, consts, 0, 0, 0, usize::from(is_module)]);
+        buf[88..96].copy_from_slice(&root.to_le_bytes());
+        buf
+    }
+
+    /// Of the first supported version, for tests elsewhere.
+    pub(crate) fn synthesize_module(imports: &[&str], consts: &[&str]) -> Vec<u8> {
+        let (version, hash) = DATA[0];
+        synthesize(version, hash, imports, consts, false)
+    }
+
+    fn sample() -> Vec<u8> {
+        let (version, hash) = DATA[0];
+        synthesize(version, hash, &["Mathlib.Tactic", "Init"], &["Foo.bar", "Foo.bar.proof_1", "Foo.«baz»"], true)
+    }
 
-    const OLEANS: [&str; 0] = [
-    ];
+    fn parse_all(payload: &[u8]) {
+        if let Some(meta) = parse_meta(payload) {
+            let _ = parse_consts(meta);
+            let _ = parse_imports(meta);
+        }
+    }
 
     #[test]
     fn test_parse() {
         init();
 
-        for path in OLEANS {
-            println!("{path}");
-            let olean = fs::read(path).unwrap();
-            let Some(meta) = parse_meta(&olean) else { continue };
-            dbg!(parse_consts(meta));
-            dbg!(parse_imports(meta));
+        for (version, hash) in DATA {
+            let olean = synthesize(version, hash, &["Mathlib.Tactic", "Init", "Init"], &["Foo.bar", "Foo.bar.proof_1", "Foo.baz"], true);
+            assert_eq!(lean_version(&olean).map(str::as_bytes), Some(version));
+            let meta = parse_meta(&olean).unwrap();
+            assert!(meta.is_module());
+            assert_eq!(parse_imports(meta).unwrap(), ["Init", "Mathlib.Tactic"]);
+            assert_eq!(parse_consts(meta).unwrap(), ["Foo.bar", "Foo.baz"]);
+        }
+
+        let olean = synthesize(b".0.0", &[b'0'; 40], &[], &[], false);
+        assert!(parse_meta(&olean).is_none());
+    }
+
+    #[test]
+    fn test_malformed() {
+        init();
+
+        let olean = sample();
+        for len in 0..olean.len() {
+            assert!(parse_meta(&olean[..len]).is_none());
+        }
+        for i in 80..olean.len() {
+            for x in [0x01, 0x80, 0xff] {
+                let mut mutated = olean.clone();
+                mutated[i] ^= x;
+                parse_all(&mutated);
+            }
+        }
+
+        // name whose prefix is itself.
+        let (version, hash) = DATA[0];
+        let mut olean = synthesize(version, hash, &[], &["Foo"], false);
+        let consts = usize::from_le_bytes(olean[olean.len() - 40..olean.len() - 32].try_into().unwrap()) - BASE;
+        let name = usize::from_le_bytes(olean[consts + 24..consts + 32].try_into().unwrap());
+        let p = name - BASE;
+        olean[p + 8..p + 16].copy_from_slice(&name.to_le_bytes());
+        assert!(parse_consts(parse_meta(&olean).unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_path() {
+        assert_eq!(check_path(b"alice/Foo/Bar.olean", "/alice/"), 6);
+        assert_eq!(check_path(b".lake/build/lib/lean/alice/Foo.ilean", "/alice/"), 0);
+        assert_eq!(check_path(b".lake/build/lib/lean/alice/Foo.olean", "/alice/"), 27);
+        assert_eq!(check_path(b"x/alice/Foo.olean", "/alice/"), 0);
+        assert_eq!(check_path(b"alice/Foo\xff.olean", "/alice/"), 0);
+        assert_eq!(check_path(b".lake/build/lib/lean\xc0/alice/Foo.olean", "/alice/"), 0);
+    }
+
+    #[test]
+    #[ignore = "needs the seeds of fuzz/fetch_corpus.py in fuzz/corpus/olean"]
+    fn test_corpus() {
+        init();
+
+        // Oleans built by the real toolchains, see `fuzz/fetch_corpus.py`.
+        let sums = fs::read_to_string(format!("{CORPUS}/SHA256SUMS")).expect("no corpus, run fuzz/fetch_corpus.py");
+        let sums = sums.lines().map(|line| line.split_once("  ").unwrap()).collect::<Vec<_>>();
+        for (version, _) in DATA {
+            let name = format!("4{}.olean", str::from_utf8(version).unwrap());
+            assert!(sums.iter().any(|&(_, file)| file == name), "no seed for {name}");
+        }
+        for (digest, file) in sums {
+            let olean = fs::read(format!("{CORPUS}/{file}")).unwrap_or_else(|e| panic!("{file}: {e}"));
+            let actual = sha256(&olean).iter().fold(String::new(), |s, b| s + &format!("{b:02x}"));
+            assert_eq!(actual, digest, "{file} changed");
+            // seeds named after their version must stay parseable.
+            if let Some(version) = file.strip_suffix(".olean").and_then(|stem| stem.strip_prefix('4')) {
+                let meta = parse_meta(&olean).unwrap_or_else(|| panic!("{file}: cannot parse the header"));
+                assert_eq!(meta.version, version, "{file}");
+                assert!(parse_consts(meta).is_some(), "{file}: cannot parse constants");
+                assert!(parse_imports(meta).is_some(), "{file}: cannot parse imports");
+            }
+        }
+
+        // anything the fuzzer added next to them.
+        for entry in fs::read_dir(CORPUS).unwrap() {
+            parse_all(&fs::read(entry.unwrap().path()).unwrap());
         }
     }
 }
@@ -0,0 +1,158 @@
+use std::time::SystemTime;
+
+use openssl::{hash::MessageDigest, pkey::PKey, sha::sha256, sign::Signer};
+use rand::{Rng, RngCore};
+
+/// RFC 6238 with the parameters every authenticator app assumes: HMAC-SHA1, 30 s steps, 6 digits.
+pub const STEP: u64 = 30;
+pub const DIGITS: u32 = 6;
+pub const SECRET_LENGTH: usize = 20;
+/// Codes of the neighbouring steps are also accepted, for clock skew.
+const SKEW: u64 = 1;
+pub const ISSUER: &str = option_env!("TOTP_ISSUER").unwrap_or("Lean4OJ");
+
+pub const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn gen_secret() -> [u8; SECRET_LENGTH] {
+    let mut secret = [0; SECRET_LENGTH];
+    rand::rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// RFC 4648 base32 without padding, as authenticator apps expect it.
+pub fn base32(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(5) * 8);
+    let (mut buf, mut bits) = (0u32, 0u32);
+    for &b in data {
+        buf = ((buf << 8) | u32::from(b)) & 0xffff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            s.push(BASE32[((buf >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        s.push(BASE32[((buf << (5 - bits)) & 31) as usize] as char);
+    }
+    s
+}
+
+/// The `otpauth://` URI for the QR code of the enrollment of `uid`.
+pub fn uri(uid: &str, secret: &[u8]) -> String {
+    fn escape(s: &str) -> String {
+        form_urlencoded::byte_serialize(s.as_bytes()).map(|s| if s == "+" { "%20" } else { s }).collect()
+    }
+
+    let issuer = escape(ISSUER);
+    format!(
+        "otpauth://totp/{issuer}:{}?secret={}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP}",
+        escape(uid),
+        base32(secret),
+    )
+}
+
+fn hotp(secret: &[u8], counter: u64) -> Option<u32> {
+    let key = PKey::hmac(secret).ok()?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key).ok()?;
+    signer.update(&counter.to_be_bytes()).ok()?;
+    let mac = signer.sign_to_vec().ok()?;
+    let offset = usize::from(mac.last()? & 15);
+    let bin = u32::from_be_bytes(mac.get(offset..offset + 4)?.try_into().ok()?) & 0x7fff_ffff;
+    Some(bin % 10u32.pow(DIGITS))
+}
+
+/// The time step `code` is valid for, if it is after `last_step` (a code can only be used once).
+pub fn verify(secret: &[u8], code: &str, last_step: i64) -> Option<i64> {
+    verify_at(secret, code, last_step, SystemTime::UNIX_EPOCH.elapsed().ok()?.as_secs() / STEP)
+}
+
+fn verify_at(secret: &[u8], code: &str, last_step: i64, now: u64) -> Option<i64> {
+    if code.len() != DIGITS as usize || !code.bytes().all(|c| c.is_ascii_digit()) { return None; }
+    let code = code.parse::<u32>().ok()?;
+    (now.saturating_sub(SKEW)..=now + SKEW)
+        .map(u64::cast_signed)
+        .filter(|&step| step > last_step)
+        .find(|&step| hotp(secret, step.cast_unsigned()) == Some(code))
+}
+
+/// Fresh recovery codes, shown as `xxxxx-xxxxx`, and their [digests](recovery_digest).
+pub fn gen_recovery_codes() -> (Vec<String>, Vec<[u8; 32]>) {
+    let mut rng = rand::rng();
+    let codes = (0..RECOVERY_CODES).map(|_| {
+        let mut code = String::with_capacity(RECOVERY_CODE_LENGTH + 1);
+        for i in 0..RECOVERY_CODE_LENGTH {
+            if i == RECOVERY_CODE_LENGTH / 2 { code.push('-'); }
+            code.push(BASE32[rng.random_range(0..32)].to_ascii_lowercase() as char);
+        }
+        code
+    }).collect::<Vec<_>>();
+    let digests = codes.iter().map(|code| recovery_digest(code)).collect();
+    (codes, digests)
+}
+
+/// What is stored of a recovery code, insensitive to case, dashes and spaces.
+pub fn recovery_digest(code: &str) -> [u8; 32] {
+    let normalized: String = code.chars().filter(|c| !matches!(c, '-' | ' ')).map(|c| c.to_ascii_lowercase()).collect();
+    sha256(normalized.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_hotp() {
+        // RFC 4226 Appendix D.
+        let expected = [755_224, 287_082, 359_152, 969_429, 338_314, 254_676, 287_922, 162_583, 399_871, 520_489];
+        for (counter, code) in (0..).zip(expected) {
+            assert_eq!(hotp(SECRET, counter), Some(code));
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        // RFC 6238 Appendix B (SHA-1), the last 6 of the 8 digits.
+        for (time, code) in [
+            (59, "287082"),
+            (1_111_111_109, "081804"),
+            (1_111_111_111, "050471"),
+            (1_234_567_890, "005924"),
+            (2_000_000_000, "279037"),
+            (20_000_000_000, "353130"),
+        ] {
+            let step = (time / STEP).cast_signed();
+            assert_eq!(verify_at(SECRET, code, -1, time / STEP), Some(step));
+            // skew.
+            assert_eq!(verify_at(SECRET, code, -1, time / STEP + 1), Some(step));
+            assert_eq!(verify_at(SECRET, code, -1, time / STEP - 1), Some(step));
+            assert_eq!(verify_at(SECRET, code, -1, time / STEP + 2), None);
+            // used once.
+            assert_eq!(verify_at(SECRET, code, step, time / STEP), None);
+        }
+        assert_eq!(verify_at(SECRET, "287083", -1, 1), None);
+        assert_eq!(verify_at(SECRET, "28708", -1, 1), None);
+        assert_eq!(verify_at(SECRET, "+87082", -1, 1), None);
+    }
+
+    #[test]
+    fn test_base32() {
+        // RFC 4648 section 10, unpadded.
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ] {
+            assert_eq!(base32(data.as_bytes()), encoded);
+        }
+        assert_eq!(base32(SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+}
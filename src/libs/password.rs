@@ -0,0 +1,187 @@
+use core::mem::MaybeUninit;
+
+use base64::{Engine, prelude::BASE64_STANDARD_NO_PAD};
+use openssl::{error::ErrorStack, memcmp, pkcs5};
+use openssl_sys::SHA256_CTX;
+use rand::RngCore;
+use tokio::task::spawn_blocking;
+use tokio_postgres::Client;
+
+use super::{constants::PASSWORD_LENGTH, error::BoxedStdError};
+
+/// scrypt with N = 2<sup>15</sup>, r = 8, p = 1 (32 MiB, ~50 ms).
+const LOG_N: u8 = 15;
+const R: u64 = 8;
+const P: u64 = 1;
+const MAX_MEM: u64 = 64 << 20;
+const SALT_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+const PREFIX: &str = "$scrypt$";
+/// Well-formed with the current parameters, matched by nothing.
+const DUMMY: &str = "$scrypt$ln=15,r=8,p=1$AAAAAAAAAAAAAAAAAAAAAA$AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+fn derive(password: &[u8], salt: &[u8], log_n: u8, r: u64, p: u64) -> Result<[u8; KEY_LENGTH], ErrorStack> {
+    let mut key = [0; KEY_LENGTH];
+    pkcs5::scrypt(password, salt, 1 << log_n, r, p, MAX_MEM, &mut key)?;
+    Ok(key)
+}
+
+fn hash_blocking(password: &[u8]) -> Result<String, ErrorStack> {
+    let mut salt = [0; SALT_LENGTH];
+    rand::rng().fill_bytes(&mut salt);
+    let key = derive(password, &salt, LOG_N, R, P)?;
+    Ok(format!(
+        "{PREFIX}ln={LOG_N},r={R},p={P}${}${}",
+        BASE64_STANDARD_NO_PAD.encode(salt),
+        BASE64_STANDARD_NO_PAD.encode(key),
+    ))
+}
+
+/// `None` if `stored` is not a well-formed hash.
+fn verify_blocking(password: &[u8], stored: &str) -> Option<bool> {
+    let mut parts = stored.strip_prefix(PREFIX)?.split('$');
+    let (params, salt, key) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() { return None; }
+
+    let mut params = params.split(',');
+    let log_n = params.next()?.strip_prefix("ln=")?.parse().ok().filter(|&n| n < 64)?;
+    let r = params.next()?.strip_prefix("r=")?.parse().ok()?;
+    let p = params.next()?.strip_prefix("p=")?.parse().ok()?;
+    if params.next().is_some() { return None; }
+
+    let salt = BASE64_STANDARD_NO_PAD.decode(salt).ok()?;
+    let key = BASE64_STANDARD_NO_PAD.decode(key).ok()?;
+    if key.len() != KEY_LENGTH { return None; }
+    let derived = derive(password, &salt, log_n, r, p).ok()?;
+    Some(memcmp::eq(&derived, &key))
+}
+
+/// Every rsync challenge starts with this, so that the first SHA-256 block of `<password><challenge>` only depends
+/// on the password (of [`PASSWORD_LENGTH`] bytes).
+pub const RSYNC_CHALLENGE_PREFIX: &[u8; 64 - PASSWORD_LENGTH] = b"Lean4OJ-rsync-verify-";
+
+/// The SHA-256 state after that first block, which is all the rsync daemon keeps of a password.
+///
+/// It answers challenges as well as the password does, but only those of this daemon: it cannot be used to log in.
+pub fn rsync_verifier(password: &[u8]) -> Option<[u8; 32]> {
+    if password.len() != PASSWORD_LENGTH { return None; }
+    let mut ctx = MaybeUninit::<SHA256_CTX>::uninit();
+    let ctx = unsafe {
+        openssl_sys::SHA256_Init(ctx.as_mut_ptr());
+        openssl_sys::SHA256_Update(ctx.as_mut_ptr(), password.as_ptr().cast(), password.len());
+        openssl_sys::SHA256_Update(ctx.as_mut_ptr(), RSYNC_CHALLENGE_PREFIX.as_ptr().cast(), RSYNC_CHALLENGE_PREFIX.len());
+        ctx.assume_init()
+    };
+    let mut verifier = [0; 32];
+    for (b, h) in verifier.as_chunks_mut::<4>().0.iter_mut().zip(ctx.h) {
+        *b = h.to_be_bytes();
+    }
+    Some(verifier)
+}
+
+/// `sha256(<password><RSYNC_CHALLENGE_PREFIX><rest>)`, from the [`rsync_verifier`] of the password.
+pub fn rsync_response(verifier: &[u8], rest: &[u8]) -> Option<[u8; 32]> {
+    let (words, []) = verifier.as_chunks::<4>() else { return None };
+    if words.len() != 8 { return None; }
+    let mut ctx = MaybeUninit::<SHA256_CTX>::uninit();
+    let mut md = [0; 32];
+    unsafe {
+        openssl_sys::SHA256_Init(ctx.as_mut_ptr());
+        let ctx = ctx.assume_init_mut();
+        for (h, word) in ctx.h.iter_mut().zip(words) {
+            *h = u32::from_be_bytes(*word);
+        }
+        // one block, in bits.
+        ctx.Nl = 512;
+        openssl_sys::SHA256_Update(ctx, rest.as_ptr().cast(), rest.len());
+        openssl_sys::SHA256_Final(md.as_mut_ptr(), ctx);
+    }
+    Some(md)
+}
+
+/// Hashes the (already client-side hashed) password for storage in `lean4oj.users`.
+pub async fn hash(password: &str) -> Result<String, BoxedStdError> {
+    let password = password.to_owned();
+    Ok(spawn_blocking(move || hash_blocking(password.as_bytes())).await??)
+}
+
+/// Takes as long as [`verify`] against a hashed row, for when there is no row at all.
+pub async fn verify_dummy(password: &str) -> Result<(), BoxedStdError> {
+    let password = password.to_owned();
+    spawn_blocking(move || verify_blocking(password.as_bytes(), DUMMY)).await?;
+    Ok(())
+}
+
+/// Checks `password` against `stored`, the `password` column of `uid`.
+///
+/// Rows from before hashing hold the client-side value as is, such a row is rehashed once it matches. Either way the
+/// [`rsync_verifier`] is filled in if it is missing.
+pub async fn verify(uid: &str, password: &str, stored: &str, db: &mut Client) -> Result<bool, BoxedStdError> {
+    const SQL_UPGRADE: &str = "update lean4oj.users set password = $1, rsync_verifier = $4 where uid = $2 and password = $3";
+    const SQL_VERIFIER: &str = "update lean4oj.users set rsync_verifier = $3 where uid = $1 and password = $2 and rsync_verifier is null";
+
+    let verifier = rsync_verifier(password.as_bytes());
+    let verifier = verifier.as_ref().map(<[u8; 32]>::as_slice);
+    if stored.starts_with(PREFIX) {
+        let (password2, stored2) = (password.to_owned(), stored.to_owned());
+        let ok = spawn_blocking(move || verify_blocking(password2.as_bytes(), &stored2)).await? == Some(true);
+        if ok && verifier.is_some() {
+            let stmt = db.prepare_static(SQL_VERIFIER.into()).await?;
+            db.execute(&stmt, &[&uid, &stored, &verifier]).await?;
+        }
+        return Ok(ok);
+    }
+
+    // empty for system users, which can never log in.
+    if stored.is_empty() || stored.len() != password.len() || !memcmp::eq(stored.as_bytes(), password.as_bytes()) {
+        return Ok(false);
+    }
+    let hashed = hash(password).await?;
+    let stmt = db.prepare_static(SQL_UPGRADE.into()).await?;
+    db.execute(&stmt, &[&hashed, &uid, &stored, &verifier]).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &[u8; PASSWORD_LENGTH] = b"n4bQgYhMfWQc1RpXy6Y0mR5PnE0hX0x2yXtDCk8lFkQ";
+
+    #[test]
+    fn test_hash() {
+        let stored = hash_blocking(PASSWORD).unwrap();
+        assert!(stored.starts_with("$scrypt$ln=15,r=8,p=1$"));
+        assert_eq!(verify_blocking(PASSWORD, &stored), Some(true));
+        assert_eq!(verify_blocking(b"n4bQgYhMfWQc1RpXy6Y0mR5PnE0hX0x2yXtDCk8lFkq", &stored), Some(false));
+        assert_eq!(verify_blocking(b"", &stored), Some(false));
+        // salted.
+        assert_ne!(hash_blocking(PASSWORD).unwrap(), stored);
+    }
+
+    #[test]
+    fn test_legacy() {
+        // rows from before hashing are left to `verify`, which compares them as is and rehashes.
+        assert_eq!(verify_blocking(PASSWORD, str::from_utf8(PASSWORD).unwrap()), None);
+        assert_eq!(verify_blocking(PASSWORD, ""), None);
+        assert_eq!(verify_blocking(PASSWORD, "$scrypt$ln=15,r=8$AAAA$AAAA"), None);
+        assert_eq!(verify_blocking(PASSWORD, "$scrypt$ln=64,r=8,p=1$AAAAAAAAAAAAAAAAAAAAAA$AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"), None);
+        // whatever parameters a row was hashed with are honoured.
+        let salt = [7; SALT_LENGTH];
+        let key = derive(PASSWORD, &salt, 10, 4, 2).unwrap();
+        let stored = format!("$scrypt$ln=10,r=4,p=2${}${}", BASE64_STANDARD_NO_PAD.encode(salt), BASE64_STANDARD_NO_PAD.encode(key));
+        assert_eq!(verify_blocking(PASSWORD, &stored), Some(true));
+        assert_eq!(verify_blocking(PASSWORD, DUMMY), Some(false));
+    }
+
+    #[test]
+    fn test_rsync_verifier() {
+        let verifier = rsync_verifier(PASSWORD).unwrap();
+        for rest in [&b""[..], b"0123456789abcdef", &[b'x'; 100]] {
+            let expected = openssl::sha::sha256(&[&PASSWORD[..], RSYNC_CHALLENGE_PREFIX, rest].concat());
+            assert_eq!(rsync_response(&verifier, rest), Some(expected));
+        }
+        assert_eq!(rsync_verifier(&PASSWORD[1..]), None);
+        assert_eq!(rsync_response(&verifier[1..], b""), None);
+    }
+}
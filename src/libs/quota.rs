@@ -0,0 +1,148 @@
+use core::future::ready;
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use compact_str::CompactString;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use tokio::task::spawn_blocking;
+use tokio_postgres::Client;
+
+use super::{
+    db::{DBResult, get_connection},
+    error::BoxedStdError,
+    olean::TOTAL_FILE_LIMIT,
+    privilege,
+};
+
+#[derive(Serialize)]
+pub struct ModuleUsage {
+    pub name: CompactString,
+    pub size: u64,
+    pub files: u32,
+}
+
+/// Storage quota of `uid` in bytes (`usize::MAX` for unlimited).
+///
+/// `Lean4OJ.TooManyOLeans` is unlimited; otherwise the user's own override wins, then the largest override among
+/// the user's groups, then [`TOTAL_FILE_LIMIT`].
+pub async fn limit(uid: &str, db: &mut Client) -> DBResult<usize> {
+    const SQL: &str = "select (select storage_quota from lean4oj.users where uid = $1), (select max(storage_quota) from lean4oj.user_groups join lean4oj.groups using (gid) where uid = $1)";
+
+    if privilege::check(uid, "Lean4OJ.TooManyOLeans", db).await? { return Ok(usize::MAX); }
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let row = db.query_one(&stmt, &[&uid]).await?;
+    let quota = match row.try_get::<_, Option<i64>>(0)? {
+        Some(q) => Some(q),
+        None => row.try_get::<_, Option<i64>>(1)?,
+    };
+    Ok(quota.map_or(TOTAL_FILE_LIMIT, |q| q.cast_unsigned() as usize))
+}
+
+/// As last recorded by [`refresh`].
+pub async fn used(uid: &str, db: &mut Client) -> DBResult<usize> {
+    const SQL: &str = "select coalesce(sum(size), 0)::bigint from lean4oj.storage_usage where uid = $1";
+
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let row = db.query_one(&stmt, &[&uid]).await?;
+    row.try_get::<_, i64>(0).map(|n| n.cast_unsigned() as usize)
+}
+
+pub fn exceeded(limit: usize) -> String {
+    format!("Total file size exceeds the storage quota ({} MB). Please contact server administrator for a larger capacity.", limit >> 20)
+}
+
+fn walk(dir: &Path, acc: &mut (u64, u32)) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let type_ = entry.file_type()?;
+        if type_.is_dir() {
+            walk(&entry.path(), acc)?;
+        } else if type_.is_file() {
+            acc.0 += entry.metadata()?.len();
+            acc.1 += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Usage per top-level module (`Foo` for both `Foo.olean` and `Foo/**`).
+fn scan(root: &Path) -> io::Result<BTreeMap<CompactString, (u64, u32)>> {
+    let mut ret = BTreeMap::<CompactString, (u64, u32)>::new();
+    let dir = match fs::read_dir(root) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ret),
+        Err(e) => return Err(e),
+    };
+    for entry in dir {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let type_ = entry.file_type()?;
+        if type_.is_dir() {
+            walk(&entry.path(), ret.entry(name.into()).or_default())?;
+        } else if type_.is_file() {
+            let module = name.split_once('.').map_or(&*name, |(m, _)| m);
+            let acc = ret.entry(module.into()).or_default();
+            acc.0 += entry.metadata()?.len();
+            acc.1 += 1;
+        }
+    }
+    Ok(ret)
+}
+
+/// Re-scans the olean tree of `uid` and records its usage, to be called whenever the tree has changed.
+pub async fn refresh(uid: &str, db: &mut Client) -> Result<(), BoxedStdError> {
+    const SQL_DELETE: &str = "delete from lean4oj.storage_usage where uid = $1";
+    const SQL_INSERT: &str = "insert into lean4oj.storage_usage (uid, module, size, files) select $1, * from unnest($2::text[], $3::bigint[], $4::integer[])";
+
+    let root = format!("{}/lean/{uid}", env!("OLEAN_ROOT"));
+    let usage = spawn_blocking(move || scan(Path::new(&root))).await??;
+    let modules = usage.keys().map(CompactString::as_str).collect::<Vec<_>>();
+    let sizes = usage.values().map(|&(size, _)| size.cast_signed()).collect::<Vec<_>>();
+    let files = usage.values().map(|&(_, files)| files.cast_signed()).collect::<Vec<_>>();
+
+    let stmt_delete = db.prepare_static(SQL_DELETE.into()).await?;
+    let stmt_insert = db.prepare_static(SQL_INSERT.into()).await?;
+    let txn = db.transaction().await?;
+    txn.execute(&stmt_delete, &[&uid]).await?;
+    txn.execute(&stmt_insert, &[&uid, &modules, &sizes, &files]).await?;
+    txn.commit().await.map_err(Into::into)
+}
+
+/// Records the usage of trees uploaded before it was kept in `storage_usage`, i.e. of users with a tree but no rows.
+async fn backfill() -> Result<(), BoxedStdError> {
+    const SQL: &str = "select uid from lean4oj.users u where not exists (select from lean4oj.storage_usage s where s.uid = u.uid)";
+
+    let mut conn = get_connection().await?;
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    let rows = conn.query(&stmt, &[]).await?;
+    for row in rows {
+        let uid = row.try_get::<_, &str>(0)?;
+        if tokio::fs::try_exists(format!("{}/lean/{uid}", env!("OLEAN_ROOT"))).await? {
+            refresh(uid, &mut conn).await?;
+        }
+    }
+    Ok(())
+}
+
+pub fn init() {
+    tokio::spawn(async {
+        if let Err(e) = backfill().await {
+            tracing::warn!(target: "quota", "failed to backfill storage usage: {e:?}");
+        }
+    });
+}
+
+pub async fn usage(uid: &str, db: &mut Client) -> DBResult<Vec<ModuleUsage>> {
+    const SQL: &str = "select module, size, files from lean4oj.storage_usage where uid = $1 order by module";
+
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let stream = db.query_raw(&stmt, [uid]).await?;
+    stream.and_then(|row| ready(try {
+        ModuleUsage {
+            name: row.try_get::<_, &str>(0)?.into(),
+            size: row.try_get::<_, i64>(1)?.cast_unsigned(),
+            files: row.try_get::<_, i32>(2)?.cast_unsigned(),
+        }
+    })).try_collect().await
+}
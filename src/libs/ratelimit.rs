@@ -0,0 +1,231 @@
+use core::time::Duration;
+use std::{sync::LazyLock, time::SystemTime};
+
+use compact_str::{CompactString, format_compact};
+use dashmap::DashMap;
+use hashbrown::DefaultHashBuilder;
+use http::{HeaderMap, StatusCode};
+use serde::Serialize;
+
+use super::{audit, constants::REMOTE_ADDR, db::get_connection, response::JkmxJsonResponse, validate::check_uid};
+
+const fn env_or(value: Option<&str>, default: u64) -> u64 {
+    match value {
+        Some(value) => match u64::from_str_radix(value, 10) {
+            Ok(n) => n,
+            Err(_) => panic!("Invalid rate limit"),
+        },
+        None => default,
+    }
+}
+
+/// The first lockout of a key lasts [`Policy::lockout`], every further one twice the previous, up to
+/// [`Policy::max_lockout`]. These are the defaults.
+const LOCKOUT: Duration = Duration::from_secs(env_or(option_env!("RATE_LIMIT_LOCKOUT"), 60));
+const MAX_LOCKOUT: Duration = Duration::from_secs(env_or(option_env!("RATE_LIMIT_MAX_LOCKOUT"), 86400));
+/// The lockout history of a key is forgotten after this long without another lockout.
+const STRIKE_DECAY: Duration = Duration::from_hours(24);
+/// Stale keys are swept once there are this many.
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// At most `attempts` counted events of a key per `window`, the limits being set at build time by
+/// `RATE_LIMIT_<NAME>` (e.g. `RATE_LIMIT_LOGIN_ADDRESS`).
+pub struct Policy {
+    pub name: &'static str,
+    /// Keyed by uid rather than by client address.
+    pub per_account: bool,
+    pub attempts: u64,
+    pub window: Duration,
+    pub lockout: Duration,
+    pub max_lockout: Duration,
+}
+
+/// Failed logins from an address.
+pub const LOGIN_ADDRESS: Policy = Policy {
+    name: "login.address",
+    per_account: false,
+    attempts: env_or(option_env!("RATE_LIMIT_LOGIN_ADDRESS"), 30),
+    window: Duration::from_mins(15),
+    lockout: LOCKOUT,
+    max_lockout: MAX_LOCKOUT,
+};
+/// Failed logins to an account from an address, keyed by the [`pair`] of what was given as the identifier or email
+/// and the address: someone elsewhere cannot lock the owner out, and unknown accounts are limited just like others.
+pub const LOGIN_ACCOUNT: Policy = Policy {
+    name: "login.account",
+    per_account: true,
+    attempts: env_or(option_env!("RATE_LIMIT_LOGIN_ACCOUNT"), 10),
+    window: Duration::from_mins(15),
+    lockout: LOCKOUT,
+    max_lockout: MAX_LOCKOUT,
+};
+/// Failed logins to an account from anywhere, keyed by what was given as the identifier or email alone. Catches
+/// guessing spread over many addresses, which [`LOGIN_ACCOUNT`] does not; as anyone can trip it, it only slows every
+/// address down to a few attempts a minute instead of locking the owner out for long.
+pub const LOGIN_ACCOUNT_ANYWHERE: Policy = Policy {
+    name: "login.accountAnywhere",
+    per_account: true,
+    attempts: env_or(option_env!("RATE_LIMIT_LOGIN_ACCOUNT_ANYWHERE"), 50),
+    window: Duration::from_mins(15),
+    lockout: Duration::from_secs(5),
+    max_lockout: Duration::from_mins(1),
+};
+/// Wrong TOTP or recovery codes of an account.
+pub const TOTP_ACCOUNT: Policy = Policy {
+    name: "totp.account",
+    per_account: true,
+    attempts: env_or(option_env!("RATE_LIMIT_TOTP_ACCOUNT"), 5),
+    window: Duration::from_mins(5),
+    lockout: LOCKOUT,
+    max_lockout: MAX_LOCKOUT,
+};
+/// Wrong old passwords given to `updateUserPassword`.
+pub const PASSWORD_ACCOUNT: Policy = Policy {
+    name: "password.account",
+    per_account: true,
+    attempts: env_or(option_env!("RATE_LIMIT_PASSWORD_ACCOUNT"), 5),
+    window: Duration::from_mins(15),
+    lockout: LOCKOUT,
+    max_lockout: MAX_LOCKOUT,
+};
+/// Registrations from an address.
+pub const REGISTER_ADDRESS: Policy = Policy {
+    name: "register.address",
+    per_account: false,
+    attempts: env_or(option_env!("RATE_LIMIT_REGISTER_ADDRESS"), 5),
+    window: Duration::from_hours(1),
+    lockout: LOCKOUT,
+    max_lockout: MAX_LOCKOUT,
+};
+/// `requestPasswordReset`s from an address, whatever the email.
+pub const RESET_ADDRESS: Policy = Policy {
+    name: "reset.address",
+    per_account: false,
+    attempts: env_or(option_env!("RATE_LIMIT_RESET_ADDRESS"), 10),
+    window: Duration::from_hours(1),
+    lockout: LOCKOUT,
+    max_lockout: MAX_LOCKOUT,
+};
+/// Identifier and email lookups of `checkAvailability` from an address.
+pub const AVAILABILITY_ADDRESS: Policy = Policy {
+    name: "availability.address",
+    per_account: false,
+    attempts: env_or(option_env!("RATE_LIMIT_AVAILABILITY_ADDRESS"), 60),
+    window: Duration::from_mins(1),
+    lockout: LOCKOUT,
+    max_lockout: MAX_LOCKOUT,
+};
+
+struct State {
+    window: Duration,
+    window_start: SystemTime,
+    count: u64,
+    strikes: u32,
+    locked_until: Option<SystemTime>,
+}
+
+impl State {
+    fn stale(&self, now: SystemTime) -> bool {
+        let (since, ttl) = match self.locked_until {
+            Some(t) => (t, STRIKE_DECAY),
+            None => (self.window_start, self.window),
+        };
+        now.duration_since(since).is_ok_and(|d| d >= ttl)
+    }
+}
+
+static STATES: LazyLock<DashMap<(&'static str, CompactString), State, DefaultHashBuilder>> = LazyLock::new(|| DashMap::with_hasher(DefaultHashBuilder::default()));
+
+fn key_of(policy: &Policy, key: &str) -> (&'static str, CompactString) {
+    (policy.name, key.to_ascii_lowercase().into())
+}
+
+/// The key of an account (or whatever names one) as seen from an address, for `per_account` policies.
+pub fn pair(account: &str, address: Option<&str>) -> CompactString {
+    format_compact!("{account} {}", address.unwrap_or("-"))
+}
+
+/// The client address, as set by the reverse proxy. `None` for requests that did not go through it.
+pub fn address(headers: &HeaderMap) -> Option<&str> {
+    headers.get(REMOTE_ADDR).and_then(|v| v.to_str().ok())
+}
+
+/// `Err` with the time left while `key` is locked out.
+pub fn check(policy: &Policy, key: &str) -> Result<(), Duration> {
+    let now = SystemTime::now();
+    let Some(state) = STATES.get(&key_of(policy, key)) else { return Ok(()) };
+    match state.locked_until.map(|t| t.duration_since(now)) {
+        Some(Ok(left)) if !left.is_zero() => Err(left),
+        _ => Ok(()),
+    }
+}
+
+/// Counts an event of `key`, locking it out once over the limit. Lockouts go to the audit log.
+pub fn fail(policy: &'static Policy, key: &str) {
+    #[derive(Serialize)]
+    struct Lockout<'a> {
+        policy: &'static str,
+        key: &'a str,
+        strikes: u32,
+        seconds: u64,
+    }
+
+    let now = SystemTime::now();
+    if STATES.len() >= PRUNE_THRESHOLD {
+        STATES.retain(|_, state| !state.stale(now));
+    }
+
+    let mut state = STATES.entry(key_of(policy, key)).or_insert_with(|| State {
+        window: policy.window,
+        window_start: now,
+        count: 0,
+        strikes: 0,
+        locked_until: None,
+    });
+    if state.locked_until.is_some() && state.stale(now) {
+        state.strikes = 0;
+        state.locked_until = None;
+    }
+    if now.duration_since(state.window_start).is_ok_and(|d| d >= policy.window) {
+        state.window_start = now;
+        state.count = 0;
+    }
+    state.count += 1;
+    if state.count <= policy.attempts { return; }
+
+    state.strikes += 1;
+    let lockout = policy.lockout.saturating_mul(1 << (state.strikes - 1).min(20)).min(policy.max_lockout);
+    state.locked_until = Some(now + lockout);
+    state.window_start = now;
+    state.count = 0;
+    let strikes = state.strikes;
+    drop(state);
+
+    tracing::warn!(target: "ratelimit", "{key} locked out of {} for {lockout:?} (strike {strikes})", policy.name);
+    let details = serde_json::to_value(Lockout { policy: policy.name, key, strikes, seconds: lockout.as_secs() }).unwrap_or_default();
+    // the account part of a [`pair`], unless it is an email (it may still name no one).
+    let target = policy.per_account.then(|| key.split(' ').next().unwrap_or(key)).filter(|uid| check_uid(uid)).map(CompactString::from);
+    tokio::spawn(async move {
+        let Ok(mut conn) = get_connection().await else { return };
+        audit::log(audit::SYSTEM, "rateLimit.lockout", target.as_deref(), audit::Object::None, details, &mut conn).await;
+    });
+}
+
+/// Forgets the counted events of `key` (not its lockout history), e.g. on a successful login.
+pub fn reset(policy: &Policy, key: &str) {
+    if let Some(mut state) = STATES.get_mut(&key_of(policy, key)) {
+        state.count = 0;
+    }
+}
+
+/// For limits on every request rather than on failures: [`check`]s and counts one.
+pub fn hit(policy: &'static Policy, key: &str) -> Result<(), Duration> {
+    check(policy, key)?;
+    fail(policy, key);
+    check(policy, key)
+}
+
+pub fn limited(retry_after: Duration) -> JkmxJsonResponse {
+    let res = format!(r#"{{"error":"RATE_LIMITED","retryAfter":{}}}"#, retry_after.as_millis().div_ceil(1000));
+    JkmxJsonResponse::Response(StatusCode::TOO_MANY_REQUESTS, res.into())
+}
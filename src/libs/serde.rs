@@ -146,3 +146,15 @@ where
 {
     JsDuration(unsafe { &*core::ptr::from_ref(time).cast() }, serializer)
 }
+
+#[inline]
+#[allow(clippy::ref_option)]
+pub fn JsMaybeTime<S>(time: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match time {
+        Some(time) => JsTime(time, serializer),
+        None => serializer.serialize_none(),
+    }
+}
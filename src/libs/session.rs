@@ -1,67 +1,167 @@
-use core::future::{Ready, ready};
+use core::time::Duration;
 use std::{sync::LazyLock, time::SystemTime};
 
 use dashmap::DashMap;
 use futures_util::FutureExt;
 use hashbrown::DefaultHashBuilder;
+use http::{HeaderMap, header::USER_AGENT};
+use serde::Serialize;
+use serde_json::Value;
+use tokio_postgres::{Client, types::Json};
 use tower_sessions_core::{
     ExpiredDeletion, Expiry, Session, SessionStore,
     session::{Id, Record},
+    session_store::Error,
 };
 
-use super::constants::{GLOBAL_INTERVAL, SESSION_EXPIRE};
+use super::{
+    constants::{GLOBAL_INTERVAL, REMOTE_ADDR, SESSION_EXPIRE},
+    db::{DBError, DBResult, get_connection},
+    serde::JsTime,
+};
 
 pub type SResult<T> = Result<T, tower_sessions_core::session::Error>;
 pub type SSResult<T> = tower_sessions_core::session_store::Result<T>;
 
+/// A cached record is trusted for this long before being re-read, which bounds how long a session deleted by
+/// another instance stays alive here.
+const CACHE_TTL: Duration = Duration::from_mins(1);
+/// The sliding expiry is only written back once it has moved this far.
+const EXPIRY_SLACK: Duration = Duration::from_mins(5);
+
+/// Sessions live in `lean4oj.sessions`, with a read-through cache in front.
 #[derive(Debug)]
 pub struct GlobalStore;
 
-static MAP: LazyLock<DashMap<Id, Record, DefaultHashBuilder>> = LazyLock::new(|| DashMap::with_hasher(DefaultHashBuilder::default()));
+struct Cached {
+    record: Record,
+    /// When the row was last read or written.
+    synced: SystemTime,
+    /// `expiry` as in the row, `record.expiry_date` may run ahead of it by up to [`EXPIRY_SLACK`].
+    stored_expiry: SystemTime,
+}
+
+static CACHE: LazyLock<DashMap<Id, Cached, DefaultHashBuilder>> = LazyLock::new(|| DashMap::with_hasher(DefaultHashBuilder::default()));
+
+#[inline]
+fn backend(e: impl ToString) -> Error {
+    Error::Backend(e.to_string())
+}
+
+#[inline]
+fn cache(record: &Record, synced: SystemTime, stored_expiry: SystemTime) {
+    CACHE.insert(record.id, Cached { record: record.clone(), synced, stored_expiry });
+}
 
 impl SessionStore for GlobalStore {
-    fn create(&self, record: &mut Record) -> Ready<SSResult<()>> {
-        while MAP.contains_key(&record.id) {
+    async fn create(&self, record: &mut Record) -> SSResult<()> {
+        const SQL: &str = "insert into lean4oj.sessions (id, uid, data, expiry, create_time, ip, user_agent) values ($1, $2, $3, $4, $5, $6, $7) on conflict do nothing";
+
+        let field = |key| record.data.get(key).and_then(Value::as_str).map(str::to_owned);
+        let Some(uid) = field("uid") else { return Err(Error::Encode("session without uid".into())) };
+        let ip = field("ip");
+        let user_agent = field("userAgent");
+
+        let now = SystemTime::now();
+        let mut conn = get_connection().await.map_err(backend)?;
+        let stmt = conn.prepare_static(SQL.into()).await.map_err(backend)?;
+        loop {
+            let id = record.id.0.to_be_bytes();
+            let n = conn.execute(
+                &stmt,
+                &[&&id[..], &uid, &Json(&record.data), &record.expiry_date, &now, &ip, &user_agent],
+            ).await.map_err(backend)?;
+            if n == 1 { break; }
             record.id = Id::default();
         }
-        MAP.insert(record.id, record.clone());
-        ready(Ok(()))
+        cache(record, now, record.expiry_date);
+        Ok(())
     }
 
-    fn save(&self, record: &Record) -> Ready<SSResult<()>> {
-        MAP.insert(record.id, record.clone());
-        ready(Ok(()))
+    async fn save(&self, record: &Record) -> SSResult<()> {
+        const SQL: &str = "update lean4oj.sessions set data = $2, expiry = $3 where id = $1";
+
+        // most saves only slide the expiry forward a little, keep those in memory.
+        let skip = CACHE.get_mut(&record.id).is_some_and(|mut c| {
+            let skip = c.record.data == record.data
+                && record.expiry_date.duration_since(c.stored_expiry).is_ok_and(|d| d < EXPIRY_SLACK);
+            if skip { c.record.expiry_date = record.expiry_date; }
+            skip
+        });
+        if skip { return Ok(()); }
+
+        let mut conn = get_connection().await.map_err(backend)?;
+        let stmt = conn.prepare_static(SQL.into()).await.map_err(backend)?;
+        let id = record.id.0.to_be_bytes();
+        let n = conn.execute(&stmt, &[&&id[..], &Json(&record.data), &record.expiry_date]).await.map_err(backend)?;
+        // never resurrect a deleted session.
+        if n == 1 {
+            cache(record, SystemTime::now(), record.expiry_date);
+        } else {
+            CACHE.remove(&record.id);
+        }
+        Ok(())
     }
 
-    fn load(&self, session_id: &Id) -> Ready<SSResult<Option<Record>>> {
-        let s = MAP.get(session_id);
-        let t = s
-            .filter(|r| r.expiry_date >= SystemTime::now())
-            .map(|r| r.clone());
-        ready(Ok(t))
+    async fn load(&self, session_id: &Id) -> SSResult<Option<Record>> {
+        const SQL: &str = "select data, expiry from lean4oj.sessions where id = $1";
+
+        let now = SystemTime::now();
+        let cached_expiry = match CACHE.get(session_id) {
+            Some(c) if now.duration_since(c.synced).is_ok_and(|d| d < CACHE_TTL) => {
+                return Ok((c.record.expiry_date >= now).then(|| c.record.clone()));
+            }
+            Some(c) => Some(c.record.expiry_date),
+            None => None,
+        };
+
+        let mut conn = get_connection().await.map_err(backend)?;
+        let stmt = conn.prepare_static(SQL.into()).await.map_err(backend)?;
+        let id = session_id.0.to_be_bytes();
+        let Some(row) = conn.query_opt(&stmt, &[&&id[..]]).await.map_err(backend)? else {
+            CACHE.remove(session_id);
+            return Ok(None);
+        };
+        let Json(data) = row.try_get(0).map_err(|e| Error::Decode(e.to_string()))?;
+        let stored_expiry: SystemTime = row.try_get(1).map_err(|e| Error::Decode(e.to_string()))?;
+
+        let record = Record {
+            id: *session_id,
+            data,
+            expiry_date: cached_expiry.map_or(stored_expiry, |e| e.max(stored_expiry)),
+        };
+        cache(&record, now, stored_expiry);
+        Ok((record.expiry_date >= now).then_some(record))
     }
 
-    fn delete(&self, session_id: &Id) -> Ready<SSResult<()>> {
-        MAP.remove(session_id);
-        ready(Ok(()))
+    async fn delete(&self, session_id: &Id) -> SSResult<()> {
+        const SQL: &str = "delete from lean4oj.sessions where id = $1";
+
+        CACHE.remove(session_id);
+        let mut conn = get_connection().await.map_err(backend)?;
+        let stmt = conn.prepare_static(SQL.into()).await.map_err(backend)?;
+        let id = session_id.0.to_be_bytes();
+        conn.execute(&stmt, &[&&id[..]]).await.map_err(backend)?;
+        Ok(())
     }
 }
 
 impl ExpiredDeletion for GlobalStore {
-    fn delete_expired(&self) -> Ready<SSResult<()>> {
+    async fn delete_expired(&self) -> SSResult<()> {
+        // rows may lag behind the sliding expiry by `EXPIRY_SLACK`.
+        const SQL: &str = "delete from lean4oj.sessions where expiry < $1";
+
         tracing::debug!(target: "expired-session-cleaner", "start clean");
 
-        let cc1 = MAP.len();
         let now = SystemTime::now();
-        for shard in MAP.shards() {
-            if let Some(mut shard) = shard.try_write() {
-                shard.retain(|v| v.1.expiry_date >= now);
-            }
-        }
-        let cc2 = MAP.len();
-        tracing::debug!(target: "expired-session-cleaner", "cleaned \x1b[32m{cc1}\x1b[0m -> \x1b[32m{cc2}\x1b[0m");
+        CACHE.retain(|_, c| c.record.expiry_date >= now);
+
+        let mut conn = get_connection().await.map_err(backend)?;
+        let stmt = conn.prepare_static(SQL.into()).await.map_err(backend)?;
+        let n = conn.execute(&stmt, &[&(now - EXPIRY_SLACK)]).await.map_err(backend)?;
+        tracing::debug!(target: "expired-session-cleaner", "cleaned \x1b[32m{n}\x1b[0m session(s), \x1b[32m{}\x1b[0m cached", CACHE.len());
 
-        ready(Ok(()))
+        Ok(())
     }
 }
 
@@ -69,17 +169,30 @@ pub fn init() {
     tokio::spawn(GlobalStore.continuously_delete_expired(GLOBAL_INTERVAL).map(Result::unwrap));
 }
 
-pub async fn create(uid: String) -> SResult<Session<GlobalStore>> {
+/// `headers` are those of the login request, the client address and user agent are kept with the session.
+pub async fn create(uid: String, headers: &HeaderMap) -> SResult<Session<GlobalStore>> {
     let session = Session::new(
         None,
         GlobalStore,
         Some(Expiry::OnInactivity(SESSION_EXPIRE)),
     );
-    session.insert_value("uid", serde_json::Value::String(uid)).await?;
+    session.insert_value("uid", Value::String(uid)).await?;
+    for (key, name) in [("ip", REMOTE_ADDR), ("userAgent", USER_AGENT)] {
+        if let Some(value) = headers.get(name).and_then(|v| v.to_str().ok()) {
+            session.insert_value(key, Value::String(value.to_owned())).await?;
+        }
+    }
     session.save().await?;
     Ok(session)
 }
 
+/// A session that is never stored, for requests authenticated by other means.
+pub async fn ephemeral(uid: String) -> SResult<Session<GlobalStore>> {
+    let session = Session::new(None, GlobalStore, None);
+    session.insert_value("uid", Value::String(uid)).await?;
+    Ok(session)
+}
+
 pub async fn load(id: Id) -> SResult<Session<GlobalStore>> {
     let session = Session::new(
         Some(id),
@@ -91,6 +204,82 @@ pub async fn load(id: Id) -> SResult<Session<GlobalStore>> {
     Ok(session)
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub login_ip: Option<String>,
+    pub user_agent: Option<String>,
+    #[serde(serialize_with = "JsTime")]
+    pub login_time: SystemTime,
+    #[serde(serialize_with = "JsTime")]
+    pub last_access_time: SystemTime,
+    pub is_current: bool,
+}
+
+/// Live sessions of `uid`, most recently used first.
+pub async fn list(uid: &str, current: Option<Id>, db: &mut Client) -> DBResult<Vec<SessionInfo>> {
+    const SQL: &str = "select id, expiry, create_time, ip, user_agent from lean4oj.sessions where uid = $1 and expiry >= $2";
+
+    let now = SystemTime::now();
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let mut sessions = Vec::new();
+    for row in db.query(&stmt, &[&uid, &(now - EXPIRY_SLACK)]).await? {
+        let id = row.try_get::<_, &[u8]>(0)?.try_into().map_err(|e|
+            DBError::new(tokio_postgres::error::Kind::FromSql(0), Some(Box::new(e)))
+        )?;
+        let id = Id(i128::from_be_bytes(id));
+        let stored_expiry: SystemTime = row.try_get(1)?;
+        let expiry = CACHE.get(&id).map_or(stored_expiry, |c| c.record.expiry_date.max(stored_expiry));
+        if expiry < now { continue; }
+        sessions.push(SessionInfo {
+            session_id: id.to_string(),
+            login_ip: row.try_get(3)?,
+            user_agent: row.try_get(4)?,
+            login_time: row.try_get(2)?,
+            last_access_time: expiry - SESSION_EXPIRE,
+            is_current: current == Some(id),
+        });
+    }
+    sessions.sort_unstable_by(|a, b| b.last_access_time.cmp(&a.last_access_time));
+    Ok(sessions)
+}
+
+pub async fn revoke(uid: &str, id: Id, db: &mut Client) -> DBResult<bool> {
+    const SQL: &str = "delete from lean4oj.sessions where uid = $1 and id = $2";
+
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let n = db.execute(&stmt, &[&uid, &&id.0.to_be_bytes()[..]]).await?;
+    if n != 0 { CACHE.remove(&id); }
+    Ok(n != 0)
+}
+
+/// Revokes every session of `uid` but `except`, returns how many were revoked.
+pub async fn revoke_all(uid: &str, except: Option<Id>, db: &mut Client) -> DBResult<u64> {
+    const SQL: &str = "delete from lean4oj.sessions where uid = $1 and id is distinct from $2";
+
+    let except_bytes = except.map(|id| id.0.to_be_bytes());
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let n = db.execute(&stmt, &[&uid, &except_bytes.as_ref().map(|b| &b[..])]).await?;
+    CACHE.retain(|id, c| Some(*id) == except || c.record.data.get("uid").and_then(Value::as_str) != Some(uid));
+    Ok(n)
+}
+
+/// Revokes every session of the members of `gid` but `except`, returns how many were revoked.
+pub async fn revoke_members(gid: &str, except: Option<Id>, db: &mut Client) -> DBResult<u64> {
+    const SQL: &str = "delete from lean4oj.sessions s using lean4oj.user_groups g where g.gid = $1 and s.uid = g.uid and s.id is distinct from $2 returning s.id";
+
+    let except_bytes = except.map(|id| id.0.to_be_bytes());
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let rows = db.query(&stmt, &[&gid, &except_bytes.as_ref().map(|b| &b[..])]).await?;
+    for row in &rows {
+        if let Some(id) = row.try_get::<_, &[u8]>(0)?.as_array::<16>() {
+            CACHE.remove(&Id(i128::from_be_bytes(*id)));
+        }
+    }
+    Ok(rows.len() as u64)
+}
+
 #[macro_export]
 #[allow(unused_variables)]
 macro_rules! exs {
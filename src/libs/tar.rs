@@ -0,0 +1,147 @@
+use axum::body::{Body, BodyDataStream};
+use bytes::{Buf, Bytes};
+use futures_util::StreamExt;
+
+use super::error::BoxedStdError;
+
+const BLOCK: usize = 512;
+const PAX_LIMIT: usize = 0x10_0000; // 1 MB
+
+pub struct Entry {
+    pub path: Vec<u8>,
+    pub size: usize,
+    pub regular: bool,
+}
+
+/// Streaming reader of ustar / pax / GNU tar, only what is needed for uploading a build tree.
+pub struct Reader {
+    stream: BodyDataStream,
+    buf: Bytes,
+}
+
+fn octal(field: &[u8]) -> Option<usize> {
+    let field = field.trim_ascii_start();
+    let n = field.iter().position(|&b| b == 0 || b == b' ').unwrap_or(field.len());
+    let digits = unsafe { field.get_unchecked(..n) };
+    if digits.is_empty() { return Some(0); }
+    usize::from_ascii_radix(digits, 8).ok()
+}
+
+#[inline]
+fn cstr(field: &[u8]) -> &[u8] {
+    let n = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    unsafe { field.get_unchecked(..n) }
+}
+
+/// `path` from pax extended header records (`"%d %s=%s\n"`).
+fn pax_path(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut ret = None;
+    while !data.is_empty() {
+        let sp = data.iter().position(|&b| b == b' ')?;
+        let len = usize::from_ascii(unsafe { data.get_unchecked(..sp) }).ok()?;
+        let record = data.get(sp + 1..len)?.strip_suffix(b"\n")?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            ret = Some(path.to_vec());
+        }
+        data = unsafe { data.get_unchecked(len..) };
+    }
+    ret
+}
+
+impl Reader {
+    pub fn new(body: Body) -> Self {
+        Self { stream: body.into_data_stream(), buf: Bytes::new() }
+    }
+
+    async fn read_exact(&mut self, mut out: &mut [u8]) -> Result<(), BoxedStdError> {
+        while !out.is_empty() {
+            if self.buf.is_empty() {
+                match self.stream.next().await {
+                    Some(chunk) => self.buf = chunk?,
+                    None => return Err("unexpected end of archive".into()),
+                }
+                continue;
+            }
+            let n = out.len().min(self.buf.len());
+            let (head, tail) = out.split_at_mut(n);
+            head.copy_from_slice(unsafe { self.buf.get_unchecked(..n) });
+            self.buf.advance(n);
+            out = tail;
+        }
+        Ok(())
+    }
+
+    async fn skip(&mut self, mut n: usize) -> Result<(), BoxedStdError> {
+        while n != 0 {
+            if self.buf.is_empty() {
+                match self.stream.next().await {
+                    Some(chunk) => self.buf = chunk?,
+                    None => return Err("unexpected end of archive".into()),
+                }
+                continue;
+            }
+            let m = n.min(self.buf.len());
+            self.buf.advance(m);
+            n -= m;
+        }
+        Ok(())
+    }
+
+    /// Content of the current entry, the padding is consumed as well.
+    pub async fn data(&mut self, size: usize) -> Result<Vec<u8>, BoxedStdError> {
+        let mut data = vec![0; size.next_multiple_of(BLOCK)];
+        self.read_exact(&mut data).await?;
+        data.truncate(size);
+        Ok(data)
+    }
+
+    pub async fn skip_data(&mut self, size: usize) -> Result<(), BoxedStdError> {
+        self.skip(size.next_multiple_of(BLOCK)).await
+    }
+
+    /// The caller must consume the content (by [`Self::data`] or [`Self::skip_data`]) before the next call.
+    pub async fn next(&mut self) -> Result<Option<Entry>, BoxedStdError> {
+        let mut long_path = None;
+        let mut header = [0u8; BLOCK];
+        loop {
+            self.read_exact(&mut header).await?;
+            if header.iter().all(|&b| b == 0) { return Ok(None); }
+
+            let checksum = header.iter().enumerate()
+                .map(|(i, &b)| if (148..156).contains(&i) { u32::from(b' ') } else { u32::from(b) })
+                .sum::<u32>();
+            if octal(&header[148..156]) != Some(checksum as usize) {
+                return Err("tar header checksum mismatch".into());
+            }
+            let Some(size) = octal(&header[124..136]) else {
+                return Err("invalid size in tar header".into());
+            };
+
+            match header[156] {
+                b'x' | b'L' => {
+                    if size > PAX_LIMIT { return Err("tar extended header too large".into()); }
+                    let data = self.data(size).await?;
+                    long_path = if header[156] == b'x' { pax_path(&data) } else { Some(cstr(&data).to_vec()) };
+                }
+                b'g' | b'K' => self.skip_data(size).await?,
+                typeflag => {
+                    let mut path = long_path.take().unwrap_or_else(|| {
+                        let name = cstr(&header[..100]);
+                        let prefix = if header[257..262] == *b"ustar" { cstr(&header[345..500]) } else { b"" };
+                        let mut path = Vec::with_capacity(prefix.len() + name.len() + 1);
+                        if !prefix.is_empty() {
+                            path.extend_from_slice(prefix);
+                            path.push(b'/');
+                        }
+                        path.extend_from_slice(name);
+                        path
+                    });
+                    let mut skip = 0;
+                    while path[skip..].starts_with(b"./") { skip += 2; }
+                    path.drain(..skip);
+                    return Ok(Some(Entry { path, size, regular: typeflag == b'0' || typeflag == 0 }));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use core::time::Duration;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use compact_str::CompactString;
+use openssl::{hash::MessageDigest, memcmp, pkey::PKey, sign::Signer};
+use rand::RngCore;
+
+static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Tokens are signed with `TOKEN_SECRET` if set at build time, or else with the key kept at `TOKEN_KEY_PATH`, made on
+/// first start. Either way every instance must share it, or tokens issued by one fail on another.
+pub fn init() {
+    const KEY_PATH: &str = option_env!("TOKEN_KEY_PATH").unwrap_or("/usr/local/nginx/conf/token.key");
+
+    let key = match option_env!("TOKEN_SECRET") {
+        Some(secret) => secret.as_bytes().to_vec(),
+        None => match fs::read(KEY_PATH) {
+            Ok(key) => key,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut key = vec![0; 32];
+                rand::rng().fill_bytes(&mut key);
+                let mut f = OpenOptions::new().write(true).create_new(true).mode(0o600).open(KEY_PATH).unwrap();
+                f.write_all(&key).unwrap();
+                key
+            }
+            Err(e) => panic!("failed to read {KEY_PATH}: {e}"),
+        },
+    };
+    assert!(key.len() >= 32, "the token key is too short");
+    KEY.get_or_init(|| key);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    VerifyEmail,
+    ResetPassword,
+    /// The second step of a login, after the password.
+    Login,
+}
+
+impl Purpose {
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::VerifyEmail => "verify-email",
+            Self::ResetPassword => "reset-password",
+            Self::Login => "login",
+        }
+    }
+
+    pub const fn ttl(self) -> Duration {
+        match self {
+            Self::VerifyEmail => Duration::from_hours(24),
+            Self::ResetPassword => Duration::from_hours(1),
+            Self::Login => Duration::from_mins(5),
+        }
+    }
+}
+
+fn sign(payload: &str, binding: &[u8]) -> Option<Vec<u8>> {
+    let key = PKey::hmac(KEY.get()?).ok()?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).ok()?;
+    signer.update(payload.as_bytes()).ok()?;
+    signer.update(b"\0").ok()?;
+    signer.update(binding).ok()?;
+    signer.sign_to_vec().ok()
+}
+
+/// A token for `uid`, valid for [`Purpose::ttl`] and as long as `binding` (state the token must not outlive, e.g. the
+/// email being verified) is unchanged.
+pub fn issue(purpose: Purpose, uid: &str, binding: &[u8]) -> Option<String> {
+    let expiry = (SystemTime::UNIX_EPOCH.elapsed().ok()? + purpose.ttl()).as_secs();
+    let payload = format!("{}:{expiry}:{uid}", purpose.tag());
+    let mac = sign(&payload, binding)?;
+    Some(format!("{}.{}", BASE64_URL_SAFE_NO_PAD.encode(payload), BASE64_URL_SAFE_NO_PAD.encode(mac)))
+}
+
+/// An unexpired token of the right purpose, whose signature is yet to be [`checked`](Self::verify).
+pub struct Claims {
+    pub uid: CompactString,
+    payload: String,
+    mac: Vec<u8>,
+}
+
+impl Claims {
+    pub fn parse(purpose: Purpose, token: &str) -> Option<Self> {
+        let (payload, mac) = token.split_once('.')?;
+        let payload = String::from_utf8(BASE64_URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        let mac = BASE64_URL_SAFE_NO_PAD.decode(mac).ok()?;
+
+        let mut parts = payload.splitn(3, ':');
+        if parts.next()? != purpose.tag() { return None; }
+        let expiry = parts.next()?.parse::<u64>().ok()?;
+        let uid = parts.next()?.into();
+        if SystemTime::UNIX_EPOCH.elapsed().ok()?.as_secs() > expiry { return None; }
+
+        Some(Self { uid, payload, mac })
+    }
+
+    pub fn verify(&self, binding: &[u8]) -> bool {
+        sign(&self.payload, binding).is_some_and(|mac| mac.len() == self.mac.len() && memcmp::eq(&mac, &self.mac))
+    }
+}
@@ -137,15 +137,20 @@ async fn main() -> std::io::Result<!> {
     libs::db::init_db().await;
     libs::emoji::init();
     libs::logger::init();
+    libs::oauth::init();
     libs::olean::init();
+    libs::quota::init();
     libs::session::init();
+    libs::token::init();
+    models::notification::init();
 
     tokio::spawn(service::rsync::main().map(Result::unwrap));
     tokio::spawn(service::submission_deposit::main().map(Result::unwrap));
 
     let mut app: Router = Router::new()
         .nest("/api", api::all())
-        .route("/lean/submission/{*path}", get(api::fs::submission));
+        .route("/lean/submission/{*path}", get(api::fs::submission))
+        .route("/lean/avatar/{hash}", get(api::fs::avatar));
 
     app = app.layer(DefaultBodyLimit::disable());
 
@@ -1,7 +1,14 @@
+pub mod access_token;
 pub mod discussion;
 pub mod group;
 pub mod localedict;
+pub mod notification;
+pub mod oauth_link;
 pub mod problem;
+pub mod ranklist;
 pub mod submission;
+pub mod suspension;
 pub mod tag;
+pub mod totp;
+pub mod upload_token;
 pub mod user;
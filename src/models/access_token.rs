@@ -0,0 +1,115 @@
+use core::{future::ready, time::Duration};
+use std::time::SystemTime;
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use compact_str::CompactString;
+use futures_util::TryStreamExt;
+use openssl::sha::sha256;
+use serde::Serialize;
+use tokio_postgres::{Client, Row};
+
+use crate::libs::{
+    db::{DBError, DBResult},
+    serde::{JsMaybeTime, JsTime},
+};
+
+pub const MAX_ACCESS_TOKENS: i64 = 16;
+/// Tells access tokens apart from session tokens (standard base64, without `_`) in `Authorization: Bearer`.
+pub const PREFIX: &str = "l4oj_";
+/// Scopes are `<area>:read` or `<area>:write`, where `<area>` is the API prefix of the route; which route needs which
+/// is listed in [`crate::libs::auth`]. Account management (`auth`, and writes to `user`) is never granted.
+pub const SCOPES: [&str; 11] = [
+    "discussion:read", "discussion:write",
+    "group:read", "group:write",
+    "olean:read", "olean:write",
+    "problem:read", "problem:write",
+    "submission:read", "submission:write",
+    "user:read",
+];
+/// Writes of `last_used` are throttled to this resolution.
+const TOUCH_INTERVAL: Duration = Duration::from_mins(1);
+
+/// A named, long-lived credential for scripts. Only a digest of the token is stored, it is shown once on creation.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessToken {
+    pub name: CompactString,
+    pub scopes: Vec<CompactString>,
+    #[serde(serialize_with = "JsTime")]
+    pub create_time: SystemTime,
+    #[serde(serialize_with = "JsMaybeTime")]
+    pub last_used: Option<SystemTime>,
+    #[serde(serialize_with = "JsMaybeTime")]
+    pub expire_time: Option<SystemTime>,
+}
+
+impl TryFrom<Row> for AccessToken {
+    type Error = DBError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let name = row.try_get::<_, &str>("name")?.into();
+        let scopes = row.try_get::<_, Vec<&str>>("scopes")?.into_iter().map(Into::into).collect();
+        let create_time = row.try_get("create_time")?;
+        let last_used = row.try_get("last_used")?;
+        let expire_time = row.try_get("expire_time")?;
+        Ok(Self { name, scopes, create_time, last_used, expire_time })
+    }
+}
+
+impl AccessToken {
+    fn gen_token() -> String {
+        use rand::RngCore;
+
+        let mut buf = [0u8; 32];
+        rand::rng().fill_bytes(&mut buf);
+        let mut token = String::with_capacity(PREFIX.len() + 43);
+        token.push_str(PREFIX);
+        BASE64_URL_SAFE_NO_PAD.encode_string(buf, &mut token);
+        token
+    }
+
+    pub async fn list(uid: &str, db: &mut Client) -> DBResult<Vec<Self>> {
+        pub const SQL: &str = "select name, scopes, create_time, last_used, expire_time from lean4oj.access_tokens where uid = $1 order by create_time";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let stream = db.query_raw(&stmt, [uid]).await?;
+        stream.and_then(|row| ready(Self::try_from(row))).try_collect().await
+    }
+
+    /// Returns the token, or `None` if the name is taken or there are already [`MAX_ACCESS_TOKENS`] tokens.
+    pub async fn create(uid: &str, name: &str, scopes: &[&str], expire_time: Option<SystemTime>, db: &mut Client) -> DBResult<Option<String>> {
+        pub const SQL: &str = "insert into lean4oj.access_tokens (uid, name, token_hash, scopes, create_time, expire_time) select $1, $2, $3, $4, $5, $6 where (select count(*) from lean4oj.access_tokens where uid = $1) < $7 on conflict do nothing";
+
+        let token = Self::gen_token();
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let n = db.execute(&stmt, &[&uid, &name, &&sha256(token.as_bytes())[..], &scopes, &SystemTime::now(), &expire_time, &MAX_ACCESS_TOKENS]).await?;
+        Ok((n == 1).then_some(token))
+    }
+
+    pub async fn revoke(uid: &str, name: &str, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "delete from lean4oj.access_tokens where uid = $1 and name = $2";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &name]).await.map(|n| n == 1)
+    }
+
+    /// The owner and scopes of an unexpired `token` of a user not suspended, also records the use.
+    pub async fn authenticate(token: &[u8], db: &mut Client) -> DBResult<Option<(String, Vec<CompactString>)>> {
+        pub const SQL: &str = "select uid, scopes, last_used from lean4oj.access_tokens where token_hash = $1 and (expire_time is null or expire_time > $2) and not exists (select from lean4oj.user_suspensions s where s.uid = access_tokens.uid and (s.until is null or s.until > $2))";
+        pub const SQL_TOUCH: &str = "update lean4oj.access_tokens set last_used = $2 where token_hash = $1";
+
+        let hash = sha256(token);
+        let now = SystemTime::now();
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let Some(row) = db.query_opt(&stmt, &[&&hash[..], &now]).await? else { return Ok(None) };
+        let uid = row.try_get("uid")?;
+        let scopes = row.try_get::<_, Vec<&str>>("scopes")?.into_iter().map(Into::into).collect();
+        let last_used: Option<SystemTime> = row.try_get("last_used")?;
+
+        if last_used.is_none_or(|t| now.duration_since(t).is_ok_and(|d| d >= TOUCH_INTERVAL)) {
+            let stmt = db.prepare_static(SQL_TOUCH.into()).await?;
+            db.execute(&stmt, &[&&hash[..], &now]).await?;
+        }
+        Ok(Some((uid, scopes)))
+    }
+}
@@ -1,18 +1,25 @@
 use core::{fmt, future::ready, num::NonZeroI32};
-use std::time::SystemTime;
+use std::{collections::BTreeMap, time::SystemTime};
 
 use compact_str::CompactString;
 use futures_util::TryStreamExt;
-use serde::{Serialize, Serializer, ser::SerializeMap};
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeMap};
 use smallvec::{SmallVec, smallvec};
-use tokio_postgres::{Client, Row, types::ToSql};
+use tokio_postgres::{
+    Client, Row,
+    types::{Json, ToSql},
+};
 
 use crate::{
     libs::{
         db::{DBResult, ToSqlIter},
         util::get_millis,
     },
-    models::{localedict::LocaleDict, problem::Problem, user::User},
+    models::{
+        localedict::{DEFAULT_LOCALE, LocaleDict, Locales},
+        problem::Problem,
+        user::User,
+    },
 };
 
 mod query_replies_type;
@@ -28,10 +35,17 @@ pub use reply::{
     ReplyAOE as DiscussionReplyAOE,
 };
 
-pub struct Discussion {
-    pub id: u32,
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DiscussionInner {
     pub title: CompactString,
     pub content: CompactString,
+}
+
+pub struct Discussion {
+    pub id: u32,
+    pub content: LocaleDict<DiscussionInner>,
+    /// The translation shown, see [`Self::localize`].
+    pub locale: CompactString,
     pub publish: SystemTime,
     pub edit: SystemTime,
     pub update: SystemTime,
@@ -47,7 +61,9 @@ impl Serialize for Discussion {
     {
         let mut map = serializer.serialize_map(None)?;
         map.serialize_entry("id", &self.id)?;
-        map.serialize_entry("title", &self.title)?;
+        map.serialize_entry("title", &self.content.0.get(&self.locale).map_or_default(|x| &*x.title))?;
+        map.serialize_entry("titleLocale", &self.locale)?;
+        map.serialize_entry("locales", &Locales(&self.content))?;
         map.serialize_entry("publishTime", &get_millis(self.publish))?;
         if self.publish != self.edit {
             map.serialize_entry("editTime", &get_millis(self.edit))?;
@@ -68,15 +84,15 @@ impl TryFrom<Row> for Discussion {
 
     fn try_from(row: Row) -> Result<Self, Self::Error> {
         let id = row.try_get::<_, i32>("id")?.cast_unsigned();
-        let title = row.try_get::<_, &str>("title")?.into();
-        let content = row.try_get::<_, &str>("content")?.into();
+        let Json(content) = row.try_get::<_, Json<LocaleDict<DiscussionInner>>>("dcontent")?;
+        let locale = resolve(&content, None);
         let publish = row.try_get("publish")?;
         let edit = row.try_get("edit")?;
         let update = row.try_get("update")?;
         let reply_count = row.try_get::<_, i32>("reply_count")?.cast_unsigned();
         let publisher = row.try_get::<_, &str>("publisher")?.into();
         let problem_id = row.try_get::<_, Option<i32>>("pid")?.and_then(NonZeroI32::new);
-        Ok(Self { id, title, content, publish, edit, update, reply_count, publisher, problem_id })
+        Ok(Self { id, content, locale, publish, edit, update, reply_count, publisher, problem_id })
     }
 }
 
@@ -85,20 +101,19 @@ fn 𝒢(row: Row) -> DBResult<(Discussion, Option<Problem>, User)> {
     Ok((row.clone().try_into()?, row.clone().try_into().ok(), row.try_into()?))
 }
 
-// example: {"zh_CN":"喵","en_US":"Meow","ja_JP":"にゃー"}
-fn backdoor_inner(s: &mut CompactString, locale: Option<&str>) {
-    if let Some(suffix) = s.strip_prefix(Discussion::MAGIC_PREFIX)
-    && let Ok(dict) = serde_json::from_str::<LocaleDict>(suffix)
-    && let Some(t) = dict.apply_owned(locale) {
-        *s = t;
-    }
+/// The locale [`LocaleDict::apply`] picks for `locale`.
+fn resolve<T>(dict: &LocaleDict<T>, locale: Option<&str>) -> CompactString {
+    dict.apply_with_key(locale).map_or_default(|(k, _)| k.clone())
 }
 
-impl Discussion {
-    pub const MAGIC_PREFIX: &str = "\u{ea97}";
+/// The locale a translation without one replaces: the one shown by default.
+fn default_key<T>(dict: &LocaleDict<T>) -> CompactString {
+    dict.apply_with_key(None).map_or_else(|| DEFAULT_LOCALE.into(), |(k, _)| k.clone())
+}
 
+impl Discussion {
     pub async fn by_id_aoe(id: u32, db: &mut Client) -> DBResult<Option<(Self, User)>> {
-        const SQL: &str = "select id, title, content, publish, edit, update, reply_count, publisher, pid, uid, username, email, password, register_time, ac, nickname, bio, avatar_info from lean4oj.discussions inner join lean4oj.users on publisher = uid where id = $1";
+        const SQL: &str = "select id, dcontent, publish, edit, update, reply_count, publisher, pid, uid, username, email, password, register_time, ac, nickname, bio, avatar_info from lean4oj.discussions inner join lean4oj.users on publisher = uid where id = $1";
 
         let stmt = db.prepare_static(SQL.into()).await?;
         let result = match db.query_opt(&stmt, &[&id.cast_signed()]).await? {
@@ -112,26 +127,56 @@ impl Discussion {
     where
         I: ExactSizeIterator<Item = u32> + Clone + fmt::Debug + Sync,
     {
-        const SQL: &str = "select id, title, content, publish, edit, update, reply_count, publisher, pid from unnest($1::integer[]) with ordinality as ids(id, o) natural join lean4oj.discussions order by o";
+        const SQL: &str = "select id, dcontent, publish, edit, update, reply_count, publisher, pid from unnest($1::integer[]) with ordinality as ids(id, o) natural join lean4oj.discussions order by o";
 
         let stmt = db.prepare_static(SQL.into()).await?;
         let stream = db.query_raw(&stmt, [ToSqlIter(ids.map(u32::cast_signed))]).await?;
         stream.and_then(|row| ready(row.try_into())).try_collect().await
     }
 
-    pub async fn create(pid: Option<i32>, title: &str, content: &str, time: SystemTime, publisher: &str, db: &mut Client) -> DBResult<u32> {
-        const SQL: &str = "insert into lean4oj.discussions (title, content, publish, edit, update, publisher, pid) values ($1, $2, $3, $3, $3, $4, $5) returning id";
+    pub async fn create(pid: Option<i32>, content: &LocaleDict<DiscussionInner>, time: SystemTime, publisher: &str, db: &mut Client) -> DBResult<u32> {
+        const SQL: &str = "insert into lean4oj.discussions (dcontent, publish, edit, update, publisher, pid) values ($1, $2, $2, $2, $3, $4) returning id";
 
         let stmt = db.prepare_static(SQL.into()).await?;
-        let row = db.query_one(&stmt, &[&title, &content, &time, &publisher, &pid]).await?;
+        let content: *const Json<BTreeMap<CompactString, DiscussionInner>> = (&raw const content.0).cast();
+        let row = db.query_one(&stmt, &[unsafe { &*content }, &time, &publisher, &pid]).await?;
         row.try_get(0).map(i32::cast_unsigned)
     }
 
+    /// Adds or replaces the translation to `locale`, by default the one shown by default. Of `publisher` only, if given.
+    /// `bump` moves the discussion to the top.
+    pub async fn translate(
+        id: u32, publisher: Option<&str>, locale: Option<&str>, inner: DiscussionInner, time: SystemTime, bump: bool, db: &mut Client,
+    ) -> DBResult<bool> {
+        const SQL_SEL: &str = "select dcontent from lean4oj.discussions where id = $1 and ($2::text is null or publisher = $2) for update";
+        const SQL_UPD: &str = "update lean4oj.discussions set dcontent = $2, edit = $3, update = case when $4 then $3 else update end where id = $1";
+
+        let stmt_sel = db.prepare_static(SQL_SEL.into()).await?;
+        let stmt_upd = db.prepare_static(SQL_UPD.into()).await?;
+        let txn = db.transaction().await?;
+        let Some(row) = txn.query_opt(&stmt_sel, &[&id.cast_signed(), &publisher]).await? else { return Ok(false) };
+        let Json(mut content) = row.try_get::<_, Json<LocaleDict<DiscussionInner>>>(0)?;
+        let key = locale.map_or_else(|| default_key(&content), Into::into);
+        content.0.insert(key, inner);
+        let content: *const Json<BTreeMap<CompactString, DiscussionInner>> = (&raw const content.0).cast();
+        txn.execute(&stmt_upd, &[&id.cast_signed(), unsafe { &*content }, &time, &bump]).await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+
+    /// Removes the translation to `locale`, unless it is the last one.
+    pub async fn untranslate(id: u32, publisher: Option<&str>, locale: &str, time: SystemTime, db: &mut Client) -> DBResult<bool> {
+        const SQL: &str = "update lean4oj.discussions set dcontent = dcontent - $3, edit = $4 where id = $1 and ($2::text is null or publisher = $2) and dcontent ? $3 and dcontent - $3 <> '{}'";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&id.cast_signed(), &publisher, &locale, &time]).await.map(|n| n == 1)
+    }
+
     pub async fn search<'a, F>(skip: i64, take: i64, extend: F, db: &mut Client) -> DBResult<Vec<Self>>
     where
         F: FnOnce(String, SmallVec<[&'a (dyn ToSql + Sync); 8]>) -> (String, SmallVec<[&'a (dyn ToSql + Sync); 8]>),
     {
-        let mut sql = "select id, title, content, publish, edit, update, reply_count, publisher, pid from lean4oj.discussions natural left join lean4oj.problems".to_owned();
+        let mut sql = "select id, dcontent, publish, edit, update, reply_count, publisher, pid from lean4oj.discussions natural left join lean4oj.problems".to_owned();
         let mut args: SmallVec<[&(dyn ToSql + Sync); 8]> = smallvec![
             unsafe { core::mem::transmute::<&i64, &'a i64>(&skip) } as _,
             unsafe { core::mem::transmute::<&i64, &'a i64>(&take) } as _,
@@ -150,7 +195,7 @@ impl Discussion {
     where
         F: FnOnce(String, SmallVec<[&'a (dyn ToSql + Sync); 8]>) -> (String, SmallVec<[&'a (dyn ToSql + Sync); 8]>),
     {
-        let mut sql = "select id, title, content, publish, edit, update, reply_count, publisher, pid, uid, username, email, password, register_time, ac, nickname, bio, avatar_info, is_public, public_at, owner, pcontent, sub, pac, submittable, jb from lean4oj.discussions inner join lean4oj.users on publisher = uid natural left join lean4oj.problems".to_owned();
+        let mut sql = "select id, dcontent, publish, edit, update, reply_count, publisher, pid, uid, username, email, password, register_time, ac, nickname, bio, avatar_info, is_public, public_at, owner, pcontent, sub, pac, submittable, jb from lean4oj.discussions inner join lean4oj.users on publisher = uid natural left join lean4oj.problems".to_owned();
         let mut args: SmallVec<[&(dyn ToSql + Sync); 8]> = smallvec![
             unsafe { core::mem::transmute::<&i64, &'a i64>(&skip) } as _,
             unsafe { core::mem::transmute::<&i64, &'a i64>(&take) } as _,
@@ -184,8 +229,8 @@ impl Discussion {
         row.try_get::<_, i64>(0).map(i64::cast_unsigned)
     }
 
-    pub fn backdoor(&mut self, locale: Option<&str>) {
-        backdoor_inner(&mut self.title, locale);
-        backdoor_inner(&mut self.content, locale);
+    /// Shows the translation [`LocaleDict::apply`] picks for `locale`.
+    pub fn localize(&mut self, locale: Option<&str>) {
+        self.locale = resolve(&self.content, locale);
     }
 }
@@ -1,23 +1,29 @@
 use core::future::ready;
-use std::time::SystemTime;
+use std::{collections::BTreeMap, time::SystemTime};
 
 use compact_str::CompactString;
 use futures_util::TryStreamExt;
 use serde::{Serialize, Serializer, ser::SerializeMap};
-use tokio_postgres::{Client, Row};
+use tokio_postgres::{Client, Row, types::Json};
 
 use crate::{
     libs::{
         db::{DBError, DBResult},
         util::get_millis,
     },
-    models::{discussion::DiscussionReactionAOE, user::UserAOE},
+    models::{
+        discussion::{DiscussionReactionAOE, default_key, resolve},
+        localedict::{LocaleDict, Locales},
+        user::UserAOE,
+    },
 };
 
 #[derive(Debug)]
 pub struct Reply {
     pub id: u32,
-    pub content: CompactString,
+    pub content: LocaleDict,
+    /// The translation shown, see [`Self::localize`].
+    pub locale: CompactString,
     pub publish: SystemTime,
     pub edit: SystemTime,
     pub did: u32,
@@ -31,7 +37,9 @@ impl Serialize for Reply {
     {
         let mut map = serializer.serialize_map(None)?;
         map.serialize_entry("id", &self.id)?;
-        map.serialize_entry("content", &self.content)?;
+        map.serialize_entry("content", &self.content.0.get(&self.locale).map_or_default(|x| &**x))?;
+        map.serialize_entry("contentLocale", &self.locale)?;
+        map.serialize_entry("locales", &Locales(&self.content))?;
         map.serialize_entry("publishTime", &get_millis(self.publish))?;
         if self.publish != self.edit {
             map.serialize_entry("editTime", &get_millis(self.edit))?;
@@ -48,18 +56,19 @@ impl TryFrom<Row> for Reply {
 
     fn try_from(row: Row) -> Result<Self, Self::Error> {
         let id = row.try_get::<_, i32>("id")?.cast_unsigned();
-        let content = row.try_get::<_, &str>("content")?.into();
+        let Json(content) = row.try_get::<_, Json<LocaleDict>>("rcontent")?;
+        let locale = resolve(&content, None);
         let publish = row.try_get("publish")?;
         let edit = row.try_get("edit")?;
         let did = row.try_get::<_, i32>("did")?.cast_unsigned();
         let publisher = row.try_get::<_, &str>("publisher")?.into();
-        Ok(Self { id, content, publish, edit, did, publisher })
+        Ok(Self { id, content, locale, publish, edit, did, publisher })
     }
 }
 
 impl Reply {
     pub async fn stat_head_tail(did: u32, head: u64, tail: u64, db: &mut Client) -> DBResult<Vec<Self>> {
-        const SQL: &str = "(select id, content, publish, edit, did, publisher from lean4oj.discussion_replies where did = $1 order by id limit $2::integer) union (select * from lean4oj.discussion_replies where did = $1 order by id desc limit $3::integer) order by id";
+        const SQL: &str = "(select id, rcontent, publish, edit, did, publisher from lean4oj.discussion_replies where did = $1 order by id limit $2::integer) union (select id, rcontent, publish, edit, did, publisher from lean4oj.discussion_replies where did = $1 order by id desc limit $3::integer) order by id";
 
         let stmt = db.prepare_static(SQL.into()).await?;
         let stream = db.query_raw(&stmt, [did.cast_signed(), head as i32, tail as i32]).await?;
@@ -67,12 +76,50 @@ impl Reply {
     }
 
     pub async fn stat_interval(did: u32, before: u32, after: u32, count: u64, db: &mut Client) -> DBResult<Vec<Self>> {
-        const SQL: &str = "select id, content, publish, edit, did, publisher from lean4oj.discussion_replies where did = $1 and id > $2 and id < $3 order by id limit $4::integer";
+        const SQL: &str = "select id, rcontent, publish, edit, did, publisher from lean4oj.discussion_replies where did = $1 and id > $2 and id < $3 order by id limit $4::integer";
 
         let stmt = db.prepare_static(SQL.into()).await?;
         let stream = db.query_raw(&stmt, [did.cast_signed(), after.cast_signed(), before.cast_signed(), count as i32]).await?;
         stream.and_then(|row| ready(Self::try_from(row))).try_collect().await
     }
+
+    /// Adds or replaces the translation to `locale`, by default the one shown by default. Of `publisher` only, if given.
+    /// Also moves the discussion to the top.
+    pub async fn translate(
+        id: u32, publisher: Option<&str>, locale: Option<&str>, content: CompactString, time: SystemTime, db: &mut Client,
+    ) -> DBResult<bool> {
+        const SQL_SEL: &str = "select rcontent, did from lean4oj.discussion_replies where id = $1 and ($2::text is null or publisher = $2) for update";
+        const SQL_UPD: &str = "update lean4oj.discussion_replies set rcontent = $2, edit = $3 where id = $1";
+        const SQL_UPDATE_PARENT: &str = "update lean4oj.discussions set update = $1 where id = $2";
+
+        let stmt_sel = db.prepare_static(SQL_SEL.into()).await?;
+        let stmt_upd = db.prepare_static(SQL_UPD.into()).await?;
+        let stmt_update_parent = db.prepare_static(SQL_UPDATE_PARENT.into()).await?;
+        let txn = db.transaction().await?;
+        let Some(row) = txn.query_opt(&stmt_sel, &[&id.cast_signed(), &publisher]).await? else { return Ok(false) };
+        let Json(mut dict) = row.try_get::<_, Json<LocaleDict>>(0)?;
+        let did = row.try_get::<_, i32>(1)?;
+        let key = locale.map_or_else(|| default_key(&dict), Into::into);
+        dict.0.insert(key, content);
+        let dict: *const Json<BTreeMap<CompactString, CompactString>> = (&raw const dict.0).cast();
+        txn.execute(&stmt_upd, &[&id.cast_signed(), unsafe { &*dict }, &time]).await?;
+        txn.execute(&stmt_update_parent, &[&time, &did]).await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+
+    /// Removes the translation to `locale`, unless it is the last one.
+    pub async fn untranslate(id: u32, publisher: Option<&str>, locale: &str, time: SystemTime, db: &mut Client) -> DBResult<bool> {
+        const SQL: &str = "update lean4oj.discussion_replies set rcontent = rcontent - $3, edit = $4 where id = $1 and ($2::text is null or publisher = $2) and rcontent ? $3 and rcontent - $3 <> '{}'";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&id.cast_signed(), &publisher, &locale, &time]).await.map(|n| n == 1)
+    }
+
+    /// Shows the translation [`LocaleDict::apply`] picks for `locale`.
+    pub fn localize(&mut self, locale: Option<&str>) {
+        self.locale = resolve(&self.content, locale);
+    }
 }
 
 #[derive(Serialize)]
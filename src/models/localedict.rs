@@ -3,7 +3,10 @@ use std::collections::BTreeMap;
 use compact_str::CompactString;
 use serde::{Deserialize, Serialize, ser::SerializeSeq};
 
-#[derive(Default, Deserialize)]
+/// What [`LocaleDict::apply`] falls back to before any other locale, and where untagged content is filed.
+pub const DEFAULT_LOCALE: &str = "zh_CN";
+
+#[derive(Clone, Debug, Default, Deserialize)]
 #[repr(transparent)]
 pub struct LocaleDict<T = CompactString>(pub BTreeMap<CompactString, T>);
 
@@ -11,7 +14,7 @@ impl<T> LocaleDict<T> {
     pub fn apply(&self, locale: Option<&str>) -> Option<&T> {
         if let Some(locale) = locale && let Some(res) = self.0.get(locale) {
             Some(res)
-        } else if let Some(res) = self.0.get("zh_CN") {
+        } else if let Some(res) = self.0.get(DEFAULT_LOCALE) {
             Some(res)
         } else {
             self.0.values().next()
@@ -21,7 +24,7 @@ impl<T> LocaleDict<T> {
     pub fn apply_with_key(&self, locale: Option<&str>) -> Option<(&CompactString, &T)> {
         if let Some(locale) = locale && let Some(res) = self.0.get_key_value(locale) {
             Some(res)
-        } else if let Some(res) = self.0.get_key_value("zh_CN") {
+        } else if let Some(res) = self.0.get_key_value(DEFAULT_LOCALE) {
             Some(res)
         } else {
             self.0.iter().next()
@@ -31,7 +34,7 @@ impl<T> LocaleDict<T> {
     pub fn apply_owned(mut self, locale: Option<&str>) -> Option<T> {
         if let Some(locale) = locale && let Some(res) = self.0.remove(locale) {
             Some(res)
-        } else if let Some(res) = self.0.remove("zh_CN") {
+        } else if let Some(res) = self.0.remove(DEFAULT_LOCALE) {
             Some(res)
         } else {
             self.0.into_values().next()
@@ -39,6 +42,19 @@ impl<T> LocaleDict<T> {
     }
 }
 
+/// Serializes the locales present, in order.
+#[repr(transparent)]
+pub struct Locales<'a, T>(pub &'a LocaleDict<T>);
+
+impl<T> Serialize for Locales<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.0.0.keys())
+    }
+}
+
 #[derive(Serialize)]
 pub struct LocaleDictEntry<'a, T> {
     pub locale: &'a str,
@@ -0,0 +1,205 @@
+use core::{convert::Infallible, future::ready, time::Duration};
+use std::{sync::LazyLock, time::SystemTime};
+
+use axum::response::sse::Event;
+use compact_str::CompactString;
+use futures_util::{Stream, StreamExt, TryStreamExt, stream};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc};
+use tokio_postgres::{AsyncMessage, Client, Row, types::ToSql};
+
+use crate::libs::{
+    broadcast::Channels,
+    constants::GLOBAL_INTERVAL,
+    db::{self, DBError, DBResult, get_connection},
+    error::BoxedStdError,
+    serde::JsTime,
+};
+
+/// Older notifications are deleted, read or not.
+const RETENTION: Duration = Duration::from_hours(90 * 24);
+/// Before listening again, once the connection is lost.
+const RELISTEN_DELAY: Duration = Duration::from_secs(5);
+
+/// What happened, which decides what is in the details.
+#[derive(Clone, Copy)]
+pub enum Kind {
+    /// To the publisher of the discussion: `discussionId`, `replyId`, `userId`.
+    DiscussionReply,
+    /// To the owner of the problem: `problemId`, `discussionId`, `userId`.
+    ProblemDiscussion,
+    /// To the owner of the problem: `problemId`, `userId`.
+    ProblemPublished,
+    /// To the submitter, once judged: `submissionId`, `problemId`, `status`.
+    SubmissionJudged,
+}
+
+impl Kind {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::DiscussionReply => "discussion.reply",
+            Self::ProblemDiscussion => "problem.discussion",
+            Self::ProblemPublished => "problem.published",
+            Self::SubmissionJudged => "submission.judged",
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: CompactString,
+    pub details: Value,
+    #[serde(serialize_with = "JsTime")]
+    pub time: SystemTime,
+    pub is_read: bool,
+}
+
+impl TryFrom<Row> for Notification {
+    type Error = DBError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let id = row.try_get::<_, i64>("id")?.cast_unsigned();
+        let kind = row.try_get::<_, &str>("kind")?.into();
+        let details = row.try_get("details")?;
+        let time = row.try_get("time")?;
+        let is_read = row.try_get("is_read")?;
+        Ok(Self { id, kind, details, time, is_read })
+    }
+}
+
+/// Streams of the users who have the notification center open here, by uid.
+static LIVE: LazyLock<Channels<CompactString, Notification>> = LazyLock::new(|| Channels::new(16));
+
+/// Best-effort like [`crate::libs::audit::log`]: what it tells of has already happened, so a failure is only logged.
+/// Subscribers on every instance hear of it through `lean4oj_notification`, see [`listen`].
+pub async fn notify(uid: &str, kind: Kind, details: Value, db: &mut Client) {
+    const SQL: &str = "with n as (insert into lean4oj.notifications (uid, kind, details, time) values ($1, $2, $3, $4) returning id) select pg_notify('lean4oj_notification', id || ' ' || $1::text) from n";
+
+    let res: DBResult<u64> = try {
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &kind.as_str(), &details, &SystemTime::now()]).await?
+    };
+    if let Err(e) = res {
+        tracing::warn!(target: "notification", "failed to notify {uid} of {}: {e:?}", kind.as_str());
+    }
+}
+
+/// Relays the notifications made by any instance to the subscribers of this one, until the connection is lost.
+async fn listen() -> Result<(), BoxedStdError> {
+    let (mut client, mut connection) = db::connect().await?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let driver = tokio::spawn(async move {
+        let mut messages = stream::poll_fn(|cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            if let AsyncMessage::Notification(n) = message? {
+                let _ = tx.send(n);
+            }
+        }
+        Ok::<_, DBError>(())
+    });
+
+    client.batch_execute("listen lean4oj_notification").await?;
+    // `<id> <uid>`.
+    while let Some(n) = rx.recv().await {
+        let Some((id, uid)) = n.payload().split_once(' ') else { continue };
+        let uid = CompactString::from(uid);
+        if !LIVE.is_subscribed(&uid) { continue }
+        let Ok(id) = id.parse() else { continue };
+        if let Some(notification) = Notification::by_id(id, &mut client).await? {
+            LIVE.send(&uid, notification);
+        }
+    }
+    Ok(driver.await??)
+}
+
+/// Deletes those older than [`RETENTION`].
+async fn prune() -> Result<u64, BoxedStdError> {
+    const SQL: &str = "delete from lean4oj.notifications where time < $1";
+
+    let mut conn = get_connection().await?;
+    let stmt = conn.prepare_static(SQL.into()).await?;
+    Ok(conn.execute(&stmt, &[&(SystemTime::now() - RETENTION)]).await?)
+}
+
+pub fn init() {
+    tokio::spawn(async {
+        loop {
+            if let Err(e) = listen().await {
+                tracing::warn!(target: "notification", "stopped listening: {e:?}");
+            }
+            tokio::time::sleep(RELISTEN_DELAY).await;
+        }
+    });
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(GLOBAL_INTERVAL);
+        loop {
+            interval.tick().await;
+            match prune().await {
+                Ok(n) => tracing::debug!(target: "notification", "pruned \x1b[32m{n}\x1b[0m notification(s)"),
+                Err(e) => tracing::warn!(target: "notification", "failed to prune: {e:?}"),
+            }
+        }
+    });
+}
+
+impl Notification {
+    pub async fn by_id(id: u64, db: &mut Client) -> DBResult<Option<Self>> {
+        pub const SQL: &str = "select id, kind, details, time, is_read from lean4oj.notifications where id = $1";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_opt(&stmt, &[&id.cast_signed()]).await?.map(Self::try_from).transpose()
+    }
+
+    /// Newest first, those before `before` (an id) if given.
+    pub async fn list(uid: &str, before: Option<u64>, unread_only: bool, take: i64, db: &mut Client) -> DBResult<Vec<Self>> {
+        pub const SQL: &str = "select id, kind, details, time, is_read from lean4oj.notifications where uid = $1 and ($2::bigint is null or id < $2) and not ($3 and is_read) order by id desc limit $4";
+
+        let before = before.map(u64::cast_signed);
+        let params: [&(dyn ToSql + Sync); 4] = [&uid, &before, &unread_only, &take];
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let stream = db.query_raw(&stmt, params).await?;
+        stream.and_then(|row| ready(Self::try_from(row))).try_collect().await
+    }
+
+    pub async fn unread_count(uid: &str, db: &mut Client) -> DBResult<u64> {
+        pub const SQL: &str = "select count(*) from lean4oj.notifications where uid = $1 and not is_read";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let row = db.query_one(&stmt, &[&uid]).await?;
+        row.try_get::<_, i64>(0).map(i64::cast_unsigned)
+    }
+
+    /// All of them if `ids` is `None`.
+    pub async fn mark_read(uid: &str, ids: Option<&[u64]>, db: &mut Client) -> DBResult<u64> {
+        pub const SQL: &str = "update lean4oj.notifications set is_read = true where uid = $1 and not is_read and ($2::bigint[] is null or id = any($2))";
+
+        let ids = ids.map(|ids| ids.iter().copied().map(u64::cast_signed).collect::<Vec<_>>());
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &ids]).await
+    }
+
+    /// Notifications to `uid` from now on, as SSE events. Those a slow client falls behind on are skipped, they are
+    /// still in [`Self::list`].
+    pub fn subscribe(uid: CompactString) -> impl Stream<Item = Result<Event, Infallible>> {
+        stream::unfold(LIVE.subscribe(uid), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(notification) => {
+                        let event = Event::default()
+                            .id(notification.id.to_string())
+                            .event("notification")
+                            .json_data(&notification)
+                            .unwrap();
+                        return Some((Ok(event), rx));
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,69 @@
+use core::future::ready;
+use std::time::SystemTime;
+
+use compact_str::CompactString;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use tokio_postgres::{Client, Row};
+
+use crate::libs::{
+    db::{DBError, DBResult},
+    serde::{JsMaybeTime, JsTime},
+};
+
+/// An account of an external identity provider (see [`crate::libs::oauth`]) that can log in as a user.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthLink {
+    pub provider: CompactString,
+    pub email: Option<CompactString>,
+    #[serde(serialize_with = "JsTime")]
+    pub create_time: SystemTime,
+    #[serde(serialize_with = "JsMaybeTime")]
+    pub last_login: Option<SystemTime>,
+}
+
+impl TryFrom<Row> for OAuthLink {
+    type Error = DBError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let provider = row.try_get::<_, &str>("provider")?.into();
+        let email = row.try_get::<_, Option<&str>>("email")?.map(Into::into);
+        let create_time = row.try_get("create_time")?;
+        let last_login = row.try_get("last_login")?;
+        Ok(Self { provider, email, create_time, last_login })
+    }
+}
+
+impl OAuthLink {
+    pub async fn list(uid: &str, db: &mut Client) -> DBResult<Vec<Self>> {
+        pub const SQL: &str = "select provider, email, create_time, last_login from lean4oj.oauth_links where uid = $1 order by provider";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let stream = db.query_raw(&stmt, [uid]).await?;
+        stream.and_then(|row| ready(Self::try_from(row))).try_collect().await
+    }
+
+    /// The user `subject` of `provider` logs in as, also recording the login.
+    pub async fn login(provider: &str, subject: &str, db: &mut Client) -> DBResult<Option<String>> {
+        pub const SQL: &str = "update lean4oj.oauth_links set last_login = $3 where provider = $1 and subject = $2 returning uid";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_opt(&stmt, &[&provider, &subject, &SystemTime::now()]).await?.map(|row| row.try_get(0)).transpose()
+    }
+
+    /// `false` if `subject` is linked to anyone, or `uid` already has an account of `provider`.
+    pub async fn link(uid: &str, provider: &str, subject: &str, email: Option<&str>, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "insert into lean4oj.oauth_links (provider, subject, uid, email, create_time) values ($1, $2, $3, $4, $5) on conflict do nothing";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&provider, &subject, &uid, &email, &SystemTime::now()]).await.map(|n| n == 1)
+    }
+
+    pub async fn unlink(uid: &str, provider: &str, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "delete from lean4oj.oauth_links where uid = $1 and provider = $2";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &provider]).await.map(|n| n == 1)
+    }
+}
@@ -0,0 +1,80 @@
+use core::{fmt, future::ready, time::Duration};
+use std::time::SystemTime;
+
+use compact_str::CompactString;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use tokio_postgres::{Client, types::ToSql};
+
+use crate::{
+    libs::{db::DBResult, serde::JsTime},
+    models::user::User,
+};
+
+/// Users ranked by the problems they solved within a window, ties going to the one who got there first.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    #[serde(rename = "userMeta")]
+    pub user: User,
+    pub rank: u64,
+    pub solved: u32,
+    /// When the last of `solved` was first accepted.
+    #[serde(serialize_with = "JsTime")]
+    pub last_accepted_time: SystemTime,
+}
+
+/// Where a page ends, as `<solved>.<last accepted time in µs>.<uid>`. Stable, unlike an offset, as new submissions come
+/// in between pages.
+pub struct Cursor {
+    pub solved: u32,
+    pub last_accepted_time: SystemTime,
+    pub uid: CompactString,
+}
+
+impl Cursor {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut iter = s.splitn(3, '.');
+        let solved = iter.next()?.parse().ok()?;
+        let micros = iter.next()?.parse().ok()?;
+        let uid = iter.next()?.into();
+        let last_accepted_time = SystemTime::UNIX_EPOCH.checked_add(Duration::from_micros(micros))?;
+        Some(Self { solved, last_accepted_time, uid })
+    }
+}
+
+impl From<&Entry> for Cursor {
+    fn from(entry: &Entry) -> Self {
+        Self { solved: entry.solved, last_accepted_time: entry.last_accepted_time, uid: entry.user.uid.clone() }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let micros = self.last_accepted_time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_micros();
+        write!(f, "{}.{micros}.{}", self.solved, self.uid)
+    }
+}
+
+/// Accepted submissions in `[from, to)`, of members of `gid` only if given, the page after `after`.
+#[allow(clippy::cast_sign_loss)]
+pub async fn list(from: SystemTime, to: SystemTime, gid: Option<&str>, after: Option<&Cursor>, take: i64, db: &mut Client) -> DBResult<Vec<Entry>> {
+    pub const SQL: &str = "with firsts as (select submitter, min(submit_time) t from lean4oj.submissions where status = '\x09' and submit_time >= $1 and submit_time < $2 and ($3::text is null or exists (select 1 from lean4oj.user_groups where gid = $3 and uid = submitter)) group by submitter, pid), \
+        scores as (select submitter uid, count(*) solved, max(t) last_ac from firsts group by submitter), \
+        ranked as (select uid, password, username, email, register_time, ac, nickname, bio, avatar_info, solved, last_ac, rank() over (order by solved desc, last_ac) from scores natural join lean4oj.users where username != '') \
+        select * from ranked where $4::bigint is null or solved < $4 or (solved = $4 and (last_ac, uid) > ($5, $6)) order by solved desc, last_ac, uid limit $7";
+
+    let solved = after.map(|c| i64::from(c.solved));
+    let last_ac = after.map(|c| c.last_accepted_time);
+    let uid = after.map(|c| &*c.uid);
+    let params: [&(dyn ToSql + Sync); 7] = [&from, &to, &gid, &solved, &last_ac, &uid, &take];
+
+    let stmt = db.prepare_static(SQL.into()).await?;
+    let stream = db.query_raw(&stmt, params).await?;
+    stream.and_then(|row| ready(try {
+        let rank = row.try_get::<_, i64>("rank")?.cast_unsigned();
+        let solved = row.try_get::<_, i64>("solved")? as u32;
+        let last_accepted_time = row.try_get("last_ac")?;
+        Entry { user: User::try_from(row)?, rank, solved, last_accepted_time }
+    })).try_collect().await
+}
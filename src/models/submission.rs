@@ -9,10 +9,10 @@ use std::{sync::LazyLock, time::SystemTime};
 
 use axum::response::sse::Event;
 use compact_str::CompactString;
-use dashmap::{DashMap, Entry};
 use futures_util::{Stream, TryStreamExt};
-use hashbrown::{DefaultHashBuilder, HashMap};
+use hashbrown::HashMap;
 use serde::{Serialize, ser::SerializeMap};
+use serde_json::json;
 use smallvec::{SmallVec, smallvec};
 use tokio::sync::broadcast;
 use tokio_postgres::{Client, Row, types::ToSql};
@@ -26,10 +26,11 @@ pub use status::Status as SubmissionStatus;
 
 use crate::{
     libs::{
+        broadcast::Channels,
         db::{DBError, DBResult, ToSqlIter},
         util::get_millis,
     },
-    models::{problem::Problem, user::User},
+    models::{notification, problem::Problem, user::User},
 };
 
 pub struct Submission {
@@ -121,19 +122,22 @@ impl Submission {
             }
         }?;
         let old = row.try_get::<_, SubmissionStatus>(0)?;
+        let pid = row.try_get::<_, i32>(1)?;
+        let submitter = row.try_get::<_, &str>(2)?;
 
         let delta = i32::from(status == SubmissionStatus::Accepted) - i32::from(old == SubmissionStatus::Accepted);
         if delta != 0 {
-            let pid = row.try_get::<_, i32>(1)?;
-            let submitter = row.try_get::<_, &str>(2)?;
             let stmt_problem_ac = db.prepare_static(SQL_PROBLEM_AC.into()).await?;
             db.execute(&stmt_problem_ac, &[&delta, &pid]).await?;
             let stmt_user_ac = db.prepare_static(SQL_USER_AC.into()).await?;
             db.execute(&stmt_user_ac, &[&submitter]).await?;
         }
 
-        if let Some(tx) = FOOD.get(&sid) {
-            let _ = tx.send(UserUpdate::Status(status, msg));
+        FOOD.send(&sid, UserUpdate::Status(status, msg));
+
+        if status.is_final() && !old.is_final() {
+            let details = json!({ "submissionId": sid, "problemId": pid, "status": status });
+            notification::notify(submitter, notification::Kind::SubmissionJudged, details, db).await;
         }
 
         Ok(())
@@ -148,9 +152,7 @@ impl Submission {
             return Err(DBError::new(tokio_postgres::error::Kind::RowCount, Some("answer update error".into())));
         }
 
-        if let Some(tx) = FOOD.get(&sid) {
-            let _ = tx.send(UserUpdate::Answer(answer));
-        }
+        FOOD.send(&sid, UserUpdate::Answer(answer));
 
         Ok(())
     }
@@ -318,9 +320,7 @@ enum UserUpdate {
     Answer(CompactString),
 }
 
-static FOOD: LazyLock<
-    DashMap<u32, broadcast::Sender<UserUpdate>, DefaultHashBuilder>
-> = LazyLock::new(|| DashMap::with_hasher(DefaultHashBuilder::default()));
+static FOOD: LazyLock<Channels<u32, UserUpdate>> = LazyLock::new(|| Channels::new(16));
 
 #[repr(transparent)]
 pub struct UserSubscription {
@@ -334,36 +334,11 @@ pub struct UserSubscription {
 impl UserSubscription {
     pub const MAX_SUBSCRIPTION: usize = 10;
 
-    async fn wait(sid: u32, tx: broadcast::Sender<UserUpdate>) {
-        let addr = unsafe { *(&raw const tx).cast::<usize>() };
-        tx.closed().await;
-        FOOD.remove_if(&sid, |_, tx1| {
-            let ret = unsafe { *ptr::from_ref(tx1).cast::<usize>() } == addr;
-            #[cfg(debug_assertions)]
-            if ret {
-                tracing::info!("UserSubscription for sid #{sid} removed.");
-            }
-            ret
-        });
-    }
-
-    fn make_one(sid: u32) -> broadcast::Receiver<UserUpdate> {
-        match FOOD.entry(sid) {
-            Entry::Occupied(e) => e.get().subscribe(),
-            Entry::Vacant(e) => {
-                let (tx, rx) = broadcast::channel(16);
-                e.insert(tx.clone());
-                tokio::spawn(Self::wait(sid, tx));
-                rx
-            }
-        }
-    }
-
     pub fn new(ids: &[u32]) -> Self {
         debug_assert!(ids.len() <= Self::MAX_SUBSCRIPTION);
         Self {
             inner: ids.iter()
-                .map(|&sid| (sid, Self::make_one(sid), None))
+                .map(|&sid| (sid, FOOD.subscribe(sid), None))
                 .collect(),
         }
     }
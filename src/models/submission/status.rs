@@ -1,7 +1,8 @@
 use core::mem;
 
+use bytes::BytesMut;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::types::{FromSql, Type, accepts};
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type, accepts, to_sql_checked};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Status {
@@ -19,13 +20,38 @@ pub enum Status {
     Accepted,
     JudgementFailed,
     Canceled,
+
+    Compiling,
+    CompilationError,
+}
+
+impl Status {
+    /// No judger will move it on by itself. Queries filter on these (`status = any($n)`), never on ordinals: `Compiling`
+    /// comes after the final ones.
+    pub const FINAL: [Self; 6] = [
+        Self::InvalidImport,
+        Self::WrongAnswer,
+        Self::Accepted,
+        Self::JudgementFailed,
+        Self::Canceled,
+        Self::CompilationError,
+    ];
+
+    pub const fn is_final(self) -> bool {
+        let mut i = 0;
+        while i < Self::FINAL.len() {
+            if Self::FINAL[i] as u8 == self as u8 { return true; }
+            i += 1;
+        }
+        false
+    }
 }
 
 impl TryFrom<u8> for Status {
     type Error = ();
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        if value < 12 {
+        if value < 14 {
             unsafe { Ok(mem::transmute::<u8, Self>(value)) }
         } else {
             Err(())
@@ -44,3 +70,13 @@ impl FromSql<'_> for Status {
 
     accepts!(CHAR);
 }
+
+impl ToSql for Status {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn core::error::Error + Send + Sync + 'static>> {
+        out.extend_from_slice(&[*self as u8]);
+        Ok(IsNull::No)
+    }
+
+    accepts!(CHAR);
+    to_sql_checked!();
+}
@@ -0,0 +1,61 @@
+use std::time::SystemTime;
+
+use compact_str::CompactString;
+use serde::Serialize;
+use tokio_postgres::{Client, Row};
+
+use crate::libs::{
+    db::{DBError, DBResult},
+    serde::{JsMaybeTime, JsTime},
+};
+
+/// A user barred from logging in, submitting, posting and uploading, `until` a time or for good.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suspension {
+    pub reason: String,
+    #[serde(serialize_with = "JsMaybeTime")]
+    pub until: Option<SystemTime>,
+    pub suspended_by: CompactString,
+    #[serde(rename = "suspendTime", serialize_with = "JsTime")]
+    pub create_time: SystemTime,
+}
+
+impl TryFrom<Row> for Suspension {
+    type Error = DBError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let reason = row.try_get("reason")?;
+        let until = row.try_get("until")?;
+        let suspended_by = row.try_get::<_, &str>("suspended_by")?.into();
+        let create_time = row.try_get("create_time")?;
+        Ok(Self { reason, until, suspended_by, create_time })
+    }
+}
+
+impl Suspension {
+    /// The suspension of `uid` in force, expired ones being ignored.
+    pub async fn of(uid: &str, db: &mut Client) -> DBResult<Option<Self>> {
+        pub const SQL: &str = "select reason, until, suspended_by, create_time from lean4oj.user_suspensions where uid = $1 and (until is null or until > $2)";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_opt(&stmt, &[&uid, &SystemTime::now()]).await?.map(Self::try_from).transpose()
+    }
+
+    /// Replaces any previous suspension of `uid`.
+    pub async fn suspend(uid: &str, actor: &str, reason: &str, until: Option<SystemTime>, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "insert into lean4oj.user_suspensions (uid, reason, until, suspended_by, create_time) values ($1, $2, $3, $4, $5) on conflict (uid) do update set reason = excluded.reason, until = excluded.until, suspended_by = excluded.suspended_by, create_time = excluded.create_time";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &reason, &until, &actor, &SystemTime::now()]).await.map(|n| n == 1)
+    }
+
+    /// `false` if `uid` was not suspended (or only by an expired suspension, which is cleared all the same).
+    pub async fn lift(uid: &str, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "delete from lean4oj.user_suspensions where uid = $1 returning until is null or until > $2";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let row = db.query_opt(&stmt, &[&uid, &SystemTime::now()]).await?;
+        row.map_or(Ok(false), |row| row.try_get(0))
+    }
+}
@@ -0,0 +1,97 @@
+use tokio_postgres::{Client, Row};
+
+use crate::libs::{
+    db::{DBError, DBResult},
+    otp,
+};
+
+/// The TOTP second factor of a user, see [`crate::libs::otp`]. A row that is not `enabled` is an enrollment in progress.
+pub struct Totp {
+    pub secret: Vec<u8>,
+    pub enabled: bool,
+    pub last_step: i64,
+    /// Digests of the unused recovery codes.
+    pub recovery_codes: Vec<Vec<u8>>,
+}
+
+impl TryFrom<Row> for Totp {
+    type Error = DBError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let secret = row.try_get("secret")?;
+        let enabled = row.try_get("enabled")?;
+        let last_step = row.try_get("last_step")?;
+        let recovery_codes = row.try_get("recovery_codes")?;
+        Ok(Self { secret, enabled, last_step, recovery_codes })
+    }
+}
+
+impl Totp {
+    pub async fn of(uid: &str, db: &mut Client) -> DBResult<Option<Self>> {
+        pub const SQL: &str = "select secret, enabled, last_step, recovery_codes from lean4oj.user_totp where uid = $1";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_opt(&stmt, &[&uid]).await?.map(Self::try_from).transpose()
+    }
+
+    /// Whether `uid` is in a group with `require_totp`.
+    pub async fn required(uid: &str, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "select from lean4oj.user_groups join lean4oj.groups using (gid) where uid = $1 and require_totp limit 1";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_opt(&stmt, &[&uid]).await.map(|x| x.is_some())
+    }
+
+    /// (Re)starts an enrollment with `secret`, `false` if TOTP is already enabled.
+    pub async fn begin(uid: &str, secret: &[u8], db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "insert into lean4oj.user_totp (uid, secret) values ($1, $2) on conflict (uid) do update set secret = excluded.secret, last_step = 0, recovery_codes = '{}' where not user_totp.enabled";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &secret]).await.map(|n| n == 1)
+    }
+
+    /// Completes the enrollment with `secret`, `step` being that of the confirming code.
+    pub async fn enable(uid: &str, secret: &[u8], step: i64, recovery_codes: &[[u8; 32]], db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "update lean4oj.user_totp set enabled = true, last_step = $3, recovery_codes = $4 where uid = $1 and secret = $2 and not enabled";
+
+        let recovery_codes = recovery_codes.iter().map(|d| &d[..]).collect::<Vec<_>>();
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &secret, &step, &recovery_codes]).await.map(|n| n == 1)
+    }
+
+    pub async fn disable(uid: &str, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "delete from lean4oj.user_totp where uid = $1";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid]).await.map(|n| n == 1)
+    }
+
+    pub async fn set_recovery_codes(uid: &str, recovery_codes: &[[u8; 32]], db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "update lean4oj.user_totp set recovery_codes = $2 where uid = $1 and enabled";
+
+        let recovery_codes = recovery_codes.iter().map(|d| &d[..]).collect::<Vec<_>>();
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &recovery_codes]).await.map(|n| n == 1)
+    }
+
+    /// Checks a code of the authenticator, or else a recovery code (consuming it), of an enabled `uid`.
+    ///
+    /// Both are single-use, concurrent uses of the same code are settled by the database.
+    pub async fn check(&self, uid: &str, code: Option<&str>, recovery_code: Option<&str>, db: &mut Client) -> DBResult<bool> {
+        pub const SQL_STEP: &str = "update lean4oj.user_totp set last_step = $2 where uid = $1 and enabled and last_step < $2";
+        pub const SQL_RECOVERY: &str = "update lean4oj.user_totp set recovery_codes = array_remove(recovery_codes, $2) where uid = $1 and enabled and $2 = any (recovery_codes)";
+
+        if !self.enabled { return Ok(false); }
+        if let Some(code) = code {
+            let Some(step) = otp::verify(&self.secret, code, self.last_step) else { return Ok(false) };
+            let stmt = db.prepare_static(SQL_STEP.into()).await?;
+            db.execute(&stmt, &[&uid, &step]).await.map(|n| n == 1)
+        } else if let Some(recovery_code) = recovery_code {
+            let digest = otp::recovery_digest(recovery_code);
+            let stmt = db.prepare_static(SQL_RECOVERY.into()).await?;
+            db.execute(&stmt, &[&uid, &&digest[..]]).await.map(|n| n == 1)
+        } else {
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use core::future::ready;
+use std::time::SystemTime;
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use compact_str::CompactString;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use tokio_postgres::{Client, Row};
+
+use crate::libs::{
+    constants::PASSWORD_LENGTH,
+    db::{DBError, DBResult},
+    password,
+    serde::{JsMaybeTime, JsTime},
+};
+
+pub const MAX_UPLOAD_TOKENS: i64 = 16;
+
+/// Credential of the rsync daemon, used as `rsync://<name>@<host>/<uid>` with the secret as the password.
+///
+/// The secret is as long as an account password, so that only its [`password::rsync_verifier`] needs to be kept. It
+/// is only shown once on creation.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadToken {
+    pub name: CompactString,
+    #[serde(skip)]
+    pub verifier: Vec<u8>,
+    pub read_only: bool,
+    #[serde(serialize_with = "JsTime")]
+    pub create_time: SystemTime,
+    #[serde(serialize_with = "JsMaybeTime")]
+    pub last_used: Option<SystemTime>,
+}
+
+impl TryFrom<Row> for UploadToken {
+    type Error = DBError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let name = row.try_get::<_, &str>("name")?.into();
+        let verifier = row.try_get("verifier")?;
+        let read_only = row.try_get("read_only")?;
+        let create_time = row.try_get("create_time")?;
+        let last_used = row.try_get("last_used")?;
+        Ok(Self { name, verifier, read_only, create_time, last_used })
+    }
+}
+
+impl UploadToken {
+    fn gen_secret() -> CompactString {
+        use rand::RngCore;
+
+        let mut buf = [0u8; 32];
+        rand::rng().fill_bytes(&mut buf);
+        let secret = CompactString::from(BASE64_URL_SAFE_NO_PAD.encode(buf));
+        debug_assert_eq!(secret.len(), PASSWORD_LENGTH);
+        secret
+    }
+
+    pub async fn list(uid: &str, db: &mut Client) -> DBResult<Vec<Self>> {
+        pub const SQL: &str = "select name, verifier, read_only, create_time, last_used from lean4oj.upload_tokens where uid = $1 order by create_time";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let stream = db.query_raw(&stmt, [uid]).await?;
+        stream.and_then(|row| ready(Self::try_from(row))).try_collect().await
+    }
+
+    pub async fn by_name(uid: &str, name: &str, db: &mut Client) -> DBResult<Option<Self>> {
+        pub const SQL: &str = "select name, verifier, read_only, create_time, last_used from lean4oj.upload_tokens where uid = $1 and name = $2";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        match db.query_opt(&stmt, &[&uid, &name]).await? {
+            Some(row) => row.try_into().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the secret, or `None` if the name is taken or there are already [`MAX_UPLOAD_TOKENS`] tokens.
+    pub async fn create(uid: &str, name: &str, read_only: bool, db: &mut Client) -> DBResult<Option<CompactString>> {
+        pub const SQL: &str = "insert into lean4oj.upload_tokens (uid, name, verifier, read_only, create_time) select $1, $2, $3, $4, $5 where (select count(*) from lean4oj.upload_tokens where uid = $1) < $6 on conflict do nothing";
+
+        let secret = Self::gen_secret();
+        let verifier = password::rsync_verifier(secret.as_bytes()).map(Vec::from);
+        let stmt = db.prepare_static(SQL.into()).await?;
+        let n = db.execute(&stmt, &[&uid, &name, &verifier, &read_only, &SystemTime::now(), &MAX_UPLOAD_TOKENS]).await?;
+        Ok((n == 1).then_some(secret))
+    }
+
+    pub async fn revoke(uid: &str, name: &str, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "delete from lean4oj.upload_tokens where uid = $1 and name = $2";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &name]).await.map(|n| n == 1)
+    }
+
+    pub async fn touch(uid: &str, name: &str, db: &mut Client) -> DBResult<()> {
+        pub const SQL: &str = "update lean4oj.upload_tokens set last_used = $3 where uid = $1 and name = $2";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &name, &SystemTime::now()]).await.map(|_| ())
+    }
+}
@@ -8,8 +8,8 @@ use serde_json::Value;
 use tokio_postgres::{Client, Row};
 use tower_sessions_core::Session;
 
+use super::suspension::Suspension;
 use crate::libs::{
-    constants::PASSWORD_LENGTH,
     db::{DBError, DBResult},
     serde::JsTime,
     session::GlobalStore,
@@ -18,13 +18,16 @@ use crate::libs::{
 
 mod information;
 pub use information::Information as UserInformation;
+mod statistics;
+pub use statistics::Statistics as UserStatistics;
 
 #[derive(Serialize)]
 pub struct User {
     #[serde(rename = "id")]
     pub uid: CompactString,
+    /// See [`crate::libs::password`].
     #[serde(skip)]
-    pub password: [u8; PASSWORD_LENGTH],
+    pub password: CompactString,
     pub username: CompactString,
     pub email: CompactString,
     #[serde(rename = "registrationTime", serialize_with = "JsTime")]
@@ -42,10 +45,7 @@ impl TryFrom<Row> for User {
 
     fn try_from(row: Row) -> Result<Self, Self::Error> {
         let uid = row.try_get::<_, &str>("uid")?.into();
-        let password = row.try_get::<_, &str>("password")?.as_bytes();
-        let password = password.try_into().map_err(|e|
-            DBError::new(tokio_postgres::error::Kind::FromSql(1), Some(Box::new(e)))
-        )?;
+        let password = row.try_get::<_, &str>("password")?.into();
         let username = row.try_get::<_, &str>("username")?.into();
         let email = row.try_get::<_, &str>("email")?.into();
         let register_time = row.try_get("register_time")?;
@@ -73,6 +73,16 @@ impl User {
         Ok(result)
     }
 
+    pub async fn by_email(email: &str, db: &mut Client) -> DBResult<Option<Self>> {
+        pub const SQL: &str = "select uid, password, username, email, register_time, ac, nickname, bio, avatar_info from lean4oj.users where email = $1 and username != ''";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        match db.query_opt(&stmt, &[&email]).await? {
+            Some(row) => row.try_into().map(Some),
+            None => Ok(None),
+        }
+    }
+
     pub async fn from_session(session: &Session<GlobalStore>, db: &mut Client) -> DBResult<Option<Self>> {
         let Ok(Some(Value::String(uid))) = session.get_value("uid").await else { return Ok(None) };
         Self::by_uid(&uid, db).await
@@ -105,6 +115,44 @@ impl User {
         stream.and_then(|row| ready(Self::try_from(row))).try_collect().await
     }
 
+    /// Unverified accounts can neither submit nor post discussions.
+    pub async fn is_email_verified(&self, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "select email_verified from lean4oj.users where uid = $1";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_one(&stmt, &[&&*self.uid]).await?.try_get(0)
+    }
+
+    /// Whether the email was confirmed by a mailed link, not just taken as verified for accounts older than
+    /// verification.
+    pub async fn is_email_confirmed(&self, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "select email_confirmed from lean4oj.users where uid = $1";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_one(&stmt, &[&&*self.uid]).await?.try_get(0)
+    }
+
+    /// See [`crate::libs::password::rsync_verifier`]; missing until the first login since passwords were hashed.
+    pub async fn rsync_verifier(&self, db: &mut Client) -> DBResult<Option<Vec<u8>>> {
+        pub const SQL: &str = "select rsync_verifier from lean4oj.users where uid = $1";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.query_one(&stmt, &[&&*self.uid]).await?.try_get(0)
+    }
+
+    /// Suspended accounts can neither log in, submit, post discussions nor upload.
+    pub async fn is_suspended(&self, db: &mut Client) -> DBResult<bool> {
+        Suspension::of(&self.uid, db).await.map(|s| s.is_some())
+    }
+
+    /// Only succeeds if the email is still `email`.
+    pub async fn verify_email(uid: &str, email: &str, db: &mut Client) -> DBResult<bool> {
+        pub const SQL: &str = "update lean4oj.users set email_verified = true, email_confirmed = true where uid = $1 and email = $2";
+
+        let stmt = db.prepare_static(SQL.into()).await?;
+        db.execute(&stmt, &[&uid, &email]).await.map(|n| n == 1)
+    }
+
     pub async fn count(db: &mut Client) -> DBResult<u64> {
         pub const SQL: &str = "select count(*) from lean4oj.users where username != ''";
 
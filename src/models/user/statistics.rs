@@ -0,0 +1,73 @@
+use core::future::ready;
+
+use compact_str::CompactString;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use tokio_postgres::{Client, types::ToSql};
+
+use crate::{libs::db::DBResult, models::tag::Tag};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    #[serde(flatten)]
+    pub tag: Tag,
+    pub accepted: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolchainCount {
+    pub lean_toolchain: CompactString,
+    pub submissions: u32,
+    pub accepted: u32,
+}
+
+/// What a user has solved and tried, and with which toolchains, among the problems the viewer can see.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statistics {
+    pub solved_problem_ids: Vec<i32>,
+    pub attempted_problem_ids: Vec<i32>,
+    pub accepted_per_tag: Vec<TagCount>,
+    pub lean_toolchains: Vec<ToolchainCount>,
+}
+
+impl Statistics {
+    /// Of `uid`, as seen by `viewer`; `all` shows the problems that are neither public nor owned by the viewer too.
+    #[allow(clippy::cast_sign_loss)]
+    pub async fn of(uid: &str, viewer: Option<&str>, all: bool, db: &mut Client) -> DBResult<Self> {
+        pub const SQL_PROBLEMS: &str = "select s.pid, bool_or(s.status = '\x09') from lean4oj.submissions s inner join lean4oj.problems p on p.pid = s.pid where s.submitter = $1 and ($2 or p.is_public or p.owner = $3) group by s.pid order by s.pid";
+        pub const SQL_TAGS: &str = "select id, color, name, count(*) as accepted from lean4oj.tags inner join lean4oj.problem_tags on id = tid where pid = any($1) group by id order by id";
+        pub const SQL_TOOLCHAINS: &str = "select s.lean_toolchain, count(*), count(*) filter (where s.status = '\x09') from lean4oj.submissions s inner join lean4oj.problems p on p.pid = s.pid where s.submitter = $1 and ($2 or p.is_public or p.owner = $3) group by s.lean_toolchain order by count(*) desc, s.lean_toolchain";
+
+        let mut solved_problem_ids = Vec::new();
+        let mut attempted_problem_ids = Vec::new();
+        let stmt = db.prepare_static(SQL_PROBLEMS.into()).await?;
+        let rows = db.query(&stmt, &[&uid, &all, &viewer]).await?;
+        for row in rows {
+            let pid = row.try_get::<_, i32>(0)?;
+            if row.try_get(1)? { solved_problem_ids.push(pid) } else { attempted_problem_ids.push(pid) }
+        }
+
+        let stmt = db.prepare_static(SQL_TAGS.into()).await?;
+        let stream = db.query_raw(&stmt, [&solved_problem_ids]).await?;
+        let accepted_per_tag = stream.and_then(|row| ready(try {
+            let accepted = row.try_get::<_, i64>("accepted")? as u32;
+            TagCount { tag: Tag::try_from(row)?, accepted }
+        })).try_collect().await?;
+
+        let params: [&(dyn ToSql + Sync); 3] = [&uid, &all, &viewer];
+        let stmt = db.prepare_static(SQL_TOOLCHAINS.into()).await?;
+        let stream = db.query_raw(&stmt, params).await?;
+        let lean_toolchains = stream.and_then(|row| ready(try {
+            ToolchainCount {
+                lean_toolchain: row.try_get::<_, &str>(0)?.into(),
+                submissions: row.try_get::<_, i64>(1)? as u32,
+                accepted: row.try_get::<_, i64>(2)? as u32,
+            }
+        })).try_collect().await?;
+
+        Ok(Self { solved_problem_ids, attempted_problem_ids, accepted_per_tag, lean_toolchains })
+    }
+}
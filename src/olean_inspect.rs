@@ -0,0 +1,362 @@
+#![feature(
+    ascii_char,
+    const_index,
+    const_trait_impl,
+    pattern,
+    ptr_cast_array,
+    result_option_map_or_default,
+    unsafe_cell_access,
+)]
+#![allow(
+    confusable_idents,
+    mixed_script_confusables,
+    uncommon_codepoints,
+)]
+
+mod libs {
+    pub mod olean;
+    pub mod util;
+    pub mod validate;
+}
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use compact_str::CompactString;
+use serde::Serialize;
+
+use libs::olean::{self, SINGLE_FILE_LIMIT, TOTAL_FILE_LIMIT, TOTAL_FILE_NUM, check_suffix};
+
+const USAGE: &str = "\
+usage: olean-inspect [--json] <file.olean>...
+       olean-inspect [--json] [--uid <uid>] --check <.lake/build/lib/lean>";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Inspection {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_module: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    imports: Option<Vec<CompactString>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    consts: Option<Vec<CompactString>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Problem {
+    path: String,
+    message: String,
+}
+
+#[derive(Default, Serialize)]
+struct Report {
+    files: usize,
+    size: usize,
+    problems: Vec<Problem>,
+}
+
+impl Report {
+    fn problem(&mut self, path: &str, message: impl Into<String>) {
+        self.problems.push(Problem { path: path.to_owned(), message: message.into() });
+    }
+}
+
+fn inspect(path: &Path) -> Inspection {
+    let mut ret = Inspection {
+        path: path.display().to_string(),
+        version: None,
+        is_module: None,
+        imports: None,
+        consts: None,
+        error: None,
+    };
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            ret.error = Some(e.to_string());
+            return ret;
+        }
+    };
+    let Some(version) = olean::lean_version(&data) else {
+        ret.error = Some("not a valid olean file (or unsupported Lean version)".to_owned());
+        return ret;
+    };
+    ret.version = Some(format!("4{version}"));
+    let Some(meta) = olean::parse_meta(&data) else {
+        ret.error = Some("cannot parse olean header".to_owned());
+        return ret;
+    };
+    ret.is_module = Some(meta.is_module());
+    ret.imports = olean::parse_imports(meta);
+    ret.consts = olean::parse_consts(meta);
+    if ret.imports.is_none() {
+        ret.error = Some("cannot parse imports".to_owned());
+    } else if ret.consts.is_none() {
+        ret.error = Some("cannot parse constants".to_owned());
+    }
+    ret
+}
+
+fn print_inspection(Inspection { path, version, is_module, imports, consts, error }: &Inspection) {
+    println!("{path}");
+    if let Some(version) = version {
+        println!("  version: {version}");
+    }
+    if let Some(is_module) = is_module {
+        println!("  module: {is_module}");
+    }
+    if let Some(imports) = imports {
+        println!("  imports ({}):", imports.len());
+        for import in imports { println!("    {import}"); }
+    }
+    if let Some(consts) = consts {
+        println!("  consts ({}):", consts.len());
+        for c in consts { println!("    {c}"); }
+    }
+    if let Some(error) = error {
+        println!("  error: {error}");
+    }
+}
+
+fn walk(dir: &Path, rel: &mut String, out: &mut Vec<(String, PathBuf, u64)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else { continue };
+        let base_len = rel.len();
+        if !rel.is_empty() { rel.push('/'); }
+        rel.push_str(&name);
+        let type_ = entry.file_type()?;
+        if type_.is_dir() {
+            walk(&entry.path(), rel, out)?;
+        } else if type_.is_file() {
+            out.push((rel.clone(), entry.path(), entry.metadata()?.len()));
+        }
+        rel.truncate(base_len);
+    }
+    Ok(())
+}
+
+#[inline]
+fn looks_like_olean(path: &str) -> bool {
+    [".olean", ".olean.server", ".olean.private", ".ir"].into_iter().any(|s| path.ends_with(s))
+}
+
+/// Mirrors what the rsync receiver accepts and what the deposit later requires.
+fn check(root: &Path, uid: Option<&str>) -> io::Result<Report> {
+    let mut report = Report::default();
+    let mut files = Vec::new();
+    walk(root, &mut String::new(), &mut files)?;
+    files.sort_unstable();
+
+    let prefix = uid.map(|uid| format!("{uid}/"));
+    let modules = files.iter()
+        .filter_map(|(rel, ..)| rel.strip_suffix(".olean"))
+        .map(|m| m.replace('/', "."))
+        .collect::<BTreeSet<_>>();
+
+    for (rel, path, size) in &files {
+        let suffix = if let Some(ref prefix) = prefix {
+            let Some(suffix) = rel.strip_prefix(&**prefix) else { continue };
+            suffix
+        } else {
+            rel
+        };
+        if !check_suffix(suffix) {
+            if looks_like_olean(suffix) {
+                report.problem(rel, "module name must not need escaping, the server will ignore this file");
+            }
+            continue;
+        }
+        if *size as usize > SINGLE_FILE_LIMIT {
+            report.problem(rel, "file exceeds 16 MB, the server will ignore this file");
+            continue;
+        }
+        report.files += 1;
+        report.size += *size as usize;
+
+        let data = fs::read(path)?;
+        if olean::lean_version(&data).is_none() {
+            report.problem(rel, "not a valid olean file (or unsupported Lean version)");
+            continue;
+        }
+        let Some(module) = rel.strip_suffix(".olean") else { continue };
+        let Some(meta) = olean::parse_meta(&data) else {
+            report.problem(rel, "cannot parse olean header");
+            continue;
+        };
+        let Some(imports) = olean::parse_imports(meta) else {
+            report.problem(rel, "cannot parse imports");
+            continue;
+        };
+        for import in imports {
+            let local = if let Some(uid) = uid {
+                import.strip_prefix(uid).is_some_and(|s| s.starts_with('.'))
+            } else {
+                !olean::is_std(&import)
+            };
+            if local {
+                if !modules.contains(&*import) {
+                    report.problem(rel, format!("{import}: imported module not found in {}", root.display()));
+                }
+            } else if !olean::is_std(&import) {
+                report.problem(rel, format!("{import}: invalid import (neither standard nor under {})", module.split('/').next().unwrap_or_default()));
+            }
+        }
+    }
+
+    if report.files == 0 {
+        let msg = if let Some(uid) = uid { format!("nothing to upload under {uid}/") } else { "nothing to upload".to_owned() };
+        report.problem(&root.display().to_string(), msg);
+    }
+    if report.size > TOTAL_FILE_LIMIT {
+        report.problem(&root.display().to_string(), "total file size exceeds 1 GB");
+    }
+    if report.files > TOTAL_FILE_NUM {
+        report.problem(&root.display().to_string(), "too many files");
+    }
+    Ok(report)
+}
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut uid = None;
+    let mut root = None;
+    let mut paths = Vec::new();
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--json") => json = true,
+            Some("--uid") => uid = args.next().and_then(|s| s.into_string().ok()),
+            Some("--check") => root = args.next().map(PathBuf::from),
+            Some("-h" | "--help") => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    olean::init();
+
+    if let Some(root) = root {
+        if !paths.is_empty() {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+        let report = match check(&root, uid.as_deref()) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("{}: {e}", root.display());
+                return ExitCode::FAILURE;
+            }
+        };
+        if json {
+            println!("{}", serde_json::to_string(&report).unwrap_or_default());
+        } else {
+            for Problem { path, message } in &report.problems {
+                eprintln!("{path}: {message}");
+            }
+            println!("{} file(s), {} byte(s) checked, {} problem(s) found.", report.files, report.size, report.problems.len());
+        }
+        return if report.problems.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+    }
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    }
+    let results = paths.iter().map(|p| inspect(p)).collect::<Vec<_>>();
+    if json {
+        println!("{}", serde_json::to_string(&results).unwrap_or_default());
+    } else {
+        results.iter().for_each(print_inspection);
+    }
+    if results.iter().all(|r| r.error.is_none()) { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use serde_json::json;
+
+    use super::{check, libs::olean::{self, tests::synthesize_module}, walk};
+
+    fn olean(imports: &[&str]) -> Vec<u8> {
+        synthesize_module(imports, &["Foo.bar"])
+    }
+
+    /// `alice/` with a good olean, a corrupt one, one with bad imports, one whose name needs escaping and a stray file.
+    fn tree(root: &Path) -> u64 {
+        let dir = root.join("alice");
+        fs::create_dir_all(dir.join("Sub")).unwrap();
+        let files: [(&str, Vec<u8>); 5] = [
+            ("Sub/Good.olean", olean(&["Init", "alice.Missing"])),
+            ("Bad.olean", b"olean but not really".to_vec()),
+            ("Missing.olean", olean(&["Evil.Thing", "alice.Gone", "alice.Sub.Good"])),
+            ("Foo Bar.olean", olean(&[])),
+            ("README.md", b"# hi".to_vec()),
+        ];
+        let mut size = 0;
+        for (name, data) in files {
+            if name.ends_with(".olean") && !name.contains(' ') { size += data.len() as u64; }
+            fs::write(dir.join(name), data).unwrap();
+        }
+        size
+    }
+
+    #[test]
+    fn test_walk() {
+        let root = tempfile::tempdir().unwrap();
+        tree(root.path());
+        let mut files = Vec::new();
+        walk(root.path(), &mut String::new(), &mut files).unwrap();
+        files.sort_unstable();
+        let rels = files.iter().map(|(rel, ..)| &**rel).collect::<Vec<_>>();
+        assert_eq!(rels, ["alice/Bad.olean", "alice/Foo Bar.olean", "alice/Missing.olean", "alice/README.md", "alice/Sub/Good.olean"]);
+        for (_, path, size) in &files {
+            assert_eq!(fs::metadata(path).unwrap().len(), *size);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        olean::init();
+        let root = tempfile::tempdir().unwrap();
+        let size = tree(root.path());
+        let report = check(root.path(), Some("alice")).unwrap();
+        let dir = root.path().display();
+
+        assert_eq!(serde_json::to_value(&report).unwrap(), json!({
+            "files": 3,
+            "size": size,
+            "problems": [
+                { "path": "alice/Bad.olean", "message": "not a valid olean file (or unsupported Lean version)" },
+                { "path": "alice/Foo Bar.olean", "message": "module name must not need escaping, the server will ignore this file" },
+                { "path": "alice/Missing.olean", "message": "Evil.Thing: invalid import (neither standard nor under alice)" },
+                { "path": "alice/Missing.olean", "message": format!("alice.Gone: imported module not found in {dir}") },
+                { "path": "alice/Sub/Good.olean", "message": format!("alice.Missing: imported module not found in {dir}") },
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_check_other_uid() {
+        olean::init();
+        let root = tempfile::tempdir().unwrap();
+        tree(root.path());
+        let report = check(root.path(), Some("bob")).unwrap();
+        assert_eq!(report.files, 0);
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].message, "nothing to upload under bob/");
+    }
+}
@@ -1,7 +1,7 @@
 use core::{ascii::Char, mem, slice};
 
 use base64::{Engine, prelude::BASE64_STANDARD};
-use openssl::sha::Sha256;
+use openssl::memcmp;
 use tokio::{
     io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
     net::{
@@ -15,51 +15,54 @@ use crate::{
         constants::PASSWORD_LENGTH,
         db::get_connection,
         error::{BoxedStdError, serialize_err},
+        password,
         util::gen_random_ascii,
     },
-    models::user::User,
+    models::{upload_token::UploadToken, user::User},
 };
-use io::ReadPossibleLine;
+use compress::Compression;
+use io::{ReadPossibleLine, read_vstring};
 use mode::Mode;
+use options::Options;
 
+mod checksum;
+mod compress;
 mod file_entry;
 mod io;
 mod jumping;
 mod mode;
 mod multiplex;
+mod options;
 mod read;
 mod write;
 
+/// Whether the account password is accepted besides upload tokens, unless built with `RSYNC_ACCOUNT_PASSWORD=0`.
+const ACCOUNT_PASSWORD: bool = !matches!(option_env!("RSYNC_ACCOUNT_PASSWORD"), Some("0"));
+
 fn protocol_version(s: &str) -> Option<u32> {
     let suffix = s.strip_prefix("@RSYNCD: ")?.as_bytes();
     let n = suffix.iter().position(|&b| !b.is_ascii_digit()).unwrap_or(suffix.len());
     u32::from_ascii(unsafe { suffix.get_unchecked(..n) }).ok()
 }
 
+/// `hash` is the SHA-256 of `<password><challenge>`.
 #[inline]
-fn check_password(password: &[u8; PASSWORD_LENGTH], salt: &[Char; 16], response: Option<&str>) -> bool {
-    let found = if let Some(a) = response && let Some(b) = a.as_bytes().as_array::<PASSWORD_LENGTH>() {
-        b
-    } else {
+fn check_password(hash: Option<[u8; 32]>, response: Option<&str>) -> bool {
+    let (Some(hash), Some(found)) = (hash, response.and_then(|a| a.as_bytes().as_array::<PASSWORD_LENGTH>())) else {
         return false;
     };
-    let mut sha256 = Sha256::new();
-    sha256.update(password);
-    sha256.update(salt.as_bytes());
-    let hash = sha256.finish();
     let mut b64hash = [0u8; PASSWORD_LENGTH];
     BASE64_STANDARD.internal_encode(&hash, &mut b64hash);
-    b64hash == *found
+    memcmp::eq(&b64hash, found)
 }
 
 async fn main_inner(
     c2s: OwnedReadHalf,
     s2c: OwnedWriteHalf,
-    #[cfg_attr(debug_assertions, allow(unused_variables))]
     salt: [Char; 16],
 ) -> Result<(), BoxedStdError> {
     let mut c2s = BufReader::new(c2s);
-    let s2c = BufWriter::new(s2c);
+    let mut s2c = BufWriter::new(s2c);
 
     let ss = c2s.read_u32_le().await?;
     if ss > 1024 { return Err("reverse proxy error".into()); }
@@ -76,7 +79,7 @@ async fn main_inner(
 
     let mut s = String::new();
     (&mut c2s).take(1024).read_line(&mut s).await?;
-    let Some(30..) = protocol_version(&s) else {
+    let Some(protocol @ 30..) = protocol_version(&s) else {
         return Err(format!("invalid first line: {s}").into());
     };
     s.clear();
@@ -90,6 +93,7 @@ async fn main_inner(
 
     let mut mode = Mode::Write;
     let mut delete = false;
+    let mut options = Options::default();
     loop {
         let s = unsafe { &*c2s.read_possible_line::<0, 10>().await? };
         if s == b"--sender" {
@@ -99,40 +103,82 @@ async fn main_inner(
         && (s.len() == 8 || unsafe { *s.get_unchecked(8) } == b'-') {
             delete = true;
         }
+        options.parse(s);
         if s.is_empty() {
             break;
         }
     }
 
+    // compat flags, checksum list (and compression list), checksum seed.
+    let offered = if mode == Mode::Read { Compression::SENDER } else { Compression::RECEIVER };
+    let compress = options.compress || options.compress_choice.is_some();
+    s2c.write_all(b"\x81\xfe\x04sha1").await?;
+    if compress && options.compress_choice.is_none() {
+        s2c.write_all(&Compression::list(offered)).await?;
+    }
+    s2c.write_all(b"\0\0\0\0").await?;
+    s2c.flush().await?;
+
+    let hash = read_vstring(&mut c2s).await?;
+    tracing::debug!(target: "lean4rsync", "client hash: {:?}", hash.utf8_chunks().debug());
+    let compression = if compress {
+        let choice = match options.compress_choice {
+            Some(ref choice) => choice.clone(),
+            None => read_vstring(&mut c2s).await?,
+        };
+        let Some(c) = Compression::negotiate(&choice, offered) else {
+            return Err(format!(
+                "none of the compressions {:?} is supported (only {:?} here)",
+                choice.utf8_chunks().debug(),
+                offered.iter().map(|c| c.name()).collect::<Vec<_>>(),
+            ).into());
+        };
+        c
+    } else {
+        Compression::None
+    };
+    tracing::debug!(target: "lean4rsync", "compression: {compression:?}");
+
+    // The rsync user names an upload token, whose secret is the password, or is the uid itself for the account
+    // password.
+    let mut auth = s.split_ascii_whitespace();
+    let name = auth.next().unwrap_or_default();
+    let response = auth.next();
+    let mut conn = get_connection().await?;
+    let Some(user) = User::by_uid(&uid, &mut conn).await? else {
+        return Err(format!("unknown user: {uid}").into());
+    };
+    let token = UploadToken::by_name(&user.uid, name, &mut conn).await?;
+    let token = token.filter(|t| check_password(password::rsync_response(&t.verifier, salt.as_bytes()), response));
+    let account = token.is_none()
+        && ACCOUNT_PASSWORD
+        && name.eq_ignore_ascii_case(&user.uid)
+        && user.rsync_verifier(&mut conn).await?.is_some_and(|v| check_password(password::rsync_response(&v, salt.as_bytes()), response));
+    if token.is_none() && !account {
+        let hint = if ACCOUNT_PASSWORD { format!(", or as rsync://{uid}@<host>/{uid} with the password") } else { String::new() };
+        return Err(format!("authentication failed for user {uid} with {name:?} (connect as rsync://<token>@<host>/{uid}{hint})").into());
+    }
+    if let Some(ref token) = token && token.read_only && mode == Mode::Write {
+        return Err(format!("token {name:?} is read-only").into());
+    }
+    if mode == Mode::Write && user.is_suspended(&mut conn).await? {
+        return Err(format!("user {uid} is suspended").into());
+    }
+    if token.is_some() { UploadToken::touch(&user.uid, name, &mut conn).await?; }
+    drop(conn);
+
     match mode {
-        Mode::Read => read::main(c2s, s2c, sni, uid),
-        Mode::Write => {
-            let user = {
-                let mut conn = get_connection().await?;
-                match User::by_uid(&uid, &mut conn).await? {
-                    Some(u) => u,
-                    None => return Err(format!("unknown user: {uid}").into()),
-                }
-            };
-
-            #[cfg(debug_assertions)]
-            return write::main(c2s, s2c, delete, &sni, user).await;
-
-            #[cfg(not(debug_assertions))]
-            if check_password(&user.password, &salt, s.split_ascii_whitespace().nth(1)) {
-                write::main(c2s, s2c, delete, &sni, user).await
-            } else {
-                Err(format!("authentication failed for user: {uid}").into())
-            }
-        }
+        Mode::Read => read::main(c2s, s2c, &options, protocol.min(32), user).await,
+        Mode::Write => write::main(c2s, s2c, delete, compression, &sni, user).await,
     }
 }
 
 async fn handle(mut socket: UnixStream) {
     let buf = gen_random_ascii::<16>();
     let _ = socket.write_all(b"@RSYNCD: 32.0 sha256\n@RSYNCD: AUTHREQD ").await;
+    let _ = socket.write_all(password::RSYNC_CHALLENGE_PREFIX).await;
     let _ = socket.write_all(buf.as_bytes()).await;
-    let _ = socket.write_all(b"\n@RSYNCD: OK\n\x81\xfe\x04sha1\0\0\0\0").await;
+    let _ = socket.write_all(b"\n@RSYNCD: OK\n").await;
     let (c2s, s2c, mut socket) = socket.tri_split();
     let res = main_inner(c2s, s2c, buf).await;
     let socket = unsafe { std::sync::Arc::get_mut_unchecked(&mut socket) };
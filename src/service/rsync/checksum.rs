@@ -0,0 +1,95 @@
+use openssl::sha::sha1;
+
+const BLOCK_SIZE: u32 = 700;
+const MAX_BLOCK_SIZE: u32 = 1 << 17;
+// rsync's `SUM_LENGTH`, long enough that a false match of sha1 prefixes is out of the question.
+const S2LENGTH: u32 = 16;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SumHead {
+    pub count: u32,
+    pub blength: u32,
+    pub s2length: u32,
+    pub remainder: u32,
+}
+
+impl SumHead {
+    /// Block length as chosen by `sum_sizes_sqroot` of rsync.
+    pub fn new(len: usize) -> Self {
+        let len = len as u64;
+        let blength = if len <= u64::from(BLOCK_SIZE * BLOCK_SIZE) {
+            BLOCK_SIZE
+        } else {
+            let mut c = 1u32;
+            let mut l = len >> 2;
+            while l != 0 {
+                c <<= 1;
+                l >>= 2;
+            }
+            if c >= MAX_BLOCK_SIZE {
+                MAX_BLOCK_SIZE
+            } else {
+                let mut b = 0;
+                while c >= 8 {
+                    b |= c;
+                    if len < u64::from(b) * u64::from(b) { b &= !c; }
+                    c >>= 1;
+                }
+                b.max(BLOCK_SIZE)
+            }
+        };
+        let remainder = (len % u64::from(blength)) as u32;
+        let count = (len / u64::from(blength)) as u32 + u32::from(remainder != 0);
+        Self { count, blength, s2length: S2LENGTH, remainder }
+    }
+
+    pub fn parse(b: &[u8; 16]) -> Option<Self> {
+        let x = |i: usize| u32::from_le_bytes(*unsafe { b.get_unchecked(i * 4..) }.first_chunk().unwrap());
+        let (count, blength, s2length, remainder) = (x(0), x(1), x(2), x(3));
+        (count == 0 || ((1..=MAX_BLOCK_SIZE).contains(&blength) && s2length <= 20 && remainder < blength))
+            .then_some(Self { count, blength, s2length, remainder })
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        for x in [self.count, self.blength, self.s2length, self.remainder] {
+            buf.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+
+    /// Total length of the basis file described by this head.
+    pub fn basis_len(&self) -> usize {
+        match self.count {
+            0 => 0,
+            n if self.remainder != 0 => (n as usize - 1) * self.blength as usize + self.remainder as usize,
+            n => n as usize * self.blength as usize,
+        }
+    }
+
+    pub fn block<'a>(&self, basis: &'a [u8], i: u32) -> Option<&'a [u8]> {
+        if i >= self.count { return None; }
+        let start = i as usize * self.blength as usize;
+        basis.get(start..(start + self.blength as usize).min(basis.len()))
+    }
+}
+
+/// `get_checksum1` of rsync, the bytes are taken as signed.
+pub fn checksum1(buf: &[u8]) -> u32 {
+    let mut s1 = 0u32;
+    let mut s2 = 0u32;
+    for &b in buf {
+        s1 = s1.wrapping_add(b.cast_signed() as u32);
+        s2 = s2.wrapping_add(s1);
+    }
+    (s1 & 0xffff) | s2 << 16
+}
+
+/// Appends the sum head and block checksums of `basis`, for the sender to compute a delta against.
+pub fn generate(basis: &[u8], buf: &mut Vec<u8>) {
+    let head = SumHead::new(basis.len());
+    head.write(buf);
+    buf.reserve(head.count as usize * (4 + S2LENGTH as usize));
+    for block in basis.chunks(head.blength as usize) {
+        buf.extend_from_slice(&checksum1(block).to_le_bytes());
+        buf.extend_from_slice(unsafe { sha1(block).get_unchecked(..S2LENGTH as usize) });
+    }
+}
@@ -0,0 +1,189 @@
+use core::mem;
+use std::io;
+
+use flate2::{Decompress, FlushDecompress};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use zstd::stream::raw::{Decoder, InBuffer, Operation, OutBuffer};
+
+const END_FLAG: u8 = 0;
+const DEFLATED_DATA: u8 = 0x40;
+const TOKEN_REL: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zlibx,
+    Zstd,
+}
+
+impl Compression {
+    /// What the receiver can decode, in order of preference.
+    pub const RECEIVER: &[Self] = &[Self::Zstd, Self::Zlibx, Self::None];
+    /// The sender only emits literal data, so it never compresses.
+    pub const SENDER: &[Self] = &[Self::None];
+
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"none" => Some(Self::None),
+            b"zlibx" => Some(Self::Zlibx),
+            b"zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Zlibx => "zlibx",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Space-separated list as sent in the handshake (a vstring).
+    pub fn list(offered: &[Self]) -> Vec<u8> {
+        let mut ret = vec![0];
+        for (i, c) in offered.iter().enumerate() {
+            if i != 0 { ret.push(b' '); }
+            ret.extend_from_slice(c.name().as_bytes());
+        }
+        ret[0] = (ret.len() - 1) as u8;
+        ret
+    }
+
+    /// Like rsync, take the first one in the client's list that we also offer.
+    pub fn negotiate(client: &[u8], offered: &[Self]) -> Option<Self> {
+        client
+            .split(|&b| b == b' ' || b == b',')
+            .filter_map(Self::from_name)
+            .find(|c| offered.contains(c))
+    }
+}
+
+pub enum Token {
+    /// Literal data has been written to the output.
+    Data,
+    /// Copy block `.0` of the basis.
+    Block(u32),
+    End,
+}
+
+/// Decoder of the (possibly compressed) token stream of a sender, see `token.c` of rsync.
+pub struct TokenReader {
+    compression: Compression,
+    inflate: Decompress,
+    inflated: bool,
+    zstd: Option<Decoder<'static>>,
+    token: u32,
+    run: u16,
+    cbuf: Vec<u8>,
+}
+
+fn overflow() -> io::Error {
+    io::const_error!(io::ErrorKind::InvalidData, "too much data for the file")
+}
+
+impl TokenReader {
+    pub fn new(compression: Compression) -> io::Result<Self> {
+        Ok(Self {
+            compression,
+            inflate: Decompress::new(false),
+            inflated: false,
+            zstd: if compression == Compression::Zstd { Some(Decoder::new()?) } else { None },
+            token: 0,
+            run: 0,
+            cbuf: Vec::new(),
+        })
+    }
+
+    fn inflate(&mut self, mut input: &[u8], out: &mut &mut [u8]) -> io::Result<()> {
+        while !input.is_empty() {
+            let (in0, out0) = (self.inflate.total_in(), self.inflate.total_out());
+            self.inflate.decompress(input, &mut **out, FlushDecompress::Sync)?;
+            let consumed = (self.inflate.total_in() - in0) as usize;
+            let produced = (self.inflate.total_out() - out0) as usize;
+            if consumed == 0 && produced == 0 {
+                return Err(if out.is_empty() { overflow() } else { io::const_error!(io::ErrorKind::InvalidData, "stalled deflate stream") });
+            }
+            input = unsafe { input.get_unchecked(consumed..) };
+            *out = unsafe { mem::take(out).get_unchecked_mut(produced..) };
+        }
+        Ok(())
+    }
+
+    fn unzstd(&mut self, out: &mut &mut [u8]) -> io::Result<()> {
+        let Some(ref mut zstd) = self.zstd else { unreachable!() };
+        let mut input = InBuffer::around(&self.cbuf);
+        while input.pos() < self.cbuf.len() {
+            let in0 = input.pos();
+            let mut output = OutBuffer::around(&mut **out);
+            zstd.run(&mut input, &mut output)?;
+            let produced = output.pos();
+            if input.pos() == in0 && produced == 0 {
+                return Err(if out.is_empty() { overflow() } else { io::const_error!(io::ErrorKind::InvalidData, "stalled zstd stream") });
+            }
+            *out = unsafe { mem::take(out).get_unchecked_mut(produced..) };
+        }
+        Ok(())
+    }
+
+    /// Reads the next token, literal data goes straight into the front of `out` (which is then advanced).
+    pub async fn next<R>(&mut self, mut rx: R, out: &mut &mut [u8]) -> io::Result<Token>
+    where
+        R: AsyncRead + Unpin,
+    {
+        if self.compression == Compression::None {
+            let token = rx.read_i32_le().await?;
+            return Ok(match token {
+                0 => Token::End,
+                1.. => {
+                    let Some(chunk) = out.split_off_mut(..token.cast_unsigned() as usize) else { return Err(overflow()) };
+                    rx.read_exact(chunk).await?;
+                    Token::Data
+                }
+                _ => Token::Block((!token).cast_unsigned()),
+            });
+        }
+
+        if self.run != 0 {
+            self.run -= 1;
+            self.token += 1;
+            return Ok(Token::Block(self.token));
+        }
+        let mut flag = rx.read_u8().await?;
+        if flag & 0xc0 == DEFLATED_DATA {
+            let n = usize::from(flag & 0x3f) << 8 | usize::from(rx.read_u8().await?);
+            self.cbuf.resize(n, 0);
+            rx.read_exact(&mut self.cbuf).await?;
+            if self.compression == Compression::Zstd {
+                self.unzstd(out)?;
+            } else {
+                let cbuf = mem::take(&mut self.cbuf);
+                let res = self.inflate(&cbuf, out);
+                self.cbuf = cbuf;
+                res?;
+                self.inflated = true;
+            }
+            return Ok(Token::Data);
+        }
+        if self.inflated {
+            // the sender strips the trailer of each sync flush.
+            self.inflate(&[0, 0, 0xff, 0xff], out)?;
+            self.inflated = false;
+        }
+        if flag == END_FLAG {
+            if self.compression == Compression::Zlibx { self.inflate.reset(false); }
+            self.token = 0;
+            return Ok(Token::End);
+        }
+        if flag & TOKEN_REL != 0 {
+            self.token += u32::from(flag & 0x3f);
+            flag >>= 6;
+        } else {
+            self.token = rx.read_i32_le().await?.cast_unsigned();
+        }
+        if flag & 1 != 0 {
+            self.run = rx.read_u16_le().await?;
+        }
+        Ok(Token::Block(self.token))
+    }
+}
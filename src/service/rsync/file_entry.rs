@@ -2,18 +2,22 @@ use core::{cmp::Ordering, fmt, mem::MaybeUninit, ptr, slice};
 use std::{
     ffi::OsStr,
     fs,
+    io::Read,
     os::fd::{FromRawFd, RawFd},
     path::{Component, Components, Path},
 };
 
 use openssl::sha::Sha1;
 
+use crate::libs::olean::SINGLE_FILE_LIMIT;
+
 pub struct FileEntry {
     pub(super) path: Vec<u8>,
     pub(super) size: usize,
     pub(super) sha1: [u8; 20],
     pub(super) enabled: usize,
     pub(super) mode: libc::mode_t,
+    pub(super) mtime: i64,
 }
 
 impl FileEntry {
@@ -32,6 +36,18 @@ impl FileEntry {
         sha1.finish() == self.sha1
     }
 
+    /// Content of the file currently at the destination, used as the basis of delta transfer.
+    pub fn basis(&self, dir: RawFd) -> Option<Vec<u8>> {
+        let fd = unsafe { libc::openat(dir, self.path.as_ptr().add(self.enabled).cast(), libc::O_RDONLY) };
+        if fd == -1 { return None; }
+        let mut f = unsafe { fs::File::from_raw_fd(fd) };
+        let meta = f.metadata().ok()?;
+        if !meta.is_file() || meta.len() > SINGLE_FILE_LIMIT as u64 { return None; }
+        let mut buf = Vec::with_capacity(meta.len() as usize);
+        f.read_to_end(&mut buf).ok()?;
+        Some(buf)
+    }
+
     #[inline]
     pub fn path(&self) -> &Path {
         Path::new(unsafe { OsStr::from_encoded_bytes_unchecked(&self.path) })
@@ -10,6 +10,39 @@ pub trait ReadVarintRsync {
     async fn read_varint<const BASE: usize>(&mut self) -> io::Result<u64>;
 }
 
+/// Counterpart of [`ReadVarintRsync::read_varint`] (rsync's `write_varint` / `write_varlong`, with `BASE + 1` minimum bytes).
+pub fn write_varint<const BASE: usize>(buf: &mut Vec<u8>, x: u64) {
+    let min = BASE + 1;
+    let mut b = [0u8; 9];
+    b[1..].copy_from_slice(&x.to_le_bytes());
+    let mut cnt = 8;
+    while cnt > min && b[cnt] == 0 { cnt -= 1; }
+    let bit = 1u8 << (7 - cnt + min);
+    if b[cnt] >= bit {
+        cnt += 1;
+        b[0] = !(bit - 1);
+    } else if cnt > min {
+        b[0] = b[cnt] | !(bit * 2 - 1);
+    } else {
+        b[0] = b[cnt];
+    }
+    buf.extend_from_slice(&b[..cnt]);
+}
+
+/// rsync's `read_vstring`: one length byte (two if the high bit is set), then the bytes.
+pub async fn read_vstring<R>(mut rx: R) -> io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len = usize::from(rx.read_u8().await?);
+    if len & 0x80 != 0 {
+        len = (len & 0x7f) << 8 | usize::from(rx.read_u8().await?);
+    }
+    let mut buf = vec![0; len];
+    rx.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
 pub trait ReadPossibleLine {
     async fn read_possible_line<const C1: u8, const C2: u8>(&mut self) -> io::Result<*const [u8]>;
 }
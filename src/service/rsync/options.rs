@@ -0,0 +1,37 @@
+/// The few server arguments that change what goes on the wire.
+#[derive(Default)]
+pub struct Options {
+    pub always_checksum: bool,
+    pub preserve_uid: bool,
+    pub preserve_gid: bool,
+    pub numeric_ids: bool,
+    pub compress: bool,
+    /// `--compress-choice`, which skips the negotiation.
+    pub compress_choice: Option<Vec<u8>>,
+}
+
+impl Options {
+    pub fn parse(&mut self, arg: &[u8]) {
+        match arg {
+            b"--numeric-ids" => self.numeric_ids = true,
+            b"--new-compress" => self.compress_choice = Some(b"zlibx".to_vec()),
+            b"--old-compress" => self.compress_choice = Some(b"zlib".to_vec()),
+            _ => (),
+        }
+        if let Some(choice) = arg.strip_prefix(b"--compress-choice=") {
+            self.compress_choice = Some(choice.to_vec());
+        }
+        let Some(short) = arg.strip_prefix(b"-") else { return };
+        if short.starts_with(b"-") { return; }
+        // everything after `e` is the capability string, e.g. `-logDtprze.iLsfxCIvu`.
+        for &c in short.iter().take_while(|&&c| c != b'e') {
+            match c {
+                b'c' => self.always_checksum = true,
+                b'o' => self.preserve_uid = true,
+                b'g' => self.preserve_gid = true,
+                b'z' => self.compress = true,
+                _ => (),
+            }
+        }
+    }
+}
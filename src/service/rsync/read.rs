@@ -1,38 +1,256 @@
-use core::{error, fmt};
-use std::io;
+use std::{
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
 
+use openssl::sha::sha1;
 use tokio::{
-    io::{BufReader, BufWriter},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter, simplex, sink},
     net::unix::{OwnedReadHalf, OwnedWriteHalf},
+    spawn,
+    task::spawn_blocking,
 };
 
-use crate::libs::error::{BoxedStdError, DynStdError};
+use super::{
+    file_entry::FileEntry,
+    io::{ReadVarintRsync, write_varint},
+    jumping::Jumping,
+    multiplex::c2s_multiplex,
+    options::Options,
+};
+use crate::{
+    libs::{error::BoxedStdError, olean::TOTAL_FILE_NUM},
+    models::user::User,
+};
 
-const INNER: io::Error = io::const_error!(io::ErrorKind::Unsupported, "operation not supported on this platform");
+const CHUNK_SIZE: usize = 0x8000;
+
+fn walk(dir: &Path, rel: &mut Vec<u8>, checksum: bool, out: &mut Vec<FileEntry>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let type_ = entry.file_type()?;
+        if !type_.is_dir() && !type_.is_file() { continue; }
+        let meta = entry.metadata()?;
+        let base_len = rel.len();
+        if !rel.is_empty() { rel.push(b'/'); }
+        rel.extend_from_slice(entry.file_name().as_encoded_bytes());
+        let sha1 = if checksum && type_.is_file() {
+            sha1(&fs::read(entry.path())?)
+        } else {
+            [0; 20]
+        };
+        out.push(FileEntry {
+            path: rel.clone(),
+            size: meta.len() as usize,
+            sha1,
+            enabled: 0,
+            mode: meta.mode(),
+            mtime: meta.mtime(),
+        });
+        if out.len() > TOTAL_FILE_NUM {
+            return Err(io::const_error!(io::ErrorKind::FileTooLarge, "too many files"));
+        }
+        if type_.is_dir() {
+            walk(&entry.path(), rel, checksum, out)?;
+        }
+        rel.truncate(base_len);
+    }
+    Ok(())
+}
 
-#[derive(Debug)]
-struct UnsupportedReadError {
-    sni: String,
-    uid: String,
+fn generate_file_list(root: &Path, uid: &str, checksum: bool) -> Result<Vec<FileEntry>, BoxedStdError> {
+    let meta = match fs::metadata(root) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(format!("nothing has been uploaded to {uid} yet").into()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut ret = vec![FileEntry {
+        path: b".".to_vec(),
+        size: meta.len() as usize,
+        sha1: [0; 20],
+        enabled: 0,
+        mode: meta.mode(),
+        mtime: meta.mtime(),
+    }];
+    walk(root, &mut Vec::new(), checksum, &mut ret)?;
+    ret.sort();
+    Ok(ret)
 }
 
-impl fmt::Display for UnsupportedReadError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Please use `wget -e robots=off -r -l 0 -nH -np --cut-dirs=1 -R 'index.html,index.html.tmp' '{}/lean/{}/'` (no backticks) to download files.", self.sni, self.uid)
+async fn send(s2c: &mut BufWriter<OwnedWriteHalf>, buf: &mut Vec<u8>, written: &mut u64) -> io::Result<()> {
+    for chunk in buf.chunks(0x00ff_ffff) {
+        s2c.write_u32_le(chunk.len() as u32 | 0x0700_0000).await?;
+        s2c.write_all(chunk).await?;
+        *written += chunk.len() as u64;
     }
+    buf.clear();
+    s2c.flush().await
 }
 
-impl error::Error for UnsupportedReadError {
-    fn source(&self) -> Option<&DynStdError> {
-        Some(const { &INNER })
+async fn expect_done<R>(mut rx: R, state: &mut Jumping) -> Result<(), BoxedStdError>
+where
+    R: AsyncRead + Unpin,
+{
+    match state.recv(&mut rx).await {
+        Err(e) if e.raw_os_error() == Some(1349) => Ok(()),
+        Ok(idx) => Err(format!("unexpected index {idx} (expecting done)").into()),
+        Err(e) => Err(e.into()),
     }
 }
 
-pub fn main(
-    _c2s: BufReader<OwnedReadHalf>,
-    _s2c: BufWriter<OwnedWriteHalf>,
-    sni: String,
-    uid: String,
+#[allow(clippy::too_many_lines)]
+pub async fn main(
+    c2s: BufReader<OwnedReadHalf>,
+    mut s2c: BufWriter<OwnedWriteHalf>,
+    options: &Options,
+    protocol: u32,
+    user: User,
 ) -> Result<(), BoxedStdError> {
-    Err(UnsupportedReadError { sni, uid }.into())
+    let (mut rx, tx) = simplex(0x4_0000);
+    let mut handler = [const { None }; 256];
+    handler[7] = Some(tx);
+    spawn(c2s_multiplex(c2s, handler));
+
+    if rx.read_u32_le().await? != 0 {
+        return Err("Do not specify rule explicitly. Server always sends the whole directory.".into());
+    }
+
+    let root = PathBuf::from(format!("{}/lean/{}", env!("OLEAN_ROOT"), user.uid));
+    let fl = {
+        let root = root.clone();
+        let uid = user.uid.clone();
+        let checksum = options.always_checksum;
+        spawn_blocking(move || generate_file_list(&root, &uid, checksum)).await??
+    };
+
+    /******** file list ********/
+    let mut buf = Vec::new();
+    let mut written = 0;
+    let mut total_size = 0;
+    for entry in &fl {
+        let len = entry.path.len();
+        let mut xflags = 0x18; // XMIT_SAME_UID | XMIT_SAME_GID
+        if entry.path == b"." { xflags |= 0x1; } // XMIT_TOP_DIR
+        if len > 255 { xflags |= 0x40; } // XMIT_LONG_NAME
+        write_varint::<0>(&mut buf, xflags);
+        if len > 255 {
+            write_varint::<0>(&mut buf, len as u64);
+        } else {
+            buf.push(len as u8);
+        }
+        buf.extend_from_slice(&entry.path);
+        write_varint::<2>(&mut buf, entry.size as u64);
+        write_varint::<3>(&mut buf, entry.mtime.cast_unsigned());
+        buf.extend_from_slice(&entry.mode.to_le_bytes());
+        if entry.mode & libc::S_IFMT == libc::S_IFREG {
+            total_size += entry.size as u64;
+            if options.always_checksum {
+                buf.extend_from_slice(&entry.sha1);
+            }
+        }
+        if buf.len() >= 0x80_0000 {
+            send(&mut s2c, &mut buf, &mut written).await?;
+        }
+    }
+    buf.extend_from_slice(&[0, 0]); // end of list, no io error.
+    if !options.numeric_ids {
+        // empty uid / gid lists, terminated by id 0 with an empty name (CF_ID0_NAMES).
+        if options.preserve_uid { buf.extend_from_slice(&[0, 0]); }
+        if options.preserve_gid { buf.extend_from_slice(&[0, 0]); }
+    }
+    send(&mut s2c, &mut buf, &mut written).await?;
+
+    /******** files ********/
+    let mut rstate = Jumping::default();
+    let mut wstate = Jumping::default();
+    let mut phase = 0;
+    let mut tot = 0;
+    loop {
+        let idx = match rstate.recv(&mut rx).await {
+            Ok(i) => i,
+            Err(e) if e.raw_os_error() == Some(1349) => {
+                phase += 1;
+                if phase > 2 { break; }
+                buf.push(0);
+                send(&mut s2c, &mut buf, &mut written).await?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let Some(entry) = fl.get(idx as usize) else {
+            return Err(format!("index {idx} out of range {}", fl.len()).into());
+        };
+        let iflags = rx.read_u16_le().await?;
+        buf.extend_from_slice(wstate.emit(idx, &mut [0; 5]));
+        buf.extend_from_slice(&iflags.to_le_bytes());
+        if iflags & 0x0800 != 0 { // ITEM_BASIS_TYPE_FOLLOWS
+            buf.push(rx.read_u8().await?);
+        }
+        if iflags & 0x1000 != 0 { // ITEM_XNAME_FOLLOWS
+            let hi = rx.read_u8().await?;
+            buf.push(hi);
+            let len = if hi & 0x80 != 0 {
+                let lo = rx.read_u8().await?;
+                buf.push(lo);
+                usize::from(hi & 0x7f) << 8 | usize::from(lo)
+            } else {
+                hi.into()
+            };
+            let start = buf.len();
+            buf.resize(start + len, 0);
+            rx.read_exact(unsafe { buf.get_unchecked_mut(start..) }).await?;
+        }
+        if iflags & 0x8000 != 0 { // ITEM_TRANSFER
+            if entry.mode & libc::S_IFMT != libc::S_IFREG {
+                return Err(format!("file #{idx} is not a regular file").into());
+            }
+            let mut head = [0u8; 16];
+            rx.read_exact(&mut head).await?;
+            let count = u32::from_le_bytes(*head.first_chunk().unwrap());
+            let s2length = u32::from_le_bytes(*head[8..].first_chunk().unwrap());
+            if count > 0x10_0000 || s2length > 64 {
+                return Err(format!("invalid sum head: {count} blocks, checksum length {s2length}").into());
+            }
+            // block checksums are of no use, the whole file goes as literal data.
+            let sums = u64::from(count) * u64::from(s2length + 4);
+            if tokio::io::copy(&mut (&mut rx).take(sums), &mut sink()).await? != sums {
+                return Err("unexpected end of block checksums".into());
+            }
+            buf.extend_from_slice(&head);
+
+            tracing::debug!(target: "lean4rsync-reader", "\x1b[36mfile sending: {entry:?}\x1b[0m");
+            let data = tokio::fs::read(root.join(entry.path())).await?;
+            for chunk in data.chunks(CHUNK_SIZE) {
+                buf.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+                buf.extend_from_slice(chunk);
+            }
+            buf.extend_from_slice(&[0; 4]);
+            buf.extend_from_slice(&sha1(&data));
+            tot += 1;
+        }
+        if buf.len() >= 0x80_0000 {
+            send(&mut s2c, &mut buf, &mut written).await?;
+        }
+    }
+    buf.push(0);
+
+    let s = format!("======== {tot} file(s) sent. ========\n");
+    send(&mut s2c, &mut buf, &mut written).await?;
+    s2c.write_u32_le(s.len() as u32 | 0x0a00_0000).await?;
+    s2c.write_all(s.as_bytes()).await?;
+
+    // total read (not tracked), total written, total size, file list build / transfer time.
+    for x in [0, written, total_size, 0, 0] {
+        write_varint::<2>(&mut buf, x);
+    }
+    send(&mut s2c, &mut buf, &mut written).await?;
+
+    expect_done(&mut rx, &mut rstate).await?;
+    if protocol >= 31 {
+        buf.push(0);
+        send(&mut s2c, &mut buf, &mut written).await?;
+        expect_done(&mut rx, &mut rstate).await?;
+    }
+    Ok(())
 }
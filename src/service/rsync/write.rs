@@ -3,22 +3,20 @@ use core::{
     index::Last,
     mem::{DropGuard, MaybeUninit},
     ptr, slice,
-    str::pattern::Pattern,
 };
 use std::{
     ffi::OsStr,
-    fs::{Permissions, ReadDir},
+    fs::Permissions,
     io,
     os::{
         fd::{AsRawFd, RawFd},
-        unix::fs::{DirEntryExt2, PermissionsExt},
+        unix::fs::PermissionsExt,
     },
     path::PathBuf,
-    str::pattern::Searcher,
-    sync::Arc,
 };
 
 use hashbrown::HashSet;
+use openssl::sha::sha1;
 use tempfile::Builder;
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter, simplex},
@@ -27,74 +25,26 @@ use tokio::{
 };
 
 use super::{
-    file_entry::FileEntry, io::ReadVarintRsync, jumping::Jumping, mode::Mode,
-    multiplex::c2s_multiplex,
+    checksum::{self, SumHead}, compress::{Compression, Token, TokenReader}, file_entry::FileEntry, io::ReadVarintRsync, jumping::Jumping, multiplex::c2s_multiplex,
 };
 use crate::{
     libs::{
         db::get_connection,
         error::BoxedStdError,
-        fs::{mkdir, unmap_send},
-        olean, privilege,
-        validate::is_lean_id,
+        fs::{do_delete, mkdir, readdir_from_rawfd, size_at, unmap_send},
+        olean::{self, SINGLE_FILE_LIMIT, TOTAL_FILE_NUM, check_path},
+        quota,
     },
     models::user::User,
 };
 
-const SINGLE_FILE_LIMIT: usize = 0x100_0000; // 16 MB
-const TOTAL_FILE_LIMIT: usize = 0x4000_0000; // 1 GB
-const TOTAL_FILE_NUM: usize = 0x10_0000; // 1 M
-
-const TOTAL_EXCEEDED: &str = "Total file size limit exceeds 1 GB. Please contact server administrator for a larger capacity.";
-
-fn check_prefix(prefix: &[u8]) -> bool {
-    const B: &[u8] = b"/.lake/build/lib/lean";
-    if prefix.len() <= B.len() { B.ends_with(prefix) } else { prefix.ends_with(B) }
-}
-
-fn check_suffix(suffix: &str) -> bool {
-    if let Some(a) = suffix.strip_suffix(".ir") {
-        return a.split('/').all(is_lean_id)
-    }
-    let b = if let Some(a) = suffix.strip_suffix(".server") { a }
-    else if let Some(a) = suffix.strip_suffix(".private") { a }
-    else { suffix };
-    b.strip_suffix(".olean").is_some_and(|c| c.split('/').all(is_lean_id))
-    // Force `Name.needsNoEscape`, prevent the outrageous case like `import «foo.olean».bar`.
-}
-
-fn check_path(path: &[u8], uid_with_slash: &str) -> usize {
-    let uid_with_trailing_slash = unsafe { uid_with_slash.as_bytes().get_unchecked(1..) };
-    if path.starts_with(uid_with_trailing_slash) {
-        let t = uid_with_trailing_slash.len(); // t > 0.
-        let suffix = unsafe { path.get_unchecked(t..) };
-        if let Some(suffix) = str::from_utf8(suffix).ok()
-        && check_suffix(suffix) {
-            t
-        } else {
-            0
-        }
-    } else {
-        let mut ss = uid_with_slash.into_searcher(unsafe { str::from_utf8_unchecked(path) });
-        let Some((s, t)) = ss.next_match() else { return 0 }; // t > 0.
-        let prefix = unsafe { path.get_unchecked(..s) };
-        let suffix = unsafe { path.get_unchecked(t..) };
-        if check_prefix(prefix)
-        && let Some(suffix) = str::from_utf8(suffix).ok()
-        && check_suffix(suffix) {
-            t
-        } else {
-            0
-        }
-    }
-}
-
 async fn generate_file_list<R>(mut rx: R, uid_with_slash: &str, limit: usize) -> Result<(Vec<FileEntry>, usize), BoxedStdError>
 where
     R: AsyncRead + Unpin,
 {
     let mut s = Vec::<u8>::new();
     let mut mode = 0;
+    let mut mtime = 0;
     let mut sha1 = [0; 20];
     let mut acc = 0;
     let mut ret = Vec::new();
@@ -130,7 +80,7 @@ where
 
         let size = rx.read_varint::<2>().await?;
         if flag & 0x80 == 0 {
-            rx.read_varint::<3>().await?;
+            mtime = rx.read_varint::<3>().await?.cast_signed();
         }
         if flag & 0x2000 != 0 {
             rx.read_varint::<0>().await?;
@@ -146,7 +96,7 @@ where
                     enabled = check_path(&s, uid_with_slash);
                     if enabled != 0 {
                         acc += size as usize;
-                        if acc > limit { return Err(TOTAL_EXCEEDED.into()); }
+                        if acc > limit { return Err(quota::exceeded(limit).into()); }
                     }
                 }
             }
@@ -157,80 +107,20 @@ where
         let mut path = Vec::with_capacity(s.len() + 1);
         path.extend_from_slice(&s);
         unsafe { path.as_mut_ptr().add(path.len()).write(0); } // make it NUL-terminated to be friendly with C.
-        ret.push(FileEntry { path, size: size as usize, sha1, enabled, mode });
+        ret.push(FileEntry { path, size: size as usize, sha1, enabled, mode, mtime });
         if ret.len() > TOTAL_FILE_NUM {
             return Err("too many files".into());
         }
     }
 }
 
-/// Just Cthulhu.
-#[allow(clippy::arc_with_non_send_sync, clippy::transmute_undefined_repr)]
-fn readdir_from_rawfd(fd: RawFd) -> io::Result<ReadDir> {
-    let ptr = unsafe { libc::fdopendir(fd) };
-    if ptr.is_null() { return Err(io::Error::last_os_error()); }
-    Ok(unsafe {
-        core::mem::transmute::<[Option<Arc<[*mut libc::DIR; 4]>>; 2], ReadDir>([
-            Some(Arc::new([ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr])),
-            None,
-        ])
-    })
-}
-
-fn do_delete(
-    cwd: &mut PathBuf,
-    exempt: &HashSet<&[u8]>,
-    dir: RawFd,
-    mode: Mode,
-    readdir: &mut ReadDir,
-) -> io::Result<(usize, bool)> {
-    let mut delcnt = 0;
-    let mut alived = false;
-    let mut stat = MaybeUninit::<libc::stat>::uninit();
-    let base_len = cwd.as_os_str().len();
-    for entry in readdir {
-        let entry = entry?;
-        let name = entry.file_name_ref();
-        let type_ = entry.file_type()?;
-        cwd.push(name);
-        let pname = name.as_encoded_bytes().as_ptr();
-        if type_.is_dir() {
-            let fd = unsafe { libc::openat(dir, pname.cast(), libc::O_DIRECTORY) };
-            if fd == -1 { return Err(io::Error::last_os_error()); }
-            let (sub_delcnt, sub_alived) = do_delete(cwd, exempt, fd, mode, &mut readdir_from_rawfd(fd)?)?;
-            delcnt += sub_delcnt;
-            if sub_alived {
-                alived = true;
-            } else if mode == Mode::Write {
-                if unsafe { libc::unlinkat(dir, pname.cast(), libc::AT_REMOVEDIR) } != 0 { return Err(io::Error::last_os_error()); }
-                delcnt += 1;
-            }
-        } else if exempt.contains(cwd.as_os_str().as_encoded_bytes()) {
-            alived = true;
-        } else {
-            match mode {
-                Mode::Read => #[allow(clippy::cast_sign_loss)] {
-                    if unsafe { libc::fstatat(dir, pname.cast(), stat.as_mut_ptr(), 0) } != 0 { return Err(io::Error::last_os_error()); }
-                    delcnt += unsafe { stat.assume_init_ref() }.st_size as usize;
-                }
-                Mode::Write => {
-                    if unsafe { libc::unlinkat(dir, pname.cast(), 0) } != 0 { return Err(io::Error::last_os_error()); }
-                    delcnt += 1;
-                }
-            }
-        }
-        cwd.as_mut_os_string().truncate(base_len);
-    }
-
-    Ok((delcnt, alived))
-}
-
-async fn receive<R>(mut rx: R, fl: &mut [FileEntry], dir: RawFd) -> Result<usize, BoxedStdError>
+async fn receive<R>(mut rx: R, fl: &mut [FileEntry], dir: RawFd, compression: Compression) -> Result<usize, BoxedStdError>
 where
     R: AsyncRead + Unpin,
 {
     let mut n = 0;
     let mut state = Jumping::default();
+    let mut tokens = TokenReader::new(compression)?;
     let mut buf = [MaybeUninit::<u8>::uninit(); 24];
     let buf18 = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr().cast(), 18) };
     let buf20 = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr().cast(), 20) };
@@ -250,6 +140,13 @@ where
         }
         rx.read_exact(buf18).await?;
         tracing::debug!(target: "lean4rsync-writer", "\x1b[35mfile receiving: {entry:?}\x1b[0m");
+        let Some(head) = SumHead::parse(unsafe { &*buf18.as_ptr().add(2).cast() }) else {
+            return Err(format!("invalid sum head for file #{idx}").into());
+        };
+        let basis = if head.count != 0 { entry.basis(dir).unwrap_or_default() } else { Vec::new() };
+        if basis.len() != head.basis_len() {
+            return Err(format!("basis of file #{idx} changed during transfer").into());
+        }
 
         let target = unsafe { entry.path.get_unchecked_mut(entry.enabled..) };
         mkdir(target, dir)?;
@@ -265,26 +162,38 @@ where
             }
         }
         let (mut buf, g) = unsafe {
-            let raw = libc::mmap(ptr::null_mut(), entry.size, libc::PROT_WRITE, libc::MAP_SHARED, f.as_raw_fd(), 0);
+            let raw = libc::mmap(ptr::null_mut(), entry.size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, f.as_raw_fd(), 0);
             (
                 slice::from_raw_parts_mut(raw.cast(), entry.size),
                 DropGuard::new((raw as usize, entry.size), unmap_send),
             )
         };
         loop {
-            let size = rx.read_u32_le().await?;
-            if size == 0 {
-                break;
+            match tokens.next(&mut rx, &mut buf).await? {
+                Token::Data => (),
+                Token::Block(i) => {
+                    let Some(block) = head.block(&basis, i) else {
+                        return Err(format!("block {i} out of range {}", head.count).into());
+                    };
+                    let Some(chunk) = buf.split_off_mut(..block.len()) else {
+                        return Err(format!("block too large: {} / {}", block.len(), buf.len()).into());
+                    };
+                    chunk.copy_from_slice(block);
+                }
+                Token::End => break,
             }
-            let Some(chunk) = buf.split_off_mut(..size as usize) else {
-                return Err(format!("chunk too large: {size} / {}", buf.len()).into());
-            };
-            rx.read_exact(chunk).await?;
         }
         if !buf.is_empty() {
             return Err(format!("{} bytes missing", buf.len()).into());
         }
+        rx.read_exact(buf20).await?;
         let buf = unsafe { slice::from_raw_parts(g.0 as *const u8, entry.size) };
+        if sha1(buf) != *buf20 {
+            return Err(format!(
+                "{}: checksum mismatch",
+                unsafe { OsStr::from_encoded_bytes_unchecked(target) }.display(),
+            ).into());
+        }
         if olean::lean_version(buf).is_none() {
             return Err(format!(
                 "{}: not a valid olean file",
@@ -308,26 +217,19 @@ where
                 return Err(io::Error::last_os_error().into());
             }
         }
-        rx.read_exact(buf20).await?;
         n += 1;
     }
 }
 
 #[allow(clippy::too_many_lines)]
 pub async fn main(
-    mut c2s: BufReader<OwnedReadHalf>,
+    c2s: BufReader<OwnedReadHalf>,
     mut s2c: BufWriter<OwnedWriteHalf>,
     delete: bool,
+    compression: Compression,
     sni: &str,
     user: User,
 ) -> Result<(), BoxedStdError> {
-    let mut l = 0u8;
-    let mut buf = [MaybeUninit::<u8>::uninit(); 255];
-    let _ = c2s.read(slice::from_mut(&mut l)).await?;
-    let l1 = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr().cast(), l.into()) };
-    c2s.read_exact(l1).await?;
-    tracing::debug!(target: "lean4rsync-writer", "client hash: {:?}", l1.utf8_chunks().debug());
-
     let (mut rx, tx) = simplex(0x4_0000);
     let mut handler = [const { None }; 256];
     handler[7] = Some(tx);
@@ -337,13 +239,9 @@ pub async fn main(
         return Err("Do not specify rule explicitly (in deletion mode). Server will filter automatically.".into());
     }
 
-    let limit = {
+    let (limit, used) = {
         let mut conn = get_connection().await?;
-        if privilege::check(&user.uid, "Lean4OJ.TooManyOLeans", &mut conn).await? {
-            usize::MAX
-        } else {
-            TOTAL_FILE_LIMIT
-        }
+        (quota::limit(&user.uid, &mut conn).await?, quota::used(&user.uid, &mut conn).await?)
     };
     let mut buf = String::with_capacity(env!("OLEAN_ROOT").len() + user.uid.len() + 7);
     buf.push_str(env!("OLEAN_ROOT"));
@@ -377,8 +275,9 @@ pub async fn main(
     let mut chestnut = readdir_from_rawfd(base_dir_fd)?;
 
     if !delete {
-        let acc2 = do_delete(&mut PathBuf::new(), &exempt, base_dir_fd, Mode::Read, &mut chestnut)?.0;
-        if acc + acc2 > limit { return Err(TOTAL_EXCEEDED.into()); }
+        // the recorded usage, less the files about to be replaced.
+        let kept = used.saturating_sub(size_at(base_dir_fd, exempt.iter().copied())?);
+        if acc + kept > limit { return Err(quota::exceeded(limit).into()); }
     }
 
     let mut state = Jumping::default();
@@ -398,10 +297,9 @@ pub async fn main(
         buf.extend_from_slice(state.emit(idx as u32, &mut [0; 5]));
         buf.push(0);
         buf.push(0x80);
-        buf.reserve(16);
-        unsafe {
-            ptr::write_bytes(buf.as_mut_ptr().add(buf.len()), 0, 16);
-            buf.set_len(buf.len() + 16);
+        match file.basis(base_dir_fd) {
+            Some(basis) => checksum::generate(&basis, &mut buf),
+            None => buf.extend_from_slice(&[0; 16]),
         }
         if buf.len() >= 0x80_0000 {
             s2c.write_u32_le(buf.len() as u32 | 0x0700_0000).await?;
@@ -416,11 +314,16 @@ pub async fn main(
     s2c.flush().await?;
 
     let delcnt = if delete {
-        do_delete(&mut PathBuf::new(), &exempt, base_dir_fd, Mode::Write, &mut chestnut)?.0
+        do_delete(&mut PathBuf::new(), &exempt, base_dir_fd, true, &mut chestnut)?.0
     } else {
         0
     };
-    let tot = receive(&mut rx, &mut fl, base_dir_fd).await?;
+    let tot = receive(&mut rx, &mut fl, base_dir_fd, compression).await;
+    let mut conn = get_connection().await?;
+    if let Err(e) = quota::refresh(&user.uid, &mut conn).await {
+        tracing::warn!(target: "lean4rsync-writer", "failed to refresh storage usage of {}: {e:?}", user.uid);
+    }
+    let tot = tot?;
 
     let s = {
         let dl = if delcnt != 0 {
@@ -1,5 +1,5 @@
 use core::{
-    ffi::CStr,
+    ffi::{CStr, c_int},
     fmt::Write,
     pin::Pin,
     task::{Context, Poll, ready},
@@ -7,8 +7,12 @@ use core::{
 use std::{
     borrow::Cow,
     collections::VecDeque,
-    fs, io,
-    os::unix::fs::DirBuilderExt,
+    ffi::CString,
+    fs, io::{self, Read, Write as _},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::fs::{DirBuilderExt, OpenOptionsExt},
+    },
     str::pattern::{Pattern, Searcher},
     sync::OnceLock,
 };
@@ -31,6 +35,7 @@ use crate::{
         error::BoxedStdError,
         judger::task::{LeanAxiom, Task as JudgeTask},
         olean,
+        validate::is_lean_id,
     },
     models::submission::{
         Submission,
@@ -55,6 +60,8 @@ pub struct Task {
     pub version: &'static str,
     pub hash: [u8; 32],
     pub checker: Bytes,
+    /// Compiled by the judger from submitted sources, local oleans are taken from its scratch tree.
+    pub source: bool,
 }
 
 static TX: OnceLock<mpsc::UnboundedSender<Task>> = OnceLock::new();
@@ -85,33 +92,219 @@ fn cache_path(hash: &[u8; 32]) -> String {
     s
 }
 
-fn submission_path(sid: u32) -> io::Result<String> {
-    const ACL_EA_ACCESS: &CStr = c"system.posix_acl_access";
-
-    let mut s = String::with_capacity(env!("OLEAN_ROOT").len() + 26);
+/// `<OLEAN_ROOT>/<area>/<sid>`, with a trailing `/` and a `\0` right after the end.
+fn sid_dir(area: &str, sid: u32) -> String {
+    let mut s = String::with_capacity(env!("OLEAN_ROOT").len() + area.len() + 15);
     s.push_str(env!("OLEAN_ROOT"));
-    s.push_str("/submissions/");
+    s.push('/');
+    s.push_str(area);
+    s.push('/');
     let bytes = sid.to_le_bytes();
     let _ = write!(&mut s, "{:02x}/{:02x}/{:02x}/{:02x}/\0", bytes[3], bytes[2], bytes[1], bytes[0]);
     s.pop();
+    s
+}
+
+/// What the judge runs on: `main.lean` and the oleans it imports. With a trailing `/` and a `\0` right after the end.
+pub fn submission_dir(sid: u32) -> String {
+    sid_dir("submissions", sid)
+}
+
+/// Submitted sources, as written by [`write_sources`]. Read-only to the sandbox.
+pub fn sources_dir(sid: u32) -> String {
+    sid_dir("sources", sid)
+}
+
+/// Where the judger compiles sources to. Writable by the sandbox, so nothing in it is trusted, see [`read_compiled`].
+pub fn scratch_dir(sid: u32) -> String {
+    sid_dir("scratch", sid)
+}
+
+const READ: u16 = 5;
+const READ_WRITE: u16 = 7;
+
+/// Grant the sandbox user of submission `sid` `perm` (rwx bits) to `path`, which must be NUL-terminated.
+fn grant(path: &str, sid: u32, perm: u16) -> io::Result<()> {
+    const ACL_EA_ACCESS: &CStr = c"system.posix_acl_access";
 
-    let mut db = fs::DirBuilder::new();
-    db.recursive(true);
-    db.create(unsafe { s.get_unchecked(..s.len() - 3) })?;
-    db.recursive(false);
-    db.mode(0o770);
-    db.create(&*s)?;
     #[cfg(target_os = "linux")]
     unsafe {
         let mut acl = *b"\x02\0\0\0\x01\0\x07\0\xff\xff\xff\xff\x02\0\x05\0\0\0\0\0\x04\0\x07\0\xff\xff\xff\xff\x10\0\x07\0\xff\xff\xff\xff \0\0\0\xff\xff\xff\xff";
+        acl.as_mut_ptr().add(14).cast::<u16>().write(perm);
         acl.as_mut_ptr().add(16).cast::<u32>().write(0x10000 + sid);
-        if libc::setxattr(s.as_ptr().cast(), ACL_EA_ACCESS.as_ptr(), acl.as_ptr().cast(), 44, 0) != 0 {
+        if libc::setxattr(path.as_ptr().cast(), ACL_EA_ACCESS.as_ptr(), acl.as_ptr().cast(), 44, 0) != 0 {
             return Err(io::Error::last_os_error());
         }
     }
+    Ok(())
+}
+
+/// Creates `s` (from [`sid_dir`]), which must not exist yet, granting `perm` to the sandbox.
+fn create_sid_dir(s: &str, sid: u32, perm: u16) -> io::Result<()> {
+    let mut db = fs::DirBuilder::new();
+    db.recursive(true);
+    db.create(unsafe { s.get_unchecked(..s.len() - 3) })?;
+    db.recursive(false);
+    db.mode(0o770);
+    db.create(s)?;
+    grant(s, sid, perm)
+}
+
+fn submission_path(sid: u32) -> io::Result<String> {
+    let s = submission_dir(sid);
+    create_sid_dir(&s, sid, READ)?;
     Ok(s)
 }
 
+/// Removes a tree of [`sid_dir`], if any.
+fn remove_sid_dir(s: &str) -> io::Result<()> {
+    match fs::remove_dir_all(s) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// `<root><uid>/Foo/Bar<ext>` for module `Foo.Bar`, `root` ending with `/`.
+fn module_path(root: &str, uid: &str, module: &str, ext: &str) -> String {
+    let mut path = String::with_capacity(root.len() + uid.len() + module.len() + ext.len() + 1);
+    path.push_str(root);
+    path.push_str(uid);
+    for part in module.split('.') {
+        path.push('/');
+        path.push_str(part);
+    }
+    path.push_str(ext);
+    path
+}
+
+/// Creates `<root><uid>/Foo/` for every module `Foo.Bar`, granting `perm` to the sandbox like `root`.
+fn create_parents<S: AsRef<str>>(root: &str, uid: &str, modules: impl IntoIterator<Item = S>, sid: u32, perm: u16) -> io::Result<()> {
+    let mut db = fs::DirBuilder::new();
+    db.mode(0o770);
+    let mut created = HashSet::<String>::new();
+    for module in modules {
+        let mut path = root.to_owned();
+        path.push_str(uid);
+        for part in module.as_ref().split('.') {
+            if !created.contains(&path) {
+                match db.create(&*path) {
+                    Ok(()) => (),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+                    Err(e) => return Err(e),
+                }
+                path.push('\0');
+                grant(&path, sid, perm)?;
+                path.pop();
+                created.insert(path.clone());
+            }
+            path.push('/');
+            path.push_str(part);
+        }
+    }
+    Ok(())
+}
+
+/// Lay out `(module, content)` as `<uid>/Foo/Bar.lean` in a fresh sources tree, readable by the sandbox.
+pub fn write_sources(sid: u32, uid: &str, sources: &[(CompactString, String)]) -> io::Result<()> {
+    let root = sources_dir(sid);
+    create_sid_dir(&root, sid, READ)?;
+    create_parents(&root, uid, sources.iter().map(|(module, _)| module), sid, READ)?;
+    for (module, content) in sources {
+        fs::write(module_path(&root, uid, module, ".lean"), content)?;
+    }
+    Ok(())
+}
+
+/// An empty scratch tree for compiling `modules` into, and no submission root; to be done before every compilation.
+pub fn prepare_scratch<S: AsRef<str>>(sid: u32, uid: &str, modules: impl IntoIterator<Item = S>) -> io::Result<()> {
+    let scratch = scratch_dir(sid);
+    remove_sid_dir(&scratch)?;
+    remove_sid_dir(&submission_dir(sid))?;
+    create_sid_dir(&scratch, sid, READ_WRITE)?;
+    create_parents(&scratch, uid, modules, sid, READ_WRITE)
+}
+
+fn open_nofollow(dir: Option<&OwnedFd>, name: &str, flags: c_int) -> io::Result<OwnedFd> {
+    let name = CString::new(name)?;
+    let dirfd = dir.map_or(libc::AT_FDCWD, AsRawFd::as_raw_fd);
+    let fd = unsafe { libc::openat(dirfd, name.as_ptr(), flags | libc::O_NOFOLLOW | libc::O_CLOEXEC) };
+    if fd < 0 { return Err(io::Error::last_os_error()); }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// The olean of `module` (relative to `uid`) in the scratch tree of `sid`. The compiled code could have planted
+/// anything there, so the path is walked one component at a time without following symlinks, and only a regular file
+/// of at most [`olean::SINGLE_FILE_LIMIT`] is read.
+pub fn read_compiled(sid: u32, uid: &str, module: &str) -> io::Result<Vec<u8>> {
+    let scratch = scratch_dir(sid);
+    let mut dir = open_nofollow(None, &scratch, libc::O_RDONLY | libc::O_DIRECTORY)?;
+    dir = open_nofollow(Some(&dir), uid, libc::O_RDONLY | libc::O_DIRECTORY)?;
+    let mut parts = module.split('.').peekable();
+    while let Some(part) = parts.next() {
+        if !is_lean_id(part) { return Err(io::const_error!(io::ErrorKind::InvalidInput, "invalid module name")); }
+        if parts.peek().is_some() {
+            dir = open_nofollow(Some(&dir), part, libc::O_RDONLY | libc::O_DIRECTORY)?;
+            continue;
+        }
+        // not to hang on a FIFO.
+        let file = fs::File::from(open_nofollow(Some(&dir), &format!("{part}.olean"), libc::O_RDONLY | libc::O_NONBLOCK)?);
+        let meta = file.metadata()?;
+        if !meta.is_file() { return Err(io::const_error!(io::ErrorKind::InvalidInput, "not a regular file")); }
+        if meta.len() > olean::SINGLE_FILE_LIMIT as u64 { return Err(io::const_error!(io::ErrorKind::FileTooLarge, "file exceeds 16 MB")); }
+        let mut data = Vec::with_capacity(meta.len() as usize);
+        file.take(olean::SINGLE_FILE_LIMIT as u64).read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    Err(io::const_error!(io::ErrorKind::InvalidInput, "invalid module name"))
+}
+
+/// A new file under the submission root, which no submitted code has run in yet.
+fn create_new(path: &str) -> io::Result<fs::File> {
+    fs::OpenOptions::new().write(true).create_new(true).custom_flags(libc::O_NOFOLLOW).open(path)
+}
+
+/// Copies an olean read by [`read_compiled`] into the submission root.
+fn deposit_compiled(uid: &str, module: &str, olean: &[u8], sroot: &str) -> io::Result<()> {
+    let path = module_path(sroot, uid, module, ".olean");
+    let pos = unsafe { path.rfind('/').unwrap_unchecked() };
+    fs::create_dir_all(unsafe { path.get_unchecked(..pos) })?;
+    create_new(&path)?.write_all(olean)
+}
+
+/// `sources` are module names relative to `uid`, in compile order.
+pub fn compile_task<S: AsRef<str>>(sid: u32, version: &str, uid: &str, sources: impl IntoIterator<Item = S>) -> JudgeTask {
+    let mut version4 = CompactString::with_capacity(version.len() + 1);
+    version4.push('4');
+    version4.push_str(version);
+    let sources = sources.into_iter().map(|module| {
+        let mut path = CompactString::from(uid);
+        for part in module.as_ref().split('.') {
+            path.push('/');
+            path.push_str(part);
+        }
+        path
+    }).collect();
+    JudgeTask { sid, version: version4, axioms: SmallVec::new(), sources }
+}
+
+/// Hand `task` to a random waiting judger, returns whether someone took it.
+#[allow(clippy::significant_drop_tightening)]
+pub fn dispatch(mut task: JudgeTask) -> bool {
+    let mut guard = FOOD.lock();
+    loop {
+        let n = guard.len();
+        if n == 0 { return false; }
+        let idx = rand::random_range(..n);
+        let sender = guard.swap_remove(idx);
+        match sender.send(task) {
+            Ok(()) => return true,
+            Err(t) => task = t,
+        }
+        tracing::info!("can't send to channel #{idx}");
+        // next loop
+    }
+}
+
 fn deposit_main_lean(
     uid: &str,
     module_name: &str,
@@ -135,7 +328,7 @@ fn deposit_main_lean(
         None => content.push_str(checker),
     }
 
-    fs::write(format!("{sroot}/main.lean"), content)
+    create_new(&format!("{sroot}main.lean"))?.write_all(content.as_bytes())
 }
 
 fn deposit_module_inner(
@@ -207,8 +400,19 @@ fn deposit_one(
 
 fn deposit_inner(task: Task, checker: String) -> io::Result<(SubmissionStatus, SubmissionMessageAction)> {
     let sroot = submission_path(task.sid)?;
-
-    deposit_one(&task.uid, &task.module_name, &task.hash, &sroot, task.is_module)?;
+    if task.source {
+        let olean = match read_compiled(task.sid, &task.uid, &task.module_name) {
+            Ok(olean) if {
+                let mut sha256 = Sha256::new();
+                sha256.update(&olean);
+                sha256.finish() == task.hash
+            } => olean,
+            _ => return Ok((CompilationError, Replace(Cow::Owned(format!("{}: compiled olean changed", task.module_name))))),
+        };
+        deposit_compiled(&task.uid, &task.module_name, &olean, &sroot)?;
+    } else {
+        deposit_one(&task.uid, &task.module_name, &task.hash, &sroot, task.is_module)?;
+    }
 
     let mut queue = VecDeque::<CompactString>::from(task.imports);
     let mut visited = HashSet::<CompactString>::new();
@@ -223,31 +427,42 @@ fn deposit_inner(task: Task, checker: String) -> io::Result<(SubmissionStatus, S
         }
         let Entry::Vacant(e) = visited.entry(module) else { continue; };
         let module = unsafe { e.get().get_unchecked(task.uid.len() + 1..) };
-        let olean_path = olean::𝑔𝑒𝑡_𝑜𝑙𝑒𝑎𝑛_𝑝𝑎𝑡ℎ(&task.uid, module);
-        let display_path = unsafe { olean_path.get_unchecked(const { env!("OLEAN_ROOT").len() }..) };
-        let olean = match fs::read(&*olean_path) {
+        let (olean, display_path) = if task.source {
+            (read_compiled(task.sid, &task.uid, module), module_path("", &task.uid, module, ".olean"))
+        } else {
+            let olean_path = olean::𝑔𝑒𝑡_𝑜𝑙𝑒𝑎𝑛_𝑝𝑎𝑡ℎ(&task.uid, module);
+            let display_path = unsafe { olean_path.get_unchecked(const { env!("OLEAN_ROOT").len() }..) }.to_owned();
+            (fs::read(&*olean_path), display_path)
+        };
+        let olean = match olean {
             Ok(r) => r,
-            Err(e) => return Ok((InvalidImport, Replace(Cow::Owned(e.to_string())))),
+            Err(e) => return Ok((InvalidImport, Replace(Cow::Owned(format!("{display_path}: {e}"))))),
         };
         let Some(meta) = olean::parse_meta(&olean) else { return Ok((InvalidImport, Replace(Cow::Owned(format!("{display_path}: not a valid olean file"))))) };
         let Some(imports) = olean::parse_imports(meta) else { return Ok((InvalidImport, Replace(Cow::Owned(format!("{display_path}: cannot parse imports"))))) };
 
-        let mut sha256 = Sha256::new();
-        sha256.update(&olean);
-        let hash = sha256.finish();
+        if task.source {
+            deposit_compiled(&task.uid, module, &olean, &sroot)?;
+        } else {
+            let mut sha256 = Sha256::new();
+            sha256.update(&olean);
+            let hash = sha256.finish();
 
-        deposit_one(&task.uid, module, &hash, &sroot, meta.is_module())?;
+            deposit_one(&task.uid, module, &hash, &sroot, meta.is_module())?;
+        }
 
         e.insert();
         imports.into_iter().filter(|import| !visited.contains(import)).collect_into(&mut queue);
     }
 
     deposit_main_lean(&task.uid, &task.module_name, &task.const_name, &checker, &sroot)?;
+    if task.source && let Err(e) = remove_sid_dir(&scratch_dir(task.sid)) {
+        tracing::warn!("cannot remove the scratch tree of submission #{}: {e}", task.sid);
+    }
 
     Ok((Deposited, NoAction))
 }
 
-#[allow(clippy::significant_drop_tightening)]
 async fn deposit(task @ Task { sid, version, .. }: Task) -> Result<(), BoxedStdError> {
     let Jb { axioms, checker } = match serde_json::from_slice(&task.checker) {
         Ok(r) => r,
@@ -261,26 +476,18 @@ async fn deposit(task @ Task { sid, version, .. }: Task) -> Result<(), BoxedStdE
 
     let mut conn = get_connection().await?;
     let final_status = if status == Deposited {
-        let mut guard = FOOD.lock();
         #[allow(clippy::transmute_undefined_repr)]
         let axioms = unsafe { core::mem::transmute::<SmallVec<[LeanAxiom; 4]>, SmallVec<[CompactString; 4]>>(axioms) };
         let mut version4 = CompactString::with_capacity(version.len() + 1);
         version4.push('4');
         version4.push_str(version);
-        loop {
-            let n = guard.len();
-            if n == 0 { break Deposited; }
-            let idx = rand::random_range(..n);
-            let sender = guard.swap_remove(idx);
-            let task = JudgeTask {
-                sid,
-                version: version4.clone(),
-                axioms: axioms.clone(),
-            };
-            if sender.send(task).is_ok() { break JudgerReceived; }
-            tracing::info!("can't send to channel #{idx}");
-            // next loop
-        }
+        let task = JudgeTask {
+            sid,
+            version: version4,
+            axioms,
+            sources: Vec::new(),
+        };
+        if dispatch(task) { JudgerReceived } else { Deposited }
     } else {
         status
     };